@@ -10,6 +10,7 @@ use evm_loader::{
     },
     account_storage::AccountStorage,
     config::STORAGE_ENTRIES_IN_CONTRACT_ACCOUNT,
+    evm::{JumpDestinations, Spec},
     executor::{Action, OwnedAccountInfo},
     gasometer::LAMPORTS_PER_SIGNATURE,
     types::Address,
@@ -20,13 +21,17 @@ use solana_sdk::entrypoint::MAX_PERMITTED_DATA_INCREASE;
 use solana_sdk::{
     account::Account,
     account_info::AccountInfo,
+    instruction::AccountMeta,
     pubkey,
     pubkey::Pubkey,
     rent::Rent,
     sysvar::{slot_hashes, Sysvar},
 };
 
-use crate::{Config, Context};
+use crate::{
+    types::{AccountOverride, AccountOverrides, BlockOverrides, StorageKey},
+    Config, Context,
+};
 
 const FAKE_OPERATOR: Pubkey = pubkey!("neonoperator1111111111111111111111111111111");
 
@@ -129,12 +134,22 @@ pub struct SolanaAccount {
 pub struct EmulatorAccountStorage<'a> {
     pub accounts: RefCell<HashMap<Address, NeonAccount>>,
     pub solana_accounts: RefCell<HashMap<Pubkey, SolanaAccount>>,
+    /// Storage slots read during the emulation, values before the transaction
+    pub storage_slots: RefCell<HashMap<(Address, U256), [u8; 32]>>,
+    jump_destinations: RefCell<HashMap<[u8; 32], JumpDestinations>>,
     config: &'a Config,
     context: &'a Context,
     block_number: u64,
     block_timestamp: i64,
     neon_token_mint: Pubkey,
     chain_id: u64,
+    spec: Spec,
+    /// Account state replacing the state read from Solana
+    overrides: AccountOverrides,
+    /// Block context replacing the current slot
+    block_overrides: BlockOverrides,
+    /// Existence of the accounts changed by the committed transactions
+    committed: HashMap<Address, bool>,
 }
 
 impl<'a> EmulatorAccountStorage<'a> {
@@ -143,6 +158,9 @@ impl<'a> EmulatorAccountStorage<'a> {
         context: &'a Context,
         token_mint: Pubkey,
         chain_id: u64,
+        spec: Spec,
+        overrides: AccountOverrides,
+        block_overrides: BlockOverrides,
     ) -> EmulatorAccountStorage<'a> {
         trace!("backend::new");
 
@@ -152,12 +170,18 @@ impl<'a> EmulatorAccountStorage<'a> {
         Self {
             accounts: RefCell::new(HashMap::new()),
             solana_accounts: RefCell::new(HashMap::new()),
+            storage_slots: RefCell::new(HashMap::new()),
+            jump_destinations: RefCell::new(HashMap::new()),
             config,
             context,
             block_number: slot,
             block_timestamp: timestamp,
             neon_token_mint: token_mint,
             chain_id,
+            spec,
+            overrides,
+            block_overrides,
+            committed: HashMap::new(),
         }
     }
 
@@ -350,6 +374,9 @@ impl<'a> EmulatorAccountStorage<'a> {
 
                     self.add_ethereum_account(address, true);
                 }
+                Action::EvmLog { address, .. } => {
+                    info!("log {address}");
+                }
                 Action::ExternalInstruction {
                     program_id,
                     accounts,
@@ -375,6 +402,48 @@ impl<'a> EmulatorAccountStorage<'a> {
         gas
     }
 
+    /// Iterations needed to resize the accounts after `apply_accounts_operations`
+    #[must_use]
+    pub fn resize_iterations(&self) -> usize {
+        self.accounts
+            .borrow()
+            .values()
+            .map(|account| account.additional_resize_steps)
+            .max()
+            .unwrap_or_default()
+    }
+
+    /// Neon accounts followed by the Solana accounts of the transaction, sorted by the pubkey
+    #[must_use]
+    pub fn account_metas(&self) -> Vec<AccountMeta> {
+        let meta = |pubkey: Pubkey, is_writable: bool| {
+            if is_writable {
+                AccountMeta::new(pubkey, false)
+            } else {
+                AccountMeta::new_readonly(pubkey, false)
+            }
+        };
+
+        let mut neon_accounts: Vec<AccountMeta> = self
+            .accounts
+            .borrow()
+            .values()
+            .map(|account| meta(account.account, account.writable))
+            .collect();
+        neon_accounts.sort_by_key(|meta| meta.pubkey);
+
+        let mut solana_accounts: Vec<AccountMeta> = self
+            .solana_accounts
+            .borrow()
+            .values()
+            .map(|account| meta(account.pubkey, account.is_writable))
+            .collect();
+        solana_accounts.sort_by_key(|meta| meta.pubkey);
+
+        neon_accounts.append(&mut solana_accounts);
+        neon_accounts
+    }
+
     #[must_use]
     pub fn apply_accounts_operations(&self, operations: AccountsOperations) -> u64 {
         let mut gas = 0_u64;
@@ -405,6 +474,125 @@ impl<'a> EmulatorAccountStorage<'a> {
         gas.saturating_add(iterations_cost)
     }
 
+    /// Layers the state overrides over the current ones, `state` replaces the patched slots
+    pub fn add_overrides(&mut self, overrides: AccountOverrides) {
+        for (address, account) in overrides {
+            let current = self.overrides.entry(address).or_default();
+            if account.nonce.is_some() {
+                current.nonce = account.nonce;
+            }
+            if account.balance.is_some() {
+                current.balance = account.balance;
+            }
+            if account.code.is_some() {
+                current.code = account.code;
+            }
+            if account.state.is_some() {
+                current.state = account.state;
+                current.state_diff = None;
+            }
+            for (index, value) in account.state_diff.into_iter().flatten() {
+                let slots = match &mut current.state {
+                    Some(state) => state,
+                    None => current.state_diff.get_or_insert_with(HashMap::new),
+                };
+                slots.insert(index, value);
+            }
+        }
+    }
+
+    pub fn set_block_overrides(&mut self, block_overrides: BlockOverrides) {
+        self.block_overrides = block_overrides;
+    }
+
+    /// Folds the actions of the executed transaction into the overrides,
+    /// the following transactions run on top of the changed state.
+    /// Destroyed accounts stop existing, a zero value transfer doesn't create the target
+    pub fn commit_actions(&mut self, actions: &[Action]) {
+        info!("commit_actions");
+
+        for action in actions {
+            match action {
+                Action::NeonTransfer {
+                    source,
+                    target,
+                    value,
+                } => {
+                    let exists = self.exists(target) || *value != U256::ZERO;
+                    self.committed.insert(*target, exists);
+
+                    let balance = self.balance(source).saturating_sub(*value);
+                    self.overrides.entry(*source).or_default().balance = Some(balance);
+
+                    let balance = self.balance(target).saturating_add(*value);
+                    self.overrides.entry(*target).or_default().balance = Some(balance);
+                }
+                Action::NeonWithdraw { source, value } => {
+                    let balance = self.balance(source).saturating_sub(*value);
+                    self.overrides.entry(*source).or_default().balance = Some(balance);
+                }
+                Action::EvmSetStorage {
+                    address,
+                    index,
+                    value,
+                } => {
+                    self.committed.insert(*address, true);
+
+                    let account = self.overrides.entry(*address).or_default();
+                    let slots = match &mut account.state {
+                        Some(state) => state,
+                        None => account.state_diff.get_or_insert_with(HashMap::new),
+                    };
+                    slots.insert(StorageKey(*index), StorageKey(U256::from_be_bytes(*value)));
+                }
+                Action::EvmIncrementNonce { address } => {
+                    self.committed.insert(*address, true);
+
+                    let nonce = self.nonce(address).saturating_add(1);
+                    self.overrides.entry(*address).or_default().nonce = Some(nonce);
+                }
+                Action::EvmSetCode { address, code } => {
+                    self.committed.insert(*address, true);
+
+                    self.overrides.entry(*address).or_default().code = Some(code.to_vec());
+                }
+                Action::EvmSelfDestruct { address } => {
+                    self.committed.insert(*address, false);
+
+                    let account = AccountOverride {
+                        nonce: Some(0),
+                        balance: Some(U256::ZERO),
+                        code: Some(Vec::new()),
+                        state: Some(HashMap::new()),
+                        state_diff: None,
+                    };
+                    self.overrides.insert(*address, account);
+                }
+                Action::EvmLog { .. } | Action::ExternalInstruction { .. } => {}
+            }
+        }
+    }
+
+    /// Override of the account, the account is still added to the transaction accounts
+    fn account_override(&self, address: &Address) -> Option<&AccountOverride> {
+        self.add_ethereum_account(address, false);
+        self.overrides.get(address)
+    }
+
+    /// Overridden storage value, the slots missing in the replaced storage are zero
+    fn storage_override(&self, address: &Address, index: &U256) -> Option<[u8; 32]> {
+        let account = self.account_override(address)?;
+        let key = StorageKey(*index);
+
+        if let Some(state) = &account.state {
+            let value = state.get(&key).map_or(U256::ZERO, |value| value.0);
+            return Some(value.to_be_bytes());
+        }
+
+        let state_diff = account.state_diff.as_ref()?;
+        state_diff.get(&key).map(|value| value.0.to_be_bytes())
+    }
+
     fn ethereum_account_map_or<F, R>(&self, address: &Address, default: R, f: F) -> R
     where
         F: FnOnce(&EthereumAccount) -> R,
@@ -462,17 +650,32 @@ impl<'a> AccountStorage for EmulatorAccountStorage<'a> {
 
     fn block_number(&self) -> U256 {
         info!("block_number");
-        self.block_number.into()
+        self.block_overrides
+            .number
+            .unwrap_or(self.block_number)
+            .into()
     }
 
     fn block_timestamp(&self) -> U256 {
         info!("block_timestamp");
-        self.block_timestamp.try_into().unwrap()
+        match self.block_overrides.time {
+            Some(time) => time.into(),
+            None => self.block_timestamp.try_into().unwrap(),
+        }
     }
 
     fn block_hash(&self, slot: u64) -> [u8; 32] {
         info!("block_hash {slot}");
 
+        let overridden = self
+            .block_overrides
+            .block_hash
+            .as_ref()
+            .and_then(|hashes| hashes.get(&slot));
+        if let Some(hash) = overridden {
+            return hash.0;
+        }
+
         self.add_solana_account(slot_hashes::ID, false);
 
         if let Ok(Some(slot_hashes_account)) = self.get_account(&slot_hashes::ID) {
@@ -483,10 +686,21 @@ impl<'a> AccountStorage for EmulatorAccountStorage<'a> {
         }
     }
 
+    fn block_coinbase(&self) -> Address {
+        info!("block_coinbase");
+        self.block_overrides.coinbase.unwrap_or_default()
+    }
+
     fn exists(&self, address: &Address) -> bool {
         info!("exists {address}");
 
-        self.add_ethereum_account(address, false);
+        if let Some(exists) = self.committed.get(address) {
+            return *exists;
+        }
+
+        if self.account_override(address).is_some() {
+            return true;
+        }
 
         let accounts = self.accounts.borrow();
         accounts.contains_key(address)
@@ -495,17 +709,30 @@ impl<'a> AccountStorage for EmulatorAccountStorage<'a> {
     fn nonce(&self, address: &Address) -> u64 {
         info!("nonce {address}");
 
+        if let Some(nonce) = self.account_override(address).and_then(|a| a.nonce) {
+            return nonce;
+        }
+
         self.ethereum_account_map_or(address, 0_u64, |a| a.trx_count)
     }
 
     fn balance(&self, address: &Address) -> U256 {
         info!("balance {address}");
 
+        if let Some(balance) = self.account_override(address).and_then(|a| a.balance) {
+            return balance;
+        }
+
         self.ethereum_account_map_or(address, U256::ZERO, |a| a.balance)
     }
 
     fn code_size(&self, address: &Address) -> usize {
         info!("code_size {address}");
+
+        if let Some(code) = self.account_override(address).and_then(|a| a.code.as_ref()) {
+            return code.len();
+        }
+
         self.ethereum_account_map_or(address, 0, |a| a.code_size as usize)
     }
 
@@ -514,6 +741,13 @@ impl<'a> AccountStorage for EmulatorAccountStorage<'a> {
 
         info!("code_hash {address}");
 
+        if let Some(code) = self.account_override(address).and_then(|a| a.code.as_ref()) {
+            if code.is_empty() {
+                return <[u8; 32]>::default();
+            }
+            return hash(code).to_bytes();
+        }
+
         self.ethereum_contract_map_or(address, <[u8; 32]>::default(), |c| {
             hash(&c.code()).to_bytes()
         })
@@ -524,9 +758,25 @@ impl<'a> AccountStorage for EmulatorAccountStorage<'a> {
 
         info!("code {address}");
 
+        if let Some(code) = self.account_override(address).and_then(|a| a.code.as_ref()) {
+            return Buffer::new(code);
+        }
+
         self.ethereum_contract_map_or(address, Buffer::empty(), |c| Buffer::new(&c.code()))
     }
 
+    fn jump_destinations(&self, code: &[u8]) -> JumpDestinations {
+        use solana_sdk::keccak::hash;
+
+        let code_hash = hash(code).to_bytes();
+
+        self.jump_destinations
+            .borrow_mut()
+            .entry(code_hash)
+            .or_insert_with(|| JumpDestinations::new(code))
+            .clone()
+    }
+
     fn generation(&self, address: &Address) -> u32 {
         let value = self.ethereum_account_map_or(address, 0_u32, |c| c.generation);
 
@@ -535,7 +785,9 @@ impl<'a> AccountStorage for EmulatorAccountStorage<'a> {
     }
 
     fn storage(&self, address: &Address, index: &U256) -> [u8; 32] {
-        let value = if *index < U256::from(STORAGE_ENTRIES_IN_CONTRACT_ACCOUNT) {
+        let value = if let Some(value) = self.storage_override(address, index) {
+            value
+        } else if *index < U256::from(STORAGE_ENTRIES_IN_CONTRACT_ACCOUNT) {
             let index: usize = index.as_usize() * 32;
             self.ethereum_contract_map_or(address, <[u8; 32]>::default(), |c| {
                 c.storage()[index..index + 32].try_into().unwrap()
@@ -580,6 +832,10 @@ impl<'a> AccountStorage for EmulatorAccountStorage<'a> {
 
         info!("storage {address} -> {index} = {}", hex::encode(value));
 
+        self.storage_slots
+            .borrow_mut()
+            .insert((*address, *index), value);
+
         value
     }
 
@@ -590,7 +846,13 @@ impl<'a> AccountStorage for EmulatorAccountStorage<'a> {
     fn chain_id(&self) -> u64 {
         info!("chain_id");
 
-        self.chain_id
+        self.block_overrides.chain_id.unwrap_or(self.chain_id)
+    }
+
+    fn spec(&self) -> Spec {
+        info!("spec");
+
+        self.spec
     }
 
     fn clone_solana_account(&self, address: &Pubkey) -> OwnedAccountInfo {
@@ -9,9 +9,11 @@ mod config;
 mod context;
 mod errors;
 mod event_listener;
+mod gas_estimation;
 mod program_options;
 mod rpc;
 mod syscall_stubs;
+mod transaction_plan;
 mod types;
 
 use std::env;
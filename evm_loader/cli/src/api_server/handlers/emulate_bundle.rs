@@ -0,0 +1,59 @@
+use tide::{Request, Result};
+
+use crate::{
+    api_server::{request_models::EmulateBundleRequest, state::State},
+    context,
+};
+
+use super::{parse_tx, parse_tx_params, process_result};
+use crate::commands::emulate_bundle as EmulateBundleCommand;
+
+#[allow(clippy::unused_async)]
+pub async fn emulate_bundle(mut req: Request<State>) -> Result<serde_json::Value> {
+    let bundle_request: EmulateBundleRequest = req.body_json().await.map_err(|e| {
+        tide::Error::from_str(
+            400,
+            format!("Error on parsing bundle request: {:?}", e.to_string()),
+        )
+    })?;
+
+    let first = bundle_request
+        .transactions
+        .first()
+        .ok_or_else(|| tide::Error::from_str(400, "Bundle has no transactions"))?;
+
+    let state = req.state();
+
+    let transactions = bundle_request.transactions.iter().map(parse_tx).collect();
+
+    let signer = context::build_singer(&state.config).map_err(|e| {
+        tide::Error::from_str(
+            400,
+            format!("Error on creating singer: {:?}", e.to_string()),
+        )
+    })?;
+
+    let rpc_client = context::build_rpc_client(&state.config, first.slot).map_err(|e| {
+        tide::Error::from_str(
+            400,
+            format!("Error on creating rpc client: {:?}", e.to_string()),
+        )
+    })?;
+
+    let context = context::create(rpc_client, signer);
+
+    let (token, chain, spec, steps, accounts, solana_accounts) =
+        parse_tx_params(&state.config, &context, first)?;
+
+    process_result(&EmulateBundleCommand::execute(
+        &state.config,
+        &context,
+        transactions,
+        token,
+        chain,
+        spec,
+        steps,
+        &accounts,
+        &solana_accounts,
+    ))
+}
@@ -5,7 +5,7 @@ use crate::{
     context,
 };
 
-use super::{parse_tx, parse_tx_params, process_result};
+use super::{iteration_steps, parse_tx, parse_tx_params, plan_options, process_result};
 use crate::commands::emulate as EmulateCommand;
 
 #[allow(clippy::unused_async)]
@@ -44,8 +44,10 @@ pub async fn emulate_hash(mut req: Request<State>) -> Result<serde_json::Value>
 
     let context = context::create(rpc_client, signer);
 
-    let (token, chain, steps, accounts, solana_accounts) =
-        parse_tx_params(&state.config, &context, &tx_params_request);
+    let (token, chain, spec, steps, accounts, solana_accounts) =
+        parse_tx_params(&state.config, &context, &tx_params_request)?;
+
+    let plan = plan_options(&state.config, &context, &tx_params_request)?;
 
     process_result(&EmulateCommand::execute(
         &state.config,
@@ -53,8 +55,11 @@ pub async fn emulate_hash(mut req: Request<State>) -> Result<serde_json::Value>
         tx,
         token,
         chain,
+        spec,
         steps,
         &accounts,
         &solana_accounts,
+        iteration_steps(&tx_params_request),
+        plan.as_ref(),
     ))
 }
@@ -1,15 +1,21 @@
 use ethnum::U256;
-use evm_loader::types::Address;
+use evm_loader::{config::EVM_STEPS_MIN, evm::Spec, types::Address};
 use solana_sdk::pubkey::Pubkey;
 
 use crate::api_server::request_models::TxParamsRequest;
 use crate::commands::get_neon_elf::CachedElfParams;
+use crate::commands::trace::TraceOptions;
+use crate::errors::NeonCliError;
+use crate::event_listener::stream_tracer::StreamSummary;
+use crate::transaction_plan::PlanOptions;
 use crate::types::TxParams;
 use crate::{Config, Context, NeonCliResult};
 
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
 pub mod emulate;
+pub mod emulate_bundle;
 pub mod emulate_hash;
 pub mod get_ether_account_data;
 pub mod get_storage_at;
@@ -41,6 +47,19 @@ pub(crate) fn parse_tx(model: &TxParamsRequest) -> TxParams {
         .clone()
         .map(|v| u256_of(v.as_str()))
         .unwrap_or_default();
+    let max_fee_per_gas = model
+        .max_fee_per_gas
+        .clone()
+        .map(|v| u256_of(v.as_str()))
+        .unwrap_or_default();
+    let max_priority_fee_per_gas = model
+        .max_priority_fee_per_gas
+        .clone()
+        .map(|v| u256_of(v.as_str()))
+        .unwrap_or_default();
+    let access_list = model.access_list.clone();
+    let state_overrides = model.state_overrides.clone();
+    let block_overrides = model.block_overrides.clone();
 
     TxParams {
         from,
@@ -48,20 +67,73 @@ pub(crate) fn parse_tx(model: &TxParamsRequest) -> TxParams {
         data,
         value,
         gas_limit,
+        max_fee_per_gas,
+        max_priority_fee_per_gas,
+        access_list,
+        state_overrides,
+        block_overrides,
     }
 }
 
+/// Steps per iteration if the accurate gas estimation is requested
+pub(crate) fn iteration_steps(params: &TxParamsRequest) -> Option<u64> {
+    params
+        .estimate_gas
+        .unwrap_or(false)
+        .then(|| params.iteration_steps.unwrap_or(EVM_STEPS_MIN))
+}
+
+/// Operator settings of the Solana transactions plan if the plan is requested
+pub(crate) fn plan_options(
+    config: &Config,
+    context: &Context,
+    params: &TxParamsRequest,
+) -> tide::Result<Option<PlanOptions>> {
+    let raw_transaction = match params.plan.as_deref() {
+        Some(raw_transaction) => raw_transaction,
+        None => return Ok(None),
+    };
+    let bad_request = |message: String| tide::Error::from_str(400, message);
+
+    let raw_transaction = hex::decode(raw_transaction.trim_start_matches("0x"))
+        .map_err(|e| bad_request(format!("Error on parsing plan: {e}")))?;
+    let operator_ether = params
+        .operator_ether
+        .ok_or_else(|| bad_request("operator_ether is required by the plan".to_string()))?;
+    let holder = params
+        .holder
+        .as_deref()
+        .ok_or_else(|| bad_request("holder is required by the plan".to_string()))?;
+    let holder = Pubkey::from_str(holder)
+        .map_err(|e| bad_request(format!("Error on parsing holder: {e}")))?;
+
+    Ok(Some(PlanOptions {
+        raw_transaction,
+        operator: context.signer.pubkey(),
+        operator_ether_account: operator_ether.find_solana_address(&config.evm_loader).0,
+        holder,
+        create_holder: params.create_holder.unwrap_or(false),
+        treasury_index: params.treasury_index.unwrap_or_default(),
+    }))
+}
+
 pub(crate) fn parse_tx_params(
     config: &Config,
     context: &Context,
     params: &TxParamsRequest,
-) -> (Pubkey, u64, u64, Vec<Address>, Vec<Pubkey>) {
-    // Read ELF params only if token_mint or chain_id is not set.
+) -> tide::Result<(Pubkey, u64, Spec, u64, Vec<Address>, Vec<Pubkey>)> {
+    // Read ELF params only if token_mint, chain_id or evm_spec is not set.
     let mut token: Option<Pubkey> =
         Pubkey::from_str(params.token_mint.clone().unwrap_or_default().as_str())
             .map_or_else(|_| None, Some);
     let mut chain = params.chain_id;
-    if token.is_none() || chain.is_none() {
+    let mut spec = params
+        .evm_spec
+        .as_deref()
+        .map(Spec::from_str)
+        .transpose()
+        .map_err(|e| tide::Error::from_str(400, format!("Error on parsing evm_spec: {e}")))?;
+    if token.is_none() || chain.is_none() || spec.is_none() {
         let cached_elf_params = CachedElfParams::new(config, context);
         token = token.or_else(|| {
             Some(
@@ -83,9 +155,16 @@ pub(crate) fn parse_tx_params(
                 .expect("NEON_CHAIN_ID u64 ctor error"),
             )
         });
+        // Programs built before the hardfork selection run the latest spec
+        spec = spec.or_else(|| {
+            cached_elf_params
+                .get("NEON_EVM_SPEC")
+                .map(|name| Spec::from_str(name).expect("NEON_EVM_SPEC parse error"))
+        });
     }
     let token = token.expect("token_mint get error");
     let chain = chain.expect("chain_id get error");
+    let spec = spec.unwrap_or_default();
     let max_steps = params
         .max_steps_to_execute
         .expect("max_steps_to_execute parse error");
@@ -102,7 +181,7 @@ pub(crate) fn parse_tx_params(
         })
         .unwrap_or_default();
 
-    (token, chain, max_steps, accounts, solana_accounts)
+    Ok((token, chain, spec, max_steps, accounts, solana_accounts))
 }
 
 fn process_result(result: &NeonCliResult) -> tide::Result<serde_json::Value> {
@@ -123,3 +202,84 @@ fn process_result(result: &NeonCliResult) -> tide::Result<serde_json::Value> {
         }
     }
 }
+
+/// Result of the trace handlers
+pub(crate) enum TraceOutput {
+    Json(NeonCliResult),
+    /// Streamed records written to the temporary file
+    Stream(StreamFile, Result<StreamSummary, NeonCliError>),
+}
+
+/// Maximal number of the streamed records, `max_records` of the request can only lower it
+const STREAM_MAX_RECORDS: usize = 100_000;
+
+/// Checks the streamed trace request and caps the number of the records
+pub(crate) fn stream_options(
+    tracer: Option<&str>,
+    mut options: TraceOptions,
+) -> tide::Result<TraceOptions> {
+    if tracer.is_some() {
+        return Err(tide::Error::from_str(
+            400,
+            "tracer is not supported by the streamed trace",
+        ));
+    }
+
+    let max_records = options
+        .max_records
+        .map_or(STREAM_MAX_RECORDS, |max_records| {
+            max_records.min(STREAM_MAX_RECORDS)
+        });
+    options.max_records = Some(max_records);
+
+    Ok(options)
+}
+
+/// Temporary file for the streamed trace records, removed on drop
+pub(crate) struct StreamFile(PathBuf);
+
+impl StreamFile {
+    pub(crate) fn new() -> Self {
+        let name = format!("neon-trace-{:016x}.ndjson", rand::random::<u64>());
+        Self(std::env::temp_dir().join(name))
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for StreamFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+async fn process_trace(output: TraceOutput) -> tide::Result<tide::Response> {
+    match output {
+        TraceOutput::Json(result) => process_result(&result).map(Into::into),
+        TraceOutput::Stream(file, result) => process_stream(file, result).await,
+    }
+}
+
+/// Sends the streamed trace as a chunked newline delimited JSON response.
+/// Records are written to the file during the emulation, so the trace is never held in memory.
+async fn process_stream(
+    file: StreamFile,
+    result: Result<StreamSummary, NeonCliError>,
+) -> tide::Result<tide::Response> {
+    if let Err(e) = result {
+        return process_result(&Err(e)).map(Into::into);
+    }
+
+    let opened = tide::Body::from_file(file.path()).await;
+    // The opened file stays readable after the removal
+    drop(file);
+    let file = opened;
+
+    // Unknown length makes the response chunked
+    let mut body = tide::Body::from_reader(file?, None);
+    body.set_mime("application/x-ndjson");
+
+    Ok(tide::Response::builder(200).body(body).build())
+}
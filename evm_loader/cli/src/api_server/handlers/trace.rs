@@ -1,15 +1,17 @@
-use tide::{Request, Result};
+use tide::{Request, Response, Result};
 
 use crate::{
     api_server::{request_models::TxParamsRequest, state::State},
     context,
+    errors::NeonCliError,
 };
 
-use super::{parse_tx, parse_tx_params, process_result};
-use crate::commands::emulate as EmulateCommand;
+use super::{parse_tx, parse_tx_params, process_trace, stream_options, StreamFile, TraceOutput};
+use crate::commands::trace::{self as TraceCommand, TracerType};
+use std::str::FromStr;
 
 #[allow(clippy::unused_async)]
-pub async fn trace(mut req: Request<State>) -> Result<serde_json::Value> {
+pub async fn trace(mut req: Request<State>) -> Result<Response> {
     let tx_params_request: TxParamsRequest = req.body_json().await.map_err(|e| {
         tide::Error::from_str(
             400,
@@ -20,38 +22,79 @@ pub async fn trace(mut req: Request<State>) -> Result<serde_json::Value> {
         )
     })?;
 
-    let state = req.state();
+    // Emulation state is dropped before the streamed response is sent
+    let output = {
+        let state = req.state();
 
-    let tx: crate::types::TxParams = parse_tx(&tx_params_request);
+        let tx: crate::types::TxParams = parse_tx(&tx_params_request);
 
-    let signer = context::build_singer(&state.config).map_err(|e| {
-        tide::Error::from_str(
-            400,
-            format!("Error on creating singer: {:?}", e.to_string()),
-        )
-    })?;
+        let tracer = tx_params_request
+            .tracer
+            .as_deref()
+            .map(TracerType::from_str)
+            .transpose()
+            .map_err(|e| tide::Error::from_str(400, format!("Error on parsing tracer: {e:?}")))?
+            .unwrap_or_default();
 
-    let rpc_client =
-        context::build_rpc_client(&state.config, tx_params_request.slot).map_err(|e| {
+        let signer = context::build_singer(&state.config).map_err(|e| {
             tide::Error::from_str(
                 400,
-                format!("Error on creating rpc client: {:?}", e.to_string()),
+                format!("Error on creating singer: {:?}", e.to_string()),
             )
         })?;
 
-    let context = context::create(rpc_client, signer);
-
-    let (token, chain, steps, accounts, solana_accounts) =
-        parse_tx_params(&state.config, &context, &tx_params_request);
-
-    process_result(&EmulateCommand::execute(
-        &state.config,
-        &context,
-        tx,
-        token,
-        chain,
-        steps,
-        &accounts,
-        &solana_accounts,
-    ))
+        let rpc_client =
+            context::build_rpc_client(&state.config, tx_params_request.slot).map_err(|e| {
+                tide::Error::from_str(
+                    400,
+                    format!("Error on creating rpc client: {:?}", e.to_string()),
+                )
+            })?;
+
+        let context = context::create(rpc_client, signer);
+
+        let (token, chain, spec, steps, accounts, solana_accounts) =
+            parse_tx_params(&state.config, &context, &tx_params_request)?;
+
+        let options = tx_params_request.tracer_config.unwrap_or_default();
+
+        if tx_params_request.stream.unwrap_or_default() {
+            let options = stream_options(tx_params_request.tracer.as_deref(), options)?;
+            let stream_file = StreamFile::new();
+            let result = std::fs::File::create(stream_file.path())
+                .map_err(NeonCliError::from)
+                .and_then(|file| {
+                    TraceCommand::stream(
+                        &state.config,
+                        &context,
+                        tx,
+                        token,
+                        chain,
+                        spec,
+                        steps,
+                        &accounts,
+                        &solana_accounts,
+                        options,
+                        std::io::BufWriter::new(file),
+                    )
+                });
+            TraceOutput::Stream(stream_file, result)
+        } else {
+            TraceOutput::Json(TraceCommand::execute(
+                &state.config,
+                &context,
+                tx,
+                token,
+                chain,
+                spec,
+                steps,
+                &accounts,
+                &solana_accounts,
+                tracer,
+                options,
+            ))
+        }
+    };
+
+    process_trace(output).await
 }
@@ -1,6 +1,9 @@
 use evm_loader::types::Address;
 use serde::{Deserialize, Serialize};
 
+use crate::commands::trace::TraceOptions;
+use crate::types::{AccessListItem, AccountOverrides, BlockOverrides};
+
 #[derive(Deserialize, Serialize, Debug, Default)]
 pub(crate) struct GetEtherRequest {
     pub(crate) ether: Option<String>,
@@ -21,11 +24,38 @@ pub(crate) struct TxParamsRequest {
     pub(crate) data: Option<Vec<u8>>,
     pub(crate) value: Option<String>,
     pub(crate) gas_limit: Option<String>,
+    pub(crate) max_fee_per_gas: Option<String>,
+    pub(crate) max_priority_fee_per_gas: Option<String>,
+    pub(crate) access_list: Option<Vec<AccessListItem>>,
+    pub(crate) state_overrides: Option<AccountOverrides>,
+    pub(crate) block_overrides: Option<BlockOverrides>,
     pub(crate) token_mint: Option<String>,
     pub(crate) chain_id: Option<u64>,
+    pub(crate) evm_spec: Option<String>,
+    pub(crate) tracer: Option<String>,
+    pub(crate) tracer_config: Option<TraceOptions>,
+    /// Trace handlers respond with the newline delimited JSON records
+    pub(crate) stream: Option<bool>,
     pub(crate) max_steps_to_execute: Option<u64>,
+    /// Emulation responds with the gas of the path the operator picks
+    pub(crate) estimate_gas: Option<bool>,
+    /// Steps in a single Solana transaction for the gas estimation
+    pub(crate) iteration_steps: Option<u64>,
+    /// Hex signed transaction, emulation responds with the operator's Solana transactions
+    pub(crate) plan: Option<String>,
+    pub(crate) holder: Option<String>,
+    pub(crate) create_holder: Option<bool>,
+    pub(crate) operator_ether: Option<Address>,
+    pub(crate) treasury_index: Option<u32>,
     pub(crate) cached_accounts: Option<Vec<Address>>,
     pub(crate) solana_accounts: Option<Vec<String>>,
     pub(crate) slot: Option<u64>,
     pub(crate) hash: Option<String>,
 }
+
+/// Transactions emulated in order on the shared state, the emulation settings
+/// (token mint, chain id, spec, steps, cached accounts and slot) are taken from the first one
+#[derive(Deserialize, Serialize, Debug, Default)]
+pub(crate) struct EmulateBundleRequest {
+    pub(crate) transactions: Vec<TxParamsRequest>,
+}
@@ -1,7 +1,8 @@
 // use evm_loader::types::Address;
 use crate::api_server::handlers::{
-    emulate::emulate, emulate_hash::emulate_hash, get_ether_account_data::get_ether_account_data,
-    get_storage_at::get_storage_at, trace::trace, trace_hash::trace_hash,
+    emulate::emulate, emulate_bundle::emulate_bundle, emulate_hash::emulate_hash,
+    get_ether_account_data::get_ether_account_data, get_storage_at::get_storage_at, trace::trace,
+    trace_hash::trace_hash,
 };
 
 use crate::api_server::state::State;
@@ -11,6 +12,7 @@ pub fn register(state: State) -> tide::Server<State> {
 
     api.at("/emulate").post(emulate);
     api.at("/emulate_hash").post(emulate_hash);
+    api.at("/emulate_bundle").post(emulate_bundle);
     api.at("/get-storage-at").get(get_storage_at);
     api.at("/get-ether-account-data")
         .get(get_ether_account_data);
@@ -0,0 +1,41 @@
+use evm_loader::{evm::Spec, types::Address};
+use solana_sdk::pubkey::Pubkey;
+
+use crate::{
+    commands::emulate, context::Context, event_listener::debugger::Debugger, types::TxParams,
+    Config, NeonCliResult,
+};
+
+/// Emulates the transaction in the interactive debugger.
+/// The transaction data comes from `--data`, the commands are read from the stdin
+/// and the debugger output goes to the stderr.
+#[allow(clippy::too_many_arguments)]
+pub fn execute(
+    config: &Config,
+    context: &Context,
+    tx: TxParams,
+    token: Pubkey,
+    chain: u64,
+    spec: Spec,
+    steps: u64,
+    accounts: &[Address],
+    solana_accounts: &[Pubkey],
+) -> NeonCliResult {
+    let mut debugger = Debugger::new(std::io::stdin().lock(), std::io::stderr());
+
+    let emulation = evm_loader::evm::tracing::using(&mut debugger, || {
+        emulate::emulate(
+            config,
+            context,
+            tx,
+            token,
+            chain,
+            spec,
+            steps,
+            accounts,
+            solana_accounts,
+        )
+    })?;
+
+    Ok(emulation.to_json())
+}
@@ -4,8 +4,8 @@ use ethnum::U256;
 use evm_loader::{
     account_storage::AccountStorage,
     config::{EVM_STEPS_MIN, PAYMENT_TO_TREASURE},
-    evm::{ExitStatus, Machine},
-    executor::ExecutorState,
+    evm::{ExitStatus, Machine, Spec},
+    executor::{Action, ExecutorState},
     gasometer::LAMPORTS_PER_SIGNATURE,
     types::{Address, Transaction},
 };
@@ -13,12 +13,30 @@ use evm_loader::{
 use crate::{
     account_storage::{EmulatorAccountStorage, NeonAccount, SolanaAccount},
     errors::NeonCliError,
+    gas_estimation::{self, transaction_rlp_len, EstimationInput, GasEstimation},
     syscall_stubs::Stubs,
+    transaction_plan::{self, PlanOptions},
     Config, NeonCliResult,
 };
-use crate::{context::Context, types::TxParams};
+use crate::{
+    context::Context,
+    types::{Log, TxParams},
+};
 use solana_sdk::pubkey::Pubkey;
 
+/// Executed transaction, `storage` holds the state before the transaction
+/// along with the accounts the transaction writes, `rent` is charged for them
+pub struct Emulation<'a> {
+    pub storage: EmulatorAccountStorage<'a>,
+    pub exit_status: ExitStatus,
+    pub actions: Vec<Action>,
+    pub steps_executed: u64,
+    pub evm_gas_used: u64,
+    pub rent: TransactionRent,
+    /// Length of the signed transaction, used by the gas estimation
+    pub rlp_len: usize,
+}
+
 #[allow(clippy::too_many_arguments)]
 pub fn execute(
     config: &Config,
@@ -26,74 +44,281 @@ pub fn execute(
     tx_params: TxParams,
     token: Pubkey,
     chain: u64,
+    spec: Spec,
     steps: u64,
     accounts: &[Address],
     solana_accounts: &[Pubkey],
+    iteration_steps: Option<u64>,
+    plan: Option<&PlanOptions>,
 ) -> NeonCliResult {
+    let sender = tx_params.from;
+    let target = tx_params.to;
+    let mut emulation = emulate(
+        config,
+        context,
+        tx_params,
+        token,
+        chain,
+        spec,
+        steps,
+        accounts,
+        solana_accounts,
+    )?;
+
+    let nonce = emulation.storage.nonce(&sender);
+    if let Some(plan) = plan {
+        check_plan_transaction(&plan.raw_transaction, sender, nonce)?;
+        emulation.rlp_len = plan.raw_transaction.len();
+    }
+
+    let mut result = emulation.to_json();
+    // Accurate estimation replaces the rough `used_gas`, the plan follows the estimated path
+    let iteration_steps = iteration_steps.or_else(|| plan.map(|_| EVM_STEPS_MIN));
+    if let Some(iteration_steps) = iteration_steps {
+        let estimation = emulation.estimate_gas(iteration_steps);
+        let used_gas = required_gas_limit(estimation.gas, emulation.evm_gas_used);
+        result["used_gas"] = serde_json::json!(used_gas);
+        result["gas_estimation"] = serde_json::json!(estimation);
+
+        if let Some(plan) = plan {
+            let target = target.unwrap_or_else(|| Address::from_create(&sender, nonce));
+            let begin_accounts =
+                [sender, target].map(|address| address.find_solana_address(&config.evm_loader).0);
+            let transaction_plan = transaction_plan::build(
+                &config.evm_loader,
+                plan,
+                &estimation,
+                iteration_steps,
+                &emulation.storage.account_metas(),
+                &begin_accounts,
+            );
+            result["transaction_plan"] = transaction_plan.to_json();
+        }
+    }
+
+    Ok(result)
+}
+
+/// The planned signed transaction must be the emulated one: same sender and nonce
+fn check_plan_transaction(
+    raw_transaction: &[u8],
+    sender: Address,
+    nonce: u64,
+) -> Result<(), NeonCliError> {
+    let trx = Transaction::from_rlp(raw_transaction)?;
+    let caller = trx.recover_caller_address()?;
+
+    if (caller != sender) || (trx.nonce != nonce) {
+        return Err(NeonCliError::PlanTransactionMismatch(format!(
+            "sender {caller}, nonce {}, expected sender {sender}, nonce {nonce}",
+            trx.nonce
+        )));
+    }
+
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn emulate<'a>(
+    config: &'a Config,
+    context: &'a Context,
+    mut tx_params: TxParams,
+    token: Pubkey,
+    chain: u64,
+    spec: Spec,
+    steps: u64,
+    accounts: &[Address],
+    solana_accounts: &[Pubkey],
+) -> Result<Emulation<'a>, NeonCliError> {
     let syscall_stubs = Stubs::new(context)?;
     solana_sdk::program_stubs::set_syscall_stubs(syscall_stubs);
 
-    let storage = EmulatorAccountStorage::new(config, context, token, chain);
+    let overrides = tx_params.state_overrides.take().unwrap_or_default();
+    let block_overrides = tx_params.block_overrides.take().unwrap_or_default();
+    let storage = EmulatorAccountStorage::new(
+        config,
+        context,
+        token,
+        chain,
+        spec,
+        overrides,
+        block_overrides,
+    );
     storage.initialize_cached_accounts(accounts, solana_accounts);
 
+    let nonce = storage.nonce(&tx_params.from);
+    let rlp_len = transaction_rlp_len(&tx_params, nonce, storage.chain_id());
+
+    let (exit_status, actions, steps_executed, evm_gas_used) =
+        execute_transaction(&storage, tx_params, steps)?;
+    let rent = apply_rent(&storage, &actions);
+
+    Ok(Emulation {
+        storage,
+        exit_status,
+        actions,
+        steps_executed,
+        evm_gas_used,
+        rent,
+        rlp_len,
+    })
+}
+
+/// Executes the transaction on top of the storage state,
+/// returns the exit status, actions, steps executed and EVM gas used
+pub fn execute_transaction(
+    storage: &EmulatorAccountStorage,
+    tx_params: TxParams,
+    steps: u64,
+) -> Result<(ExitStatus, Vec<Action>, u64, u64), NeonCliError> {
+    let chain = storage.chain_id();
+
     let trx = Transaction {
+        transaction_type: tx_params.transaction_type(),
         nonce: storage.nonce(&tx_params.from),
         gas_price: U256::ZERO,
-        gas_limit: U256::MAX,
+        max_priority_fee_per_gas: tx_params.max_priority_fee_per_gas.unwrap_or_default(),
+        max_fee_per_gas: tx_params.max_fee_per_gas.unwrap_or_default(),
+        gas_limit: tx_params.gas_limit.unwrap_or(U256::MAX),
         target: tx_params.to,
         value: tx_params.value.unwrap_or_default(),
         call_data: evm_loader::evm::Buffer::new(&tx_params.data.unwrap_or_default()),
+        access_list: tx_params
+            .access_list
+            .iter()
+            .flatten()
+            .map(Into::into)
+            .collect(),
         chain_id: Some(chain.into()),
         ..Transaction::default()
     };
 
-    let (exit_status, actions, steps_executed) = {
-        let mut backend = ExecutorState::new(&storage);
+    let (exit_status, actions, steps_executed, evm_gas_used) = {
+        let mut backend = ExecutorState::new(storage, ExecutorState::precompiles());
         let mut evm = Machine::new(trx, tx_params.from, &mut backend)?;
 
         let (result, steps_executed) = evm.execute(steps, &mut backend)?;
+        let evm_gas_used = evm.used_gas();
         let actions = backend.into_actions();
-        (result, actions, steps_executed)
+        (result, actions, steps_executed, evm_gas_used)
     };
 
     debug!("Execute done, result={exit_status:?}");
-    debug!("{steps_executed} steps executed");
+    debug!("{steps_executed} steps executed, {evm_gas_used} EVM gas used");
 
     if exit_status == ExitStatus::StepLimit {
         return Err(NeonCliError::TooManySteps);
     }
 
-    let accounts_operations = storage.calc_accounts_operations(&actions);
+    Ok((exit_status, actions, steps_executed, evm_gas_used))
+}
+
+/// Rent the program charges for the accounts written by the transaction
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TransactionRent {
+    /// Rent of the storage accounts and the external instruction allocations
+    pub actions: u64,
+    /// Rent of the created and resized Neon accounts
+    pub accounts: u64,
+    /// Iterations needed to resize the accounts by `MAX_PERMITTED_DATA_INCREASE`
+    pub resize_iterations: u64,
+}
+
+/// Adds the accounts written by the actions to the storage and computes their rent,
+/// must be called once per transaction
+#[must_use]
+pub fn apply_rent(storage: &EmulatorAccountStorage, actions: &[Action]) -> TransactionRent {
+    let accounts_operations = storage.calc_accounts_operations(actions);
+    let actions = storage.apply_actions(actions);
+    let accounts_gas = storage.apply_accounts_operations(accounts_operations);
+
+    // `apply_accounts_operations` charges a signature per resize iteration
+    let resize_iterations = storage.resize_iterations() as u64;
+    let accounts = accounts_gas.saturating_sub(resize_iterations * LAMPORTS_PER_SIGNATURE);
+
+    TransactionRent {
+        actions,
+        accounts,
+        resize_iterations,
+    }
+}
+
+/// `used_gas` reported to the proxy, the proxy sets it as the transaction `gas_limit`.
+/// The program checks both the Neon gas charged by the `Gasometer` and the EVM gas
+/// of the execution against the same `gas_limit`, so the larger of the two is required.
+#[must_use]
+pub fn required_gas_limit(neon_gas: u64, evm_gas_used: u64) -> u64 {
+    neon_gas.max(evm_gas_used)
+}
 
+/// Neon gas of the executed transaction
+#[must_use]
+pub fn neon_gas(rent: &TransactionRent, steps_executed: u64) -> u64 {
     let max_iterations = (steps_executed + (EVM_STEPS_MIN - 1)) / EVM_STEPS_MIN;
     let steps_gas = max_iterations * (LAMPORTS_PER_SIGNATURE + PAYMENT_TO_TREASURE);
     let begin_end_gas = 2 * LAMPORTS_PER_SIGNATURE;
-    let actions_gas = storage.apply_actions(&actions);
-    let accounts_gas = storage.apply_accounts_operations(accounts_operations);
+    let actions_gas = rent.actions;
+    let accounts_gas = rent.accounts + rent.resize_iterations * LAMPORTS_PER_SIGNATURE;
     info!("Gas - steps: {steps_gas}, actions: {actions_gas}, accounts: {accounts_gas}");
 
-    let (result, status) = match exit_status {
-        ExitStatus::Return(v) => (v, "succeed"),
-        ExitStatus::Revert(v) => (v, "revert"),
-        ExitStatus::Stop | ExitStatus::Suicide => (vec![], "succeed"),
+    steps_gas + begin_end_gas + actions_gas + accounts_gas
+}
+
+/// Return data and status name of the finished transaction
+#[must_use]
+pub fn exit_result(exit_status: &ExitStatus) -> (&[u8], &'static str) {
+    match exit_status {
+        ExitStatus::Return(v) => (v.as_slice(), "succeed"),
+        ExitStatus::Revert(v) => (v.as_slice(), "revert"),
+        ExitStatus::Stop | ExitStatus::Suicide => (&[], "succeed"),
         ExitStatus::StepLimit => unreachable!(),
-    };
+    }
+}
+
+impl Emulation<'_> {
+    /// Gas of the path the operator picks for the transaction,
+    /// `iteration_steps` is the number of steps in a single Solana transaction
+    #[must_use]
+    pub fn estimate_gas(&self, iteration_steps: u64) -> GasEstimation {
+        let storage = &self.storage;
+        let accounts = storage.accounts.borrow().len() + storage.solana_accounts.borrow().len();
+
+        gas_estimation::estimate(&EstimationInput {
+            steps_executed: self.steps_executed,
+            iteration_steps,
+            rlp_len: self.rlp_len,
+            accounts,
+            resize_iterations: self.rent.resize_iterations,
+            actions_rent: self.rent.actions,
+            accounts_rent: self.rent.accounts,
+        })
+    }
+
+    /// Emulation result in the format expected by the proxy
+    #[must_use]
+    pub fn to_json(&self) -> serde_json::Value {
+        let storage = &self.storage;
+        let steps_executed = self.steps_executed;
 
-    let accounts: Vec<NeonAccount> = storage.accounts.borrow().values().cloned().collect();
+        let used_gas = required_gas_limit(neon_gas(&self.rent, steps_executed), self.evm_gas_used);
+        let (result, status) = exit_result(&self.exit_status);
 
-    let solana_accounts: Vec<SolanaAccount> =
-        storage.solana_accounts.borrow().values().cloned().collect();
+        let accounts: Vec<NeonAccount> = storage.accounts.borrow().values().cloned().collect();
 
-    let json = serde_json::json!({
-        "accounts": accounts,
-        "solana_accounts": solana_accounts,
-        "token_accounts": [],
-        "result": hex::encode(result),
-        "exit_status": status,
-        "steps_executed": steps_executed,
-        "used_gas": steps_gas + begin_end_gas + actions_gas + accounts_gas,
-        "actions": actions
-    });
+        let solana_accounts: Vec<SolanaAccount> =
+            storage.solana_accounts.borrow().values().cloned().collect();
 
-    Ok(json)
+        serde_json::json!({
+            "accounts": accounts,
+            "solana_accounts": solana_accounts,
+            "token_accounts": [],
+            "result": hex::encode(result),
+            "exit_status": status,
+            "steps_executed": steps_executed,
+            "used_gas": used_gas,
+            "evm_gas_used": self.evm_gas_used,
+            "logs": Log::from_actions(&self.actions),
+            "actions": self.actions
+        })
+    }
 }
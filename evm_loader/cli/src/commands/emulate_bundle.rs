@@ -0,0 +1,84 @@
+use evm_loader::{evm::Spec, types::Address};
+use solana_sdk::pubkey::Pubkey;
+
+use crate::{
+    account_storage::{EmulatorAccountStorage, NeonAccount, SolanaAccount},
+    commands::emulate::{
+        apply_rent, execute_transaction, exit_result, neon_gas, required_gas_limit,
+    },
+    context::Context,
+    syscall_stubs::Stubs,
+    types::{AccountOverrides, BlockOverrides, Log, TxParams},
+    Config, NeonCliResult,
+};
+
+/// Emulates the transactions in order, every transaction runs on top of the state
+/// changed by the previous ones. State overrides of a transaction are added before it,
+/// block overrides apply to the transaction only.
+#[allow(clippy::too_many_arguments)]
+pub fn execute(
+    config: &Config,
+    context: &Context,
+    transactions: Vec<TxParams>,
+    token: Pubkey,
+    chain: u64,
+    spec: Spec,
+    steps: u64,
+    accounts: &[Address],
+    solana_accounts: &[Pubkey],
+) -> NeonCliResult {
+    let syscall_stubs = Stubs::new(context)?;
+    solana_sdk::program_stubs::set_syscall_stubs(syscall_stubs);
+
+    let mut storage = EmulatorAccountStorage::new(
+        config,
+        context,
+        token,
+        chain,
+        spec,
+        AccountOverrides::default(),
+        BlockOverrides::default(),
+    );
+    storage.initialize_cached_accounts(accounts, solana_accounts);
+
+    let mut results = Vec::with_capacity(transactions.len());
+    let mut total_gas = 0_u64;
+
+    for mut tx_params in transactions {
+        storage.add_overrides(tx_params.state_overrides.take().unwrap_or_default());
+        storage.set_block_overrides(tx_params.block_overrides.take().unwrap_or_default());
+
+        let (exit_status, actions, steps_executed, evm_gas_used) =
+            execute_transaction(&storage, tx_params, steps)?;
+
+        let rent = apply_rent(&storage, &actions);
+        let used_gas = required_gas_limit(neon_gas(&rent, steps_executed), evm_gas_used);
+        total_gas = total_gas.saturating_add(used_gas);
+
+        let (result, status) = exit_result(&exit_status);
+        results.push(serde_json::json!({
+            "result": hex::encode(result),
+            "exit_status": status,
+            "steps_executed": steps_executed,
+            "used_gas": used_gas,
+            "evm_gas_used": evm_gas_used,
+            "logs": Log::from_actions(&actions),
+            "actions": actions,
+        }));
+
+        storage.commit_actions(&actions);
+    }
+
+    let accounts: Vec<NeonAccount> = storage.accounts.borrow().values().cloned().collect();
+
+    let solana_accounts: Vec<SolanaAccount> =
+        storage.solana_accounts.borrow().values().cloned().collect();
+
+    Ok(serde_json::json!({
+        "accounts": accounts,
+        "solana_accounts": solana_accounts,
+        "token_accounts": [],
+        "used_gas": total_gas,
+        "transactions": results,
+    }))
+}
@@ -1,22 +1,27 @@
 pub mod cancel_trx;
 pub mod collect_treasury;
 pub mod create_ether_account;
+pub mod debug;
 pub mod deposit;
 pub mod emulate;
+pub mod emulate_bundle;
 pub mod get_ether_account_data;
 pub mod get_neon_elf;
 pub mod get_storage_at;
 pub mod init_environment;
-mod trace;
+pub mod trace;
 mod transaction_executor;
 
 use crate::{
-    commands::get_neon_elf::CachedElfParams, context::Context, program_options::truncate,
-    types::TxParams, Config, NeonCliResult,
+    commands::get_neon_elf::CachedElfParams,
+    context::Context,
+    transaction_plan::PlanOptions,
+    types::{AccessListItem, AccountOverrides, BlockOverrides, TxParams},
+    Config, NeonCliResult,
 };
 use clap::ArgMatches;
 use ethnum::U256;
-use evm_loader::types::Address;
+use evm_loader::{config::EVM_STEPS_MIN, evm::Spec, types::Address};
 use solana_clap_utils::input_parsers::{pubkey_of, value_of, values_of};
 use solana_client::{
     client_error::Result as SolanaClientResult, rpc_config::RpcSendTransactionConfig,
@@ -29,7 +34,7 @@ use solana_sdk::{
     signature::Signature,
     transaction::Transaction,
 };
-use std::str::FromStr;
+use std::{io::Read, str::FromStr};
 
 pub fn execute(
     cmd: &str,
@@ -39,8 +44,8 @@ pub fn execute(
 ) -> NeonCliResult {
     match (cmd, params) {
         ("emulate", Some(params)) => {
-            let tx = parse_tx(params);
-            let (token, chain, steps, accounts, solana_accounts) =
+            let tx = parse_tx(params, read_stdin()?);
+            let (token, chain, spec, steps, accounts, solana_accounts) =
                 parse_tx_params(config, context, params);
             emulate::execute(
                 config,
@@ -48,6 +53,30 @@ pub fn execute(
                 tx,
                 token,
                 chain,
+                spec,
+                steps,
+                &accounts,
+                &solana_accounts,
+                iteration_steps(params),
+                plan_options(config, context, params).as_ref(),
+            )
+        }
+        ("debug", Some(params)) => {
+            // The stdin is read by the debugger
+            let data = params
+                .value_of("data")
+                .map(|data| hex::decode(data.trim_start_matches("0x")))
+                .transpose()?;
+            let tx = parse_tx(params, data);
+            let (token, chain, spec, steps, accounts, solana_accounts) =
+                parse_tx_params(config, context, params);
+            debug::execute(
+                config,
+                context,
+                tx,
+                token,
+                chain,
+                spec,
                 steps,
                 &accounts,
                 &solana_accounts,
@@ -55,7 +84,7 @@ pub fn execute(
         }
         ("emulate_hash", Some(params)) => {
             let tx = context.rpc_client.get_transaction_data()?;
-            let (token, chain, steps, accounts, solana_accounts) =
+            let (token, chain, spec, steps, accounts, solana_accounts) =
                 parse_tx_params(config, context, params);
             emulate::execute(
                 config,
@@ -63,39 +92,102 @@ pub fn execute(
                 tx,
                 token,
                 chain,
+                spec,
+                steps,
+                &accounts,
+                &solana_accounts,
+                iteration_steps(params),
+                plan_options(config, context, params).as_ref(),
+            )
+        }
+        ("emulate-bundle", Some(params)) => {
+            let transactions = parse_bundle(params)?;
+            let (token, chain, spec, steps, accounts, solana_accounts) =
+                parse_tx_params(config, context, params);
+            emulate_bundle::execute(
+                config,
+                context,
+                transactions,
+                token,
+                chain,
+                spec,
                 steps,
                 &accounts,
                 &solana_accounts,
             )
         }
         ("trace", Some(params)) => {
-            let tx = parse_tx(params);
-            let (token, chain, steps, accounts, solana_accounts) =
+            let tx = parse_tx(params, read_stdin()?);
+            let (token, chain, spec, steps, accounts, solana_accounts) =
                 parse_tx_params(config, context, params);
+            let tracer = value_of(params, "tracer").unwrap_or_default();
+            let options = trace_options(params);
+            if let Some(path) = params.value_of("stream") {
+                let file = std::io::BufWriter::new(std::fs::File::create(path)?);
+                let summary = trace::stream(
+                    config,
+                    context,
+                    tx,
+                    token,
+                    chain,
+                    spec,
+                    steps,
+                    &accounts,
+                    &solana_accounts,
+                    options,
+                    file,
+                )?;
+                return Ok(serde_json::json!(summary));
+            }
             trace::execute(
                 config,
                 context,
                 tx,
                 token,
                 chain,
+                spec,
                 steps,
                 &accounts,
                 &solana_accounts,
+                tracer,
+                options,
             )
         }
         ("trace_hash", Some(params)) => {
             let tx = context.rpc_client.get_transaction_data()?;
-            let (token, chain, steps, accounts, solana_accounts) =
+            let (token, chain, spec, steps, accounts, solana_accounts) =
                 parse_tx_params(config, context, params);
+            let tracer = value_of(params, "tracer").unwrap_or_default();
+            let options = trace_options(params);
+            if let Some(path) = params.value_of("stream") {
+                let file = std::io::BufWriter::new(std::fs::File::create(path)?);
+                let summary = trace::stream(
+                    config,
+                    context,
+                    tx,
+                    token,
+                    chain,
+                    spec,
+                    steps,
+                    &accounts,
+                    &solana_accounts,
+                    options,
+                    file,
+                )?;
+                return Ok(serde_json::json!(summary));
+            }
             trace::execute(
                 config,
                 context,
                 tx,
                 token,
                 chain,
+                spec,
                 steps,
                 &accounts,
                 &solana_accounts,
+                tracer,
+                options,
             )
         }
         ("create-ether-account", Some(params)) => {
@@ -137,6 +229,51 @@ pub fn execute(
     }
 }
 
+/// Steps per iteration if the accurate gas estimation is requested
+fn iteration_steps(params: &ArgMatches) -> Option<u64> {
+    params
+        .is_present("estimate_gas")
+        .then(|| value_of(params, "iteration_steps").unwrap_or(EVM_STEPS_MIN))
+}
+
+/// Operator settings of the Solana transactions plan if the plan is requested
+fn plan_options(config: &Config, context: &Context, params: &ArgMatches) -> Option<PlanOptions> {
+    let raw_transaction = params.value_of("plan")?;
+    let operator_ether = address_of(params, "operator_ether").expect("operator_ether parse error");
+
+    Some(PlanOptions {
+        raw_transaction: hex::decode(raw_transaction.trim_start_matches("0x"))
+            .expect("plan parse error"),
+        operator: context.signer.pubkey(),
+        operator_ether_account: operator_ether.find_solana_address(&config.evm_loader).0,
+        holder: pubkey_of(params, "holder").expect("holder parse error"),
+        create_holder: params.is_present("create_holder"),
+        treasury_index: value_of(params, "treasury_index").unwrap_or_default(),
+    })
+}
+
+fn trace_options(params: &ArgMatches) -> trace::TraceOptions {
+    trace::TraceOptions {
+        diff_mode: params.is_present("diff_mode"),
+        with_log: params.is_present("with_log"),
+        disable_stack: params.is_present("disable_stack"),
+        disable_memory: params.is_present("disable_memory"),
+        disable_storage: params.is_present("disable_storage"),
+        enable_return_data: params.is_present("enable_return_data"),
+        max_records: value_of(params, "max_records"),
+        artifacts: params.value_of("artifacts").map(std::path::PathBuf::from),
+        filter: trace::TraceFilter {
+            addresses: values_of::<Address>(params, "filter_address").unwrap_or_default(),
+            min_depth: value_of(params, "min_depth"),
+            max_depth: value_of(params, "max_depth"),
+            min_pc: value_of(params, "min_pc"),
+            max_pc: value_of(params, "max_pc"),
+            first_step: value_of(params, "first_step"),
+            last_step: value_of(params, "last_step"),
+        },
+    }
+}
+
 fn address_or_deploy_of(matches: &ArgMatches<'_>, name: &str) -> Option<Address> {
     if matches.value_of(name) == Some("deploy") {
         return None;
@@ -160,18 +297,16 @@ fn u256_of(matches: &ArgMatches<'_>, name: &str) -> Option<U256> {
     })
 }
 
-fn read_stdin() -> Option<Vec<u8>> {
+/// Transaction data from the first line of the stdin
+fn read_stdin() -> Result<Option<Vec<u8>>, hex::FromHexError> {
     let mut data = String::new();
 
-    if let Ok(len) = std::io::stdin().read_line(&mut data) {
-        if len == 0 {
-            return None;
+    match std::io::stdin().read_line(&mut data) {
+        Ok(len) if len > 0 => {
+            let data = data.trim().trim_start_matches("0x");
+            hex::decode(data).map(Some)
         }
-        let data = truncate(data.as_str());
-        let bin = hex::decode(data).expect("data hex::decore error");
-        Some(bin)
-    } else {
-        None
+        _ => Ok(None),
     }
 }
 
@@ -199,12 +334,22 @@ pub fn send_transaction(
         )
 }
 
-fn parse_tx(params: &ArgMatches) -> TxParams {
+fn parse_tx(params: &ArgMatches, data: Option<Vec<u8>>) -> TxParams {
     let from = address_of(params, "sender").expect("sender parse error");
     let to = address_or_deploy_of(params, "contract");
-    let data = read_stdin();
     let value = u256_of(params, "value");
     let gas_limit = u256_of(params, "gas_limit");
+    let max_fee_per_gas = u256_of(params, "max_fee_per_gas");
+    let max_priority_fee_per_gas = u256_of(params, "max_priority_fee_per_gas");
+    let access_list = params.value_of("access_list").map(|value| {
+        serde_json::from_str::<Vec<AccessListItem>>(value).expect("access_list parse error")
+    });
+    let state_overrides = params.value_of("state_overrides").map(|value| {
+        serde_json::from_str::<AccountOverrides>(value).expect("state_overrides parse error")
+    });
+    let block_overrides = params.value_of("block_overrides").map(|value| {
+        serde_json::from_str::<BlockOverrides>(value).expect("block_overrides parse error")
+    });
 
     TxParams {
         from,
@@ -212,18 +357,38 @@ fn parse_tx(params: &ArgMatches) -> TxParams {
         data,
         value,
         gas_limit,
+        max_fee_per_gas,
+        max_priority_fee_per_gas,
+        access_list,
+        state_overrides,
+        block_overrides,
     }
 }
 
+/// Transactions of the bundle from the file or stdin
+fn parse_bundle(params: &ArgMatches) -> std::io::Result<Vec<TxParams>> {
+    let data = match params.value_of("transactions") {
+        Some(path) => std::fs::read_to_string(path)?,
+        None => {
+            let mut data = String::new();
+            std::io::stdin().read_to_string(&mut data)?;
+            data
+        }
+    };
+
+    Ok(serde_json::from_str(&data).expect("transactions parse error"))
+}
+
 pub fn parse_tx_params(
     config: &Config,
     context: &Context,
     params: &ArgMatches,
-) -> (Pubkey, u64, u64, Vec<Address>, Vec<Pubkey>) {
-    // Read ELF params only if token_mint or chain_id is not set.
+) -> (Pubkey, u64, Spec, u64, Vec<Address>, Vec<Pubkey>) {
+    // Read ELF params only if token_mint, chain_id or evm_spec is not set.
     let mut token = pubkey_of(params, "token_mint");
     let mut chain = value_of(params, "chain_id");
-    if token.is_none() || chain.is_none() {
+    let mut spec = value_of::<Spec>(params, "evm_spec");
+    if token.is_none() || chain.is_none() || spec.is_none() {
         let cached_elf_params = CachedElfParams::new(config, context);
         token = token.or_else(|| {
             Some(
@@ -245,9 +410,16 @@ pub fn parse_tx_params(
                 .expect("NEON_CHAIN_ID u64 ctor error"),
             )
         });
+        // Programs built before the hardfork selection run the latest spec
+        spec = spec.or_else(|| {
+            cached_elf_params
+                .get("NEON_EVM_SPEC")
+                .map(|name| Spec::from_str(name).expect("NEON_EVM_SPEC parse error"))
+        });
     }
     let token = token.expect("token_mint get error");
     let chain = chain.expect("chain_id get error");
+    let spec = spec.unwrap_or_default();
     let max_steps =
         value_of::<u64>(params, "max_steps_to_execute").expect("max_steps_to_execute parse error");
 
@@ -255,5 +427,5 @@ pub fn parse_tx_params(
 
     let solana_accounts = values_of::<Pubkey>(params, "solana_accounts").unwrap_or_default();
 
-    (token, chain, max_steps, accounts, solana_accounts)
+    (token, chain, spec, max_steps, accounts, solana_accounts)
 }
@@ -1,13 +1,117 @@
+use std::{io::Write, path::PathBuf, str::FromStr, sync::Arc};
+
 use crate::{
     commands::emulate,
     context::Context,
-    event_listener::tracer::Tracer,
-    types::{trace::TracedCall, TxParams},
+    errors::NeonCliError,
+    event_listener::{
+        action_tracer::{ActionTracer, ReplayTracer},
+        call_tracer::CallTracer,
+        filter::FilteredListener,
+        prestate_tracer::PrestateTracer,
+        stream_tracer::{StreamSummary, StreamTracer},
+        struct_logger::StructLogger,
+        tracer::Tracer,
+    },
+    types::{
+        source_map::SourceMaps,
+        trace::{TraceResults, TracedCall, VMTrace},
+        Log, TxParams,
+    },
     Config, NeonCliResult,
 };
-use evm_loader::types::Address;
+use evm_loader::{
+    evm::{ExitStatus, Spec},
+    types::Address,
+};
+use serde::{Deserialize, Serialize};
 use solana_sdk::pubkey::Pubkey;
 
+/// Output format of the trace
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum TracerType {
+    /// OpenEthereum `vmTrace` with the full trace data
+    VmTrace,
+    /// Geth `callTracer` nested call frames
+    CallTracer,
+    /// Geth `prestateTracer` touched accounts state
+    PrestateTracer,
+    /// Geth `structLogger` steps, the `debug_traceTransaction` default
+    StructLogger,
+    /// OpenEthereum `trace_replayTransaction` result with `trace`, `vmTrace` and `stateDiff`
+    OpenEthereum,
+}
+
+impl TracerType {
+    pub const NAMES: [&'static str; 5] = [
+        "vmTrace",
+        "callTracer",
+        "prestateTracer",
+        "structLogger",
+        "openEthereum",
+    ];
+}
+
+impl Default for TracerType {
+    fn default() -> Self {
+        Self::VmTrace
+    }
+}
+
+impl FromStr for TracerType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "vmTrace" => Ok(Self::VmTrace),
+            "callTracer" => Ok(Self::CallTracer),
+            "prestateTracer" => Ok(Self::PrestateTracer),
+            "structLogger" => Ok(Self::StructLogger),
+            "openEthereum" => Ok(Self::OpenEthereum),
+            _ => Err(format!("Unknown tracer {s}")),
+        }
+    }
+}
+
+/// Steps to include in the trace, all steps by default.
+/// Frames are always reported, so `callTracer` and `prestateTracer` are not affected.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct TraceFilter {
+    /// Steps executing the code of, or on the storage of, these contracts
+    pub addresses: Vec<Address>,
+    /// Call depth of the top level frame is 1
+    pub min_depth: Option<usize>,
+    pub max_depth: Option<usize>,
+    pub min_pc: Option<usize>,
+    pub max_pc: Option<usize>,
+    /// Window of the transaction steps, counted from 0 across all frames
+    pub first_step: Option<u64>,
+    pub last_step: Option<u64>,
+}
+
+/// Tracer options, named after the Geth tracer config
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct TraceOptions {
+    /// `prestateTracer` reports the state before and after the transaction
+    pub diff_mode: bool,
+    /// `callTracer` includes the logs of the frames
+    pub with_log: bool,
+    /// `structLogger` and `vmTrace` full trace data options
+    pub disable_stack: bool,
+    pub disable_memory: bool,
+    pub disable_storage: bool,
+    pub enable_return_data: bool,
+    /// Streamed trace stops writing records after this number
+    pub max_records: Option<usize>,
+    /// Directory with the solc standard JSON output, `vmTrace` and `openEthereum`
+    /// operations are annotated with the Solidity source locations
+    pub artifacts: Option<PathBuf>,
+    #[serde(flatten)]
+    pub filter: TraceFilter,
+}
+
 #[allow(clippy::too_many_arguments)]
 pub fn execute(
     config: &Config,
@@ -15,32 +119,156 @@ pub fn execute(
     tx: TxParams,
     token: Pubkey,
     chain: u64,
+    spec: Spec,
     steps: u64,
     accounts: &[Address],
     solana_accounts: &[Pubkey],
+    tracer: TracerType,
+    options: TraceOptions,
 ) -> NeonCliResult {
-    let mut tracer = Tracer::new();
+    let emulate_trx = || {
+        emulate::emulate(
+            config,
+            context,
+            tx,
+            token,
+            chain,
+            spec,
+            steps,
+            accounts,
+            solana_accounts,
+        )
+    };
 
-    evm_loader::evm::tracing::using(&mut tracer, || {
-        emulate::execute(
+    let filter = options.filter.clone();
+    let source_maps = match (tracer, &options.artifacts) {
+        (TracerType::VmTrace | TracerType::OpenEthereum, Some(artifacts)) => {
+            Some(Arc::new(SourceMaps::load(artifacts)?))
+        }
+        _ => None,
+    };
+
+    match tracer {
+        TracerType::VmTrace => {
+            let mut tracer = FilteredListener::new(filter, Tracer::new(options, source_maps));
+            let emulation = evm_loader::evm::tracing::using(&mut tracer, emulate_trx)?;
+
+            let (vm_trace, full_trace_data) = tracer.into_inner().into_traces();
+            let revert_location = match emulation.exit_status {
+                ExitStatus::Revert(_) => vm_trace
+                    .as_ref()
+                    .and_then(VMTrace::revert_location)
+                    .cloned(),
+                _ => None,
+            };
+
+            let trace = TracedCall {
+                vm_trace,
+                full_trace_data,
+                used_gas: emulation.to_json()["used_gas"].as_u64().unwrap_or_default(),
+                logs: Log::from_actions(&emulation.actions),
+                revert_location,
+            };
+
+            Ok(serde_json::json!(trace))
+        }
+        TracerType::CallTracer => {
+            let mut tracer = CallTracer::new(options.with_log);
+            evm_loader::evm::tracing::using(&mut tracer, emulate_trx)?;
+
+            Ok(serde_json::json!(tracer.into_call_frame()))
+        }
+        TracerType::PrestateTracer => {
+            let emulation = emulate_trx()?;
+
+            let tracer = PrestateTracer::new(&emulation.storage, &emulation.actions);
+            if options.diff_mode {
+                Ok(serde_json::json!(tracer.into_diff()))
+            } else {
+                Ok(serde_json::json!(tracer.into_prestate()))
+            }
+        }
+        TracerType::StructLogger => {
+            let mut logger = FilteredListener::new(filter, StructLogger::new(options));
+            let emulation = evm_loader::evm::tracing::using(&mut logger, emulate_trx)?;
+            let logger = logger.into_inner();
+
+            let (failed, return_value): (bool, &[u8]) = match &emulation.exit_status {
+                ExitStatus::Return(value) => (false, value.as_slice()),
+                ExitStatus::Revert(value) => (true, value.as_slice()),
+                _ => (false, &[]),
+            };
+
+            let result = logger.into_result(emulation.evm_gas_used, failed, return_value);
+            Ok(serde_json::json!(result))
+        }
+        TracerType::OpenEthereum => {
+            let mut tracer = ReplayTracer {
+                vm: FilteredListener::new(filter, Tracer::new(options, source_maps)),
+                actions: ActionTracer::new(),
+            };
+            let emulation = evm_loader::evm::tracing::using(&mut tracer, emulate_trx)?;
+
+            let output = match &emulation.exit_status {
+                ExitStatus::Return(value) | ExitStatus::Revert(value) => value.as_slice(),
+                _ => &[],
+            };
+
+            let (vm_trace, _) = tracer.vm.into_inner().into_traces();
+            let state_diff =
+                PrestateTracer::new(&emulation.storage, &emulation.actions).into_state_diff();
+
+            let results = TraceResults {
+                output: format!("0x{}", hex::encode(output)),
+                trace: tracer.actions.into_traces(),
+                vm_trace,
+                state_diff,
+            };
+
+            Ok(serde_json::json!(results))
+        }
+    }
+}
+
+/// Writes the trace as newline delimited JSON records, see `StreamTracer`
+#[allow(clippy::too_many_arguments)]
+pub fn stream<W: Write + 'static>(
+    config: &Config,
+    context: &Context,
+    tx: TxParams,
+    token: Pubkey,
+    chain: u64,
+    spec: Spec,
+    steps: u64,
+    accounts: &[Address],
+    solana_accounts: &[Pubkey],
+    options: TraceOptions,
+    writer: W,
+) -> Result<StreamSummary, NeonCliError> {
+    let filter = options.filter.clone();
+    let mut tracer = FilteredListener::new(filter, StreamTracer::new(options, writer));
+    let emulation = evm_loader::evm::tracing::using(&mut tracer, || {
+        emulate::emulate(
             config,
             context,
             tx,
             token,
             chain,
+            spec,
             steps,
             accounts,
             solana_accounts,
         )
     })?;
 
-    let (vm_trace, full_trace_data) = tracer.into_traces();
-
-    let trace = TracedCall {
-        vm_trace,
-        full_trace_data,
-        used_gas: 0,
+    let (failed, return_value): (bool, &[u8]) = match &emulation.exit_status {
+        ExitStatus::Return(value) => (false, value.as_slice()),
+        ExitStatus::Revert(value) => (true, value.as_slice()),
+        _ => (false, &[]),
     };
 
-    Ok(serde_json::json!(trace))
+    let summary = tracer
+        .into_inner()
+        .finish(emulation.evm_gas_used, failed, return_value)?;
+    Ok(summary)
 }
@@ -85,6 +85,9 @@ pub enum NeonCliError {
 
     #[error("Panic: {0}")]
     Panic(String),
+
+    #[error("Planned transaction doesn't match the emulated one: {0}")]
+    PlanTransactionMismatch(String),
 }
 
 impl NeonCliError {
@@ -113,6 +116,7 @@ impl NeonCliError {
             NeonCliError::TooManySteps => 245,
             NeonCliError::FromHexError(_) => 246,
             NeonCliError::InvalidChDbConfig => 247,
+            NeonCliError::PlanTransactionMismatch(_) => 248,
         }
     }
 }
@@ -0,0 +1,426 @@
+use ethnum::U256;
+use evm_loader::evm::tracing::{CallType, Event, EventListener};
+use evm_loader::evm::ExitStatus;
+use evm_loader::types::Address;
+
+use super::{filter::FilteredListener, tracer::Tracer};
+use crate::types::trace::{
+    ActionResult, ActionTrace, CallAction, CreateAction, SuicideAction, TraceAction,
+};
+
+struct OpenAction {
+    /// Index in the flat list
+    index: usize,
+    /// Storage address of the frame, `from` of the nested delegate calls
+    contract: Address,
+    /// Exceptional halt of the frame, reported instead of the revert
+    halt: Option<String>,
+}
+
+/// Builds the flat OpenEthereum action traces from the frame events
+#[derive(Default)]
+pub struct ActionTracer {
+    traces: Vec<ActionTrace>,
+    stack: Vec<OpenAction>,
+}
+
+impl ActionTracer {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Actions in the execution order
+    #[must_use]
+    pub fn into_traces(mut self) -> Vec<ActionTrace> {
+        // Frames left open by the step limit
+        while !self.stack.is_empty() {
+            self.close(None, Err("Out of steps".to_string()));
+        }
+
+        self.traces
+    }
+
+    /// Adds the action as the next nested action of the current frame
+    fn push(&mut self, action: TraceAction, action_type: &str) -> usize {
+        let trace_address = match self.stack.last() {
+            Some(parent) => {
+                let parent = &mut self.traces[parent.index];
+                let mut trace_address = parent.trace_address.clone();
+                trace_address.push(parent.subtraces);
+                parent.subtraces += 1;
+                trace_address
+            }
+            None => Vec::new(),
+        };
+
+        self.traces.push(ActionTrace {
+            action,
+            result: None,
+            error: None,
+            subtraces: 0,
+            trace_address,
+            action_type: action_type.to_string(),
+        });
+
+        self.traces.len() - 1
+    }
+
+    fn begin(
+        &mut self,
+        context: evm_loader::evm::Context,
+        call_type: CallType,
+        code: &[u8],
+        input: &[u8],
+        gas: u64,
+    ) {
+        let (action, action_type) = match call_type {
+            CallType::Create | CallType::Create2 => {
+                let action = TraceAction::Create(CreateAction {
+                    from: context.caller,
+                    value: format!("0x{:x}", context.value),
+                    gas: format!("{gas:#x}"),
+                    init: format!("0x{}", hex::encode(code)),
+                });
+                (action, "create")
+            }
+            _ => {
+                let from = match call_type {
+                    CallType::DelegateCall => self
+                        .stack
+                        .last()
+                        .map_or(context.caller, |parent| parent.contract),
+                    _ => context.caller,
+                };
+
+                let action = TraceAction::Call(CallAction {
+                    from,
+                    to: context.code_address.unwrap_or(context.contract),
+                    value: format!("0x{:x}", context.value),
+                    gas: format!("{gas:#x}"),
+                    input: format!("0x{}", hex::encode(input)),
+                    call_type: call_type.name().to_lowercase(),
+                });
+                (action, "call")
+            }
+        };
+
+        let index = self.push(action, action_type);
+        self.stack.push(OpenAction {
+            index,
+            contract: context.contract,
+            halt: None,
+        });
+    }
+
+    fn end(&mut self, gas_used: u64, status: ExitStatus) {
+        match status {
+            ExitStatus::Stop | ExitStatus::Suicide => {
+                self.close(Some(gas_used), Ok(Vec::new()));
+            }
+            ExitStatus::Return(output) => {
+                self.close(Some(gas_used), Ok(output));
+            }
+            ExitStatus::Revert(_) => {
+                let error = self
+                    .stack
+                    .last_mut()
+                    .and_then(|open| open.halt.take())
+                    .unwrap_or_else(|| "Reverted".to_string());
+                self.close(Some(gas_used), Err(error));
+            }
+            ExitStatus::StepLimit => {
+                // Reported by the top level frame, nested frames are still open
+                while !self.stack.is_empty() {
+                    self.close(None, Err("Out of steps".to_string()));
+                }
+            }
+        }
+    }
+
+    /// Sets the result of the current frame, failed actions have no result
+    fn close(&mut self, gas_used: Option<u64>, output: Result<Vec<u8>, String>) {
+        let open = match self.stack.pop() {
+            Some(open) => open,
+            None => return,
+        };
+
+        let trace = &mut self.traces[open.index];
+        let output = match output {
+            Ok(output) => output,
+            Err(error) => {
+                trace.error = Some(error);
+                return;
+            }
+        };
+
+        let gas_used = format!("{:#x}", gas_used.unwrap_or_default());
+        trace.result = Some(match trace.action {
+            TraceAction::Create(_) => ActionResult::Create {
+                gas_used,
+                code: format!("0x{}", hex::encode(output)),
+                address: open.contract,
+            },
+            _ => ActionResult::Call {
+                gas_used,
+                output: format!("0x{}", hex::encode(output)),
+            },
+        });
+    }
+
+    fn suicide(&mut self, address: Address, beneficiary: Address, value: U256) {
+        let action = TraceAction::Suicide(SuicideAction {
+            address,
+            refund_address: beneficiary,
+            balance: format!("0x{value:x}"),
+        });
+
+        self.push(action, "suicide");
+    }
+}
+
+impl EventListener for ActionTracer {
+    fn event(&mut self, event: Event) {
+        match event {
+            Event::BeginVM {
+                context,
+                call_type,
+                code,
+                input,
+                gas,
+            } => {
+                self.begin(context, call_type, code, input, gas);
+            }
+            Event::EndVM { status, gas_used } => {
+                self.end(gas_used, status);
+            }
+            Event::SelfDestruct {
+                address,
+                beneficiary,
+                value,
+            } => {
+                self.suicide(address, beneficiary, value);
+            }
+            Event::StepError { message, reason } => {
+                if let Some(open) = self.stack.last_mut() {
+                    open.halt =
+                        Some(reason.map_or(message, |reason| halt_error(reason).to_string()));
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// OpenEthereum error of the exceptional halt with the Geth `reason`
+fn halt_error(reason: &'static str) -> &'static str {
+    match reason {
+        "out of gas" => "Out of gas",
+        "stack limit reached" => "Out of stack",
+        "stack underflow" => "Stack underflow",
+        "invalid jump destination" => "Bad jump destination",
+        "invalid opcode" => "Bad instruction",
+        "write protection" => "Mutable call in static context",
+        "return data out of bounds" => "Out of bounds",
+        reason => reason,
+    }
+}
+
+/// OpenEthereum `trace` and `vmTrace` collected in a single emulation
+pub struct ReplayTracer {
+    pub vm: FilteredListener<Tracer>,
+    pub actions: ActionTracer,
+}
+
+impl EventListener for ReplayTracer {
+    fn event(&mut self, event: Event) {
+        self.actions.event(event.clone());
+        self.vm.event(event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ethnum::U256;
+    use evm_loader::evm::tracing::{CallType, Event, EventListener};
+    use evm_loader::evm::{Context, ExitStatus};
+    use evm_loader::types::Address;
+    use serde_json::json;
+
+    use super::ActionTracer;
+
+    const A: Address = Address([0xaa; 20]);
+    const B: Address = Address([0xbb; 20]);
+    const C: Address = Address([0xcc; 20]);
+    const D: Address = Address([0xdd; 20]);
+
+    fn begin(
+        tracer: &mut ActionTracer,
+        call_type: CallType,
+        context: Context,
+        code: &[u8],
+        gas: u64,
+    ) {
+        tracer.event(Event::BeginVM {
+            context,
+            call_type,
+            code,
+            input: &[0x01],
+            gas,
+        });
+    }
+
+    fn end(tracer: &mut ActionTracer, status: ExitStatus, gas_used: u64) {
+        tracer.event(Event::EndVM { status, gas_used });
+    }
+
+    #[test]
+    fn nested_actions() {
+        let mut tracer = ActionTracer::new();
+
+        let context = Context {
+            caller: A,
+            contract: B,
+            value: U256::new(1),
+            code_address: None,
+        };
+        begin(&mut tracer, CallType::Call, context, &[], 1000);
+
+        // Delegate call is reported from the storage address of the parent
+        let context = Context {
+            caller: A,
+            contract: B,
+            value: U256::new(1),
+            code_address: Some(C),
+        };
+        begin(&mut tracer, CallType::DelegateCall, context, &[], 500);
+        end(&mut tracer, ExitStatus::Stop, 100);
+
+        let context = Context {
+            caller: B,
+            contract: D,
+            value: U256::ZERO,
+            code_address: None,
+        };
+        begin(&mut tracer, CallType::Create, context, &[0x60, 0x00], 300);
+        tracer.event(Event::StepError {
+            message: "out of gas".to_string(),
+            reason: Some("out of gas"),
+        });
+        end(&mut tracer, ExitStatus::Revert(Vec::new()), 300);
+
+        tracer.event(Event::SelfDestruct {
+            address: B,
+            beneficiary: A,
+            value: U256::new(1),
+        });
+        end(&mut tracer, ExitStatus::Return(vec![0x42]), 900);
+
+        assert_eq!(
+            serde_json::to_value(tracer.into_traces()).unwrap(),
+            json!([
+                {
+                    "action": {
+                        "from": A.to_string(),
+                        "to": B.to_string(),
+                        "value": "0x1",
+                        "gas": "0x3e8",
+                        "input": "0x01",
+                        "callType": "call",
+                    },
+                    "result": { "gasUsed": "0x384", "output": "0x42" },
+                    "subtraces": 3,
+                    "traceAddress": [],
+                    "type": "call",
+                },
+                {
+                    "action": {
+                        "from": B.to_string(),
+                        "to": C.to_string(),
+                        "value": "0x1",
+                        "gas": "0x1f4",
+                        "input": "0x01",
+                        "callType": "delegatecall",
+                    },
+                    "result": { "gasUsed": "0x64", "output": "0x" },
+                    "subtraces": 0,
+                    "traceAddress": [0],
+                    "type": "call",
+                },
+                {
+                    "action": {
+                        "from": B.to_string(),
+                        "value": "0x0",
+                        "gas": "0x12c",
+                        "init": "0x6000",
+                    },
+                    "result": null,
+                    "error": "Out of gas",
+                    "subtraces": 0,
+                    "traceAddress": [1],
+                    "type": "create",
+                },
+                {
+                    "action": {
+                        "address": B.to_string(),
+                        "refundAddress": A.to_string(),
+                        "balance": "0x1",
+                    },
+                    "result": null,
+                    "subtraces": 0,
+                    "traceAddress": [2],
+                    "type": "suicide",
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn revert_and_step_limit() {
+        let mut tracer = ActionTracer::new();
+
+        let context = Context {
+            caller: A,
+            contract: D,
+            value: U256::ZERO,
+            code_address: None,
+        };
+        begin(&mut tracer, CallType::Create2, context, &[0x00], 1000);
+
+        let context = Context {
+            caller: D,
+            contract: B,
+            value: U256::ZERO,
+            code_address: None,
+        };
+        begin(&mut tracer, CallType::StaticCall, context, &[], 500);
+        end(&mut tracer, ExitStatus::Revert(vec![0x01]), 10);
+
+        begin(&mut tracer, CallType::StaticCall, context, &[], 400);
+        end(&mut tracer, ExitStatus::StepLimit, 20);
+
+        let traces = serde_json::to_value(tracer.into_traces()).unwrap();
+        let summary: Vec<_> = traces
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|trace| {
+                json!([
+                    trace["type"],
+                    trace["traceAddress"],
+                    trace["result"],
+                    trace["error"]
+                ])
+            })
+            .collect();
+
+        assert_eq!(
+            summary,
+            vec![
+                json!(["create", [], null, "Out of steps"]),
+                json!(["call", [0], null, "Reverted"]),
+                json!(["call", [1], null, "Out of steps"]),
+            ]
+        );
+    }
+}
@@ -0,0 +1,509 @@
+use ethnum::U256;
+use evm_loader::evm::tracing::{CallType, Event, EventListener};
+use evm_loader::evm::ExitStatus;
+use evm_loader::types::Address;
+use serde::Serialize;
+
+/// Call frame in the Geth `callTracer` format
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CallFrame {
+    #[serde(rename = "type")]
+    pub call_type: String,
+    pub from: Address,
+    pub to: Address,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+    pub gas: String,
+    pub gas_used: String,
+    pub input: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub revert_reason: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub logs: Vec<CallLog>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub calls: Vec<CallFrame>,
+}
+
+/// Log emitted by the frame, `position` is the number of the nested calls made before it
+#[derive(Serialize, Debug, Clone)]
+pub struct CallLog {
+    pub address: Address,
+    pub topics: Vec<String>,
+    pub data: String,
+    pub position: String,
+}
+
+struct OpenFrame {
+    /// Storage address of the frame, `from` of the nested delegate calls
+    contract: Address,
+    /// Exceptional halt of the frame, reported instead of the revert
+    halt: Option<String>,
+    frame: CallFrame,
+}
+
+/// Builds nested call frames from the `BeginVM` and `EndVM` events
+#[derive(Default)]
+pub struct CallTracer {
+    stack: Vec<OpenFrame>,
+    root: Option<CallFrame>,
+    /// Geth `withLog` option
+    with_log: bool,
+}
+
+impl CallTracer {
+    #[must_use]
+    pub fn new(with_log: bool) -> Self {
+        Self {
+            with_log,
+            ..Self::default()
+        }
+    }
+
+    /// Top level call frame, `None` if the execution has not started
+    #[must_use]
+    pub fn into_call_frame(mut self) -> Option<CallFrame> {
+        // Frames left open by the step limit
+        while !self.stack.is_empty() {
+            self.close_frame(None, None, Some("step limit exceeded".to_string()));
+        }
+
+        self.root
+    }
+
+    fn open_frame(
+        &mut self,
+        context: evm_loader::evm::Context,
+        call_type: CallType,
+        code: &[u8],
+        input: &[u8],
+        gas: u64,
+    ) {
+        let from = match call_type {
+            CallType::DelegateCall => self
+                .stack
+                .last()
+                .map_or(context.caller, |parent| parent.contract),
+            _ => context.caller,
+        };
+
+        let (to, input) = match call_type {
+            CallType::Create | CallType::Create2 => (context.contract, code),
+            _ => (context.code_address.unwrap_or(context.contract), input),
+        };
+
+        let value = match call_type {
+            CallType::DelegateCall | CallType::StaticCall => None,
+            _ => Some(format!("0x{:x}", context.value)),
+        };
+
+        self.stack.push(OpenFrame {
+            contract: context.contract,
+            halt: None,
+            frame: CallFrame {
+                call_type: call_type.name().to_string(),
+                from,
+                to,
+                value,
+                gas: format!("{gas:#x}"),
+                gas_used: "0x0".to_string(),
+                input: format!("0x{}", hex::encode(input)),
+                output: None,
+                error: None,
+                revert_reason: None,
+                logs: Vec::new(),
+                calls: Vec::new(),
+            },
+        });
+    }
+
+    fn log(&mut self, address: Address, topics: &[[u8; 32]], data: &[u8]) {
+        if !self.with_log {
+            return;
+        }
+
+        if let Some(OpenFrame { frame, .. }) = self.stack.last_mut() {
+            frame.logs.push(CallLog {
+                address,
+                topics: topics
+                    .iter()
+                    .map(|topic| format!("0x{}", hex::encode(topic)))
+                    .collect(),
+                data: format!("0x{}", hex::encode(data)),
+                position: format!("{:#x}", frame.calls.len()),
+            });
+        }
+    }
+
+    /// Geth reports SELFDESTRUCT as a nested frame
+    fn selfdestruct(&mut self, address: Address, beneficiary: Address, value: U256) {
+        if let Some(OpenFrame { frame, .. }) = self.stack.last_mut() {
+            frame.calls.push(CallFrame {
+                call_type: "SELFDESTRUCT".to_string(),
+                from: address,
+                to: beneficiary,
+                value: Some(format!("0x{value:x}")),
+                gas: "0x0".to_string(),
+                gas_used: "0x0".to_string(),
+                input: "0x".to_string(),
+                output: None,
+                error: None,
+                revert_reason: None,
+                logs: Vec::new(),
+                calls: Vec::new(),
+            });
+        }
+    }
+
+    fn close_frame(
+        &mut self,
+        gas_used: Option<u64>,
+        output: Option<Vec<u8>>,
+        error: Option<String>,
+    ) {
+        let (mut frame, halt) = match self.stack.pop() {
+            Some(open) => (open.frame, open.halt),
+            None => return,
+        };
+
+        if let Some(gas_used) = gas_used {
+            frame.gas_used = format!("{gas_used:#x}");
+        }
+
+        // Exceptional halt reverts with the error message, Geth reports the halt only
+        let (output, error) = match halt {
+            Some(halt) if error.is_some() => (None, Some(halt)),
+            _ => (output, error),
+        };
+
+        if error.is_some() {
+            // Logs of the failed frame and its nested frames are reverted
+            clear_logs(&mut frame);
+        }
+        if error.as_deref() == Some("execution reverted") {
+            frame.revert_reason = output.as_deref().and_then(decode_revert_reason);
+        }
+        frame.output = output
+            .filter(|output| !output.is_empty())
+            .map(|output| format!("0x{}", hex::encode(output)));
+        frame.error = error;
+
+        match self.stack.last_mut() {
+            Some(parent) => parent.frame.calls.push(frame),
+            None => self.root = Some(frame),
+        }
+    }
+}
+
+impl EventListener for CallTracer {
+    fn event(&mut self, event: Event) {
+        match event {
+            Event::BeginVM {
+                context,
+                call_type,
+                code,
+                input,
+                gas,
+            } => {
+                self.open_frame(context, call_type, code, input, gas);
+            }
+            Event::EndVM { status, gas_used } => match status {
+                ExitStatus::Stop | ExitStatus::Suicide => {
+                    self.close_frame(Some(gas_used), None, None);
+                }
+                ExitStatus::Return(output) => {
+                    self.close_frame(Some(gas_used), Some(output), None);
+                }
+                ExitStatus::Revert(output) => {
+                    let error = Some("execution reverted".to_string());
+                    self.close_frame(Some(gas_used), Some(output), error);
+                }
+                ExitStatus::StepLimit => {
+                    // Reported by the top level frame, nested frames are still open
+                    while self.stack.len() > 1 {
+                        let error = Some("step limit exceeded".to_string());
+                        self.close_frame(None, None, error);
+                    }
+                    let error = Some("step limit exceeded".to_string());
+                    self.close_frame(Some(gas_used), None, error);
+                }
+            },
+            Event::Log {
+                address,
+                topics,
+                data,
+            } => {
+                self.log(address, topics, data);
+            }
+            Event::SelfDestruct {
+                address,
+                beneficiary,
+                value,
+            } => {
+                self.selfdestruct(address, beneficiary, value);
+            }
+            Event::StepError { message, reason } => {
+                if let Some(open) = self.stack.last_mut() {
+                    open.halt = Some(reason.map_or(message, ToString::to_string));
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn clear_logs(frame: &mut CallFrame) {
+    frame.logs.clear();
+    for call in &mut frame.calls {
+        clear_logs(call);
+    }
+}
+
+/// Decodes the ABI encoded `Error(string)` revert data
+fn decode_revert_reason(data: &[u8]) -> Option<String> {
+    const ERROR_SELECTOR: [u8; 4] = [0x08, 0xc3, 0x79, 0xa0];
+
+    if data.len() < 4 + 32 + 32 || data[..4] != ERROR_SELECTOR {
+        return None;
+    }
+
+    let length = U256::from_be_bytes(data[36..68].try_into().ok()?);
+    let length = usize::try_from(length).ok()?;
+    let message = data.get(68..68usize.checked_add(length)?)?;
+
+    String::from_utf8(message.to_vec()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use ethnum::U256;
+    use evm_loader::evm::tracing::{CallType, Event, EventListener};
+    use evm_loader::evm::{Context, ExitStatus};
+    use evm_loader::types::Address;
+    use serde_json::json;
+
+    use super::{decode_revert_reason, CallTracer};
+
+    const A: Address = Address([0xaa; 20]);
+    const B: Address = Address([0xbb; 20]);
+    const C: Address = Address([0xcc; 20]);
+    const D: Address = Address([0xdd; 20]);
+
+    /// ABI encoded `Error(string)`
+    fn revert_data(message: &str) -> Vec<u8> {
+        let mut data = vec![0x08, 0xc3, 0x79, 0xa0];
+        data.extend_from_slice(&U256::new(0x20).to_be_bytes());
+        data.extend_from_slice(&U256::new(message.len() as u128).to_be_bytes());
+        data.extend_from_slice(message.as_bytes());
+        data.resize(data.len() + (32 - message.len() % 32) % 32, 0);
+        data
+    }
+
+    fn begin(tracer: &mut CallTracer, call_type: CallType, context: Context, gas: u64) {
+        tracer.event(Event::BeginVM {
+            context,
+            call_type,
+            code: &[0x00],
+            input: &[0x01, 0x02],
+            gas,
+        });
+    }
+
+    fn log(tracer: &mut CallTracer, address: Address, topic: u8) {
+        tracer.event(Event::Log {
+            address,
+            topics: &[[topic; 32]],
+            data: &[topic],
+        });
+    }
+
+    #[test]
+    fn nested_frames() {
+        let mut tracer = CallTracer::new(true);
+
+        let context = Context {
+            caller: A,
+            contract: B,
+            value: U256::new(5),
+            code_address: None,
+        };
+        begin(&mut tracer, CallType::Call, context, 100_000);
+        log(&mut tracer, B, 1);
+
+        // Delegate call keeps the caller of the parent frame, Geth reports the parent as `from`
+        let context = Context {
+            caller: A,
+            contract: B,
+            value: U256::new(5),
+            code_address: Some(C),
+        };
+        begin(&mut tracer, CallType::DelegateCall, context, 50_000);
+        log(&mut tracer, B, 2);
+        tracer.event(Event::EndVM {
+            status: ExitStatus::Return(vec![0x42]),
+            gas_used: 100,
+        });
+
+        // Logs of the reverted frame are dropped
+        let context = Context {
+            caller: B,
+            contract: D,
+            value: U256::ZERO,
+            code_address: None,
+        };
+        begin(&mut tracer, CallType::Call, context, 40_000);
+        log(&mut tracer, D, 3);
+        tracer.event(Event::EndVM {
+            status: ExitStatus::Revert(revert_data("nope")),
+            gas_used: 200,
+        });
+
+        tracer.event(Event::EndVM {
+            status: ExitStatus::Stop,
+            gas_used: 1000,
+        });
+
+        let frame = tracer.into_call_frame();
+        assert_eq!(
+            serde_json::to_value(frame).unwrap(),
+            json!({
+                "type": "CALL",
+                "from": A.to_string(),
+                "to": B.to_string(),
+                "value": "0x5",
+                "gas": "0x186a0",
+                "gasUsed": "0x3e8",
+                "input": "0x0102",
+                "logs": [{
+                    "address": B.to_string(),
+                    "topics": [format!("0x{}", "01".repeat(32))],
+                    "data": "0x01",
+                    "position": "0x0",
+                }],
+                "calls": [
+                    {
+                        "type": "DELEGATECALL",
+                        "from": B.to_string(),
+                        "to": C.to_string(),
+                        "gas": "0xc350",
+                        "gasUsed": "0x64",
+                        "input": "0x0102",
+                        "output": "0x42",
+                        "logs": [{
+                            "address": B.to_string(),
+                            "topics": [format!("0x{}", "02".repeat(32))],
+                            "data": "0x02",
+                            "position": "0x0",
+                        }],
+                    },
+                    {
+                        "type": "CALL",
+                        "from": B.to_string(),
+                        "to": D.to_string(),
+                        "value": "0x0",
+                        "gas": "0x9c40",
+                        "gasUsed": "0xc8",
+                        "input": "0x0102",
+                        "output": format!("0x{}", hex::encode(revert_data("nope"))),
+                        "error": "execution reverted",
+                        "revertReason": "nope",
+                    },
+                ],
+            })
+        );
+    }
+
+    #[test]
+    fn halt_reverts_nested_logs() {
+        let mut tracer = CallTracer::new(true);
+
+        let context = Context {
+            caller: A,
+            contract: B,
+            value: U256::ZERO,
+            code_address: None,
+        };
+        begin(&mut tracer, CallType::Create, context, 1000);
+
+        let context = Context {
+            caller: B,
+            contract: C,
+            value: U256::ZERO,
+            code_address: None,
+        };
+        begin(&mut tracer, CallType::StaticCall, context, 500);
+        log(&mut tracer, C, 1);
+        tracer.event(Event::EndVM {
+            status: ExitStatus::Stop,
+            gas_used: 10,
+        });
+
+        tracer.event(Event::StepError {
+            message: "EVM Stack Underflow".to_string(),
+            reason: Some("stack underflow"),
+        });
+        tracer.event(Event::EndVM {
+            status: ExitStatus::Revert(Vec::new()),
+            gas_used: 1000,
+        });
+
+        let frame = tracer.into_call_frame();
+        assert_eq!(
+            serde_json::to_value(frame).unwrap(),
+            json!({
+                "type": "CREATE",
+                "from": A.to_string(),
+                "to": B.to_string(),
+                "value": "0x0",
+                "gas": "0x3e8",
+                "gasUsed": "0x3e8",
+                "input": "0x00",
+                "error": "stack underflow",
+                "calls": [{
+                    "type": "STATICCALL",
+                    "from": B.to_string(),
+                    "to": C.to_string(),
+                    "gas": "0x1f4",
+                    "gasUsed": "0xa",
+                    "input": "0x0102",
+                }],
+            })
+        );
+    }
+
+    #[test]
+    fn revert_reason_bounds() {
+        assert_eq!(
+            decode_revert_reason(&revert_data("nope")),
+            Some("nope".to_string())
+        );
+        assert_eq!(decode_revert_reason(&revert_data("")), Some(String::new()));
+
+        // Unknown selector
+        let mut data = revert_data("nope");
+        data[0] = 0;
+        assert_eq!(decode_revert_reason(&data), None);
+
+        // Too short for the offset and the length
+        assert_eq!(decode_revert_reason(&revert_data("nope")[..67]), None);
+
+        // Length past the end of the data
+        let mut data = revert_data("nope");
+        data[67] = 33;
+        assert_eq!(decode_revert_reason(&data), None);
+
+        // Length does not fit usize
+        let mut data = revert_data("nope");
+        data[36..68].copy_from_slice(&[0xff; 32]);
+        assert_eq!(decode_revert_reason(&data), None);
+
+        // Not UTF-8
+        let mut data = revert_data("nope");
+        data[68] = 0xff;
+        assert_eq!(decode_revert_reason(&data), None);
+    }
+}
@@ -0,0 +1,446 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io::{BufRead, Write};
+
+use ethnum::U256;
+use evm_loader::evm::tracing::{CallType, Event, EventListener};
+use evm_loader::types::Address;
+
+use super::opcode_names::opcode_name;
+
+const SSTORE: u8 = 0x55;
+
+const HELP: &str = "\
+Commands:
+  s, step [N]          execute N steps, 1 by default
+  c, continue          run to the next breakpoint
+  r, run               run to the end ignoring the breakpoints
+  b pc <PC>            break at the pc in any contract
+  b op <NAME|0xNN>     break at the opcode
+  b addr <ADDRESS>     break on entering the contract
+  b sstore [INDEX]     break after a write to the storage slot, any slot by default
+  breakpoints          list the breakpoints
+  delete <N>           delete the breakpoint by number
+  stack                print the stack, the top item first
+  memory [OFFSET LEN]  print the memory, all by default
+  storage              print the storage slots accessed in the current contract
+  frames               print the call frames, the current frame last
+  h, help              print this help";
+
+/// Condition to stop the execution
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Breakpoint {
+    Pc(usize),
+    Opcode(u8),
+    /// Entering the contract
+    Address(Address),
+    /// Write to the storage slot, any slot if `None`
+    StorageWrite(Option<U256>),
+}
+
+impl std::fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Pc(pc) => write!(f, "pc {pc:#x}"),
+            Self::Opcode(opcode) => write!(f, "opcode {}", opcode_name(*opcode)),
+            Self::Address(address) => write!(f, "address {address}"),
+            Self::StorageWrite(Some(index)) => write!(f, "sstore 0x{index:x}"),
+            Self::StorageWrite(None) => write!(f, "sstore"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    /// Stop after the number of steps
+    Step(u64),
+    /// Stop at the breakpoints
+    Continue,
+    /// Run to the end
+    Run,
+}
+
+struct Frame {
+    call_type: CallType,
+    contract: Address,
+    code_address: Option<Address>,
+    pc: usize,
+}
+
+/// SSTORE step, copied to stop after the write
+struct WriteStep {
+    pc: usize,
+    /// Stack after the SSTORE popped the index and the value
+    stack: Vec<[u8; 32]>,
+    memory: Vec<u8>,
+    /// Breakpoint hit by the write
+    reason: Option<String>,
+}
+
+/// Step the execution is stopped at
+struct Stop<'a> {
+    opcode: u8,
+    pc: usize,
+    stack: &'a [[u8; 32]],
+    memory: &'a [u8],
+}
+
+/// Stops the emulation at the breakpoints and reads the commands from the input.
+/// The end of the input runs the emulation to the end.
+pub struct Debugger<R: BufRead, W: Write> {
+    input: R,
+    output: W,
+    mode: Mode,
+    breakpoints: Vec<Breakpoint>,
+    frames: Vec<Frame>,
+    /// Storage slots accessed so far, by contract
+    storage: HashMap<Address, BTreeMap<U256, [u8; 32]>>,
+    /// Stop at the next step, with the reason
+    pending_stop: Option<String>,
+    /// Current SSTORE step while there are storage breakpoints
+    write_step: Option<WriteStep>,
+    steps: u64,
+}
+
+impl<R: BufRead, W: Write> Debugger<R, W> {
+    /// Stops at the first step
+    pub fn new(input: R, output: W) -> Self {
+        Self {
+            input,
+            output,
+            mode: Mode::Step(1),
+            breakpoints: Vec::new(),
+            frames: Vec::new(),
+            storage: HashMap::new(),
+            pending_stop: None,
+            write_step: None,
+            steps: 0,
+        }
+    }
+
+    fn print(&mut self, text: &str) {
+        // Output errors do not interrupt the emulation
+        let _ = writeln!(self.output, "{text}");
+    }
+
+    /// Reason to stop at the step, breakpoints also interrupt the stepping
+    fn should_stop(&mut self, opcode: u8, pc: usize) -> Option<String> {
+        if self.mode == Mode::Run {
+            return None;
+        }
+
+        let breakpoint = self.pending_stop.take().or_else(|| {
+            self.breakpoints
+                .iter()
+                .find(|breakpoint| match breakpoint {
+                    Breakpoint::Pc(value) => *value == pc,
+                    Breakpoint::Opcode(value) => *value == opcode,
+                    Breakpoint::Address(_) | Breakpoint::StorageWrite(_) => false,
+                })
+                .map(ToString::to_string)
+        });
+
+        if let Mode::Step(remaining) = self.mode {
+            let remaining = remaining.saturating_sub(1);
+            self.mode = Mode::Step(remaining);
+            if remaining == 0 {
+                return breakpoint.or_else(|| Some("step".to_string()));
+            }
+        }
+
+        breakpoint
+    }
+
+    fn stop(&mut self, reason: &str, step: &Stop) {
+        let location = format!(
+            "Stopped at {reason}: step {} depth {} pc {:#x} {}",
+            self.steps,
+            self.frames.len(),
+            step.pc,
+            opcode_name(step.opcode)
+        );
+        self.print(&location);
+
+        loop {
+            let _ = write!(self.output, "(debug) ");
+            let _ = self.output.flush();
+
+            let mut line = String::new();
+            match self.input.read_line(&mut line) {
+                Ok(0) | Err(_) => {
+                    self.mode = Mode::Run;
+                    return;
+                }
+                Ok(_) => {}
+            }
+
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words.as_slice() {
+                [] => {}
+                ["s" | "step"] => {
+                    self.mode = Mode::Step(1);
+                    return;
+                }
+                ["s" | "step", count] => match count.parse::<u64>() {
+                    Ok(count) if count > 0 => {
+                        self.mode = Mode::Step(count);
+                        return;
+                    }
+                    _ => self.print("Invalid number of steps"),
+                },
+                ["c" | "continue"] => {
+                    self.mode = Mode::Continue;
+                    return;
+                }
+                ["r" | "run"] => {
+                    self.mode = Mode::Run;
+                    return;
+                }
+                ["b" | "break", args @ ..] => match parse_breakpoint(args) {
+                    Ok(breakpoint) => {
+                        self.breakpoints.push(breakpoint);
+                        let text = format!("Breakpoint {}: {breakpoint}", self.breakpoints.len());
+                        self.print(&text);
+                    }
+                    Err(e) => self.print(&e),
+                },
+                ["breakpoints"] => {
+                    let list = self
+                        .breakpoints
+                        .iter()
+                        .enumerate()
+                        .map(|(i, breakpoint)| format!("{}: {breakpoint}", i + 1))
+                        .collect::<Vec<_>>()
+                        .join("\n");
+                    self.print(&list);
+                }
+                ["delete", number] => match number.parse::<usize>() {
+                    Ok(number) if (1..=self.breakpoints.len()).contains(&number) => {
+                        self.breakpoints.remove(number - 1);
+                    }
+                    _ => self.print("Invalid breakpoint number"),
+                },
+                ["stack"] => {
+                    let stack = step
+                        .stack
+                        .iter()
+                        .rev()
+                        .enumerate()
+                        .map(|(i, word)| format!("{i:>4}: 0x{}", hex::encode(word)))
+                        .collect::<Vec<_>>()
+                        .join("\n");
+                    self.print(&stack);
+                }
+                ["memory"] => {
+                    let memory = dump_memory(step.memory, 0);
+                    self.print(&memory);
+                }
+                ["memory", offset, length] => {
+                    let range = parse_usize(offset).and_then(|offset| {
+                        let end = offset.checked_add(parse_usize(length)?)?;
+                        step.memory.get(offset..end).map(|data| (offset, data))
+                    });
+                    match range {
+                        Some((offset, data)) => {
+                            let memory = dump_memory(data, offset);
+                            self.print(&memory);
+                        }
+                        None => self.print("Invalid memory range"),
+                    }
+                }
+                ["storage"] => {
+                    let storage = self
+                        .frames
+                        .last()
+                        .and_then(|frame| self.storage.get(&frame.contract))
+                        .map(|slots| {
+                            slots
+                                .iter()
+                                .map(|(index, value)| {
+                                    format!("0x{index:x}: 0x{}", hex::encode(value))
+                                })
+                                .collect::<Vec<_>>()
+                                .join("\n")
+                        })
+                        .unwrap_or_default();
+                    self.print(&storage);
+                }
+                ["frames"] => {
+                    let frames = self
+                        .frames
+                        .iter()
+                        .enumerate()
+                        .map(|(i, frame)| {
+                            let code = frame
+                                .code_address
+                                .filter(|address| *address != frame.contract)
+                                .map(|address| format!(" code {address}"))
+                                .unwrap_or_default();
+                            format!(
+                                "{}: {} {}{code} pc {:#x}",
+                                i + 1,
+                                frame.call_type.name(),
+                                frame.contract,
+                                frame.pc
+                            )
+                        })
+                        .collect::<Vec<_>>()
+                        .join("\n");
+                    self.print(&frames);
+                }
+                ["h" | "help"] => self.print(HELP),
+                _ => self.print("Unknown command, type `help` for the list of commands"),
+            }
+        }
+    }
+}
+
+fn parse_usize(value: &str) -> Option<usize> {
+    match value.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16).ok(),
+        None => value.parse().ok(),
+    }
+}
+
+fn parse_breakpoint(args: &[&str]) -> Result<Breakpoint, String> {
+    match args {
+        ["pc", pc] => parse_usize(pc)
+            .map(Breakpoint::Pc)
+            .ok_or_else(|| format!("Invalid pc {pc}")),
+        ["op", opcode] => {
+            let name = opcode.to_uppercase();
+            let by_name = (0..=u8::MAX).find(|opcode| opcode_name(*opcode) == name);
+            let by_value = opcode
+                .strip_prefix("0x")
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+
+            by_value
+                .or(by_name)
+                .map(Breakpoint::Opcode)
+                .ok_or_else(|| format!("Invalid opcode {opcode}"))
+        }
+        ["addr", address] => Address::from_hex(address)
+            .map(Breakpoint::Address)
+            .map_err(|e| e.to_string()),
+        ["sstore"] => Ok(Breakpoint::StorageWrite(None)),
+        ["sstore", index] => U256::from_str_prefixed(index)
+            .map(|index| Breakpoint::StorageWrite(Some(index)))
+            .map_err(|e| e.to_string()),
+        _ => Err(
+            "Usage: b pc <PC> | b op <NAME|0xNN> | b addr <ADDRESS> | b sstore [INDEX]".to_string(),
+        ),
+    }
+}
+
+/// Memory as 32 bytes rows
+fn dump_memory(data: &[u8], offset: usize) -> String {
+    data.chunks(32)
+        .enumerate()
+        .map(|(i, chunk)| format!("{:#06x}: {}", offset + i * 32, hex::encode(chunk)))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+impl<R: BufRead, W: Write> EventListener for Debugger<R, W> {
+    fn event(&mut self, event: Event) {
+        match event {
+            Event::BeginVM {
+                context, call_type, ..
+            } => {
+                self.frames.push(Frame {
+                    call_type,
+                    contract: context.contract,
+                    code_address: context.code_address,
+                    pc: 0,
+                });
+
+                let breakpoint = self.breakpoints.iter().find(|breakpoint| {
+                    **breakpoint == Breakpoint::Address(context.contract)
+                        || context.code_address.map_or(false, |address| {
+                            **breakpoint == Breakpoint::Address(address)
+                        })
+                });
+                if let Some(breakpoint) = breakpoint {
+                    self.pending_stop = Some(breakpoint.to_string());
+                }
+            }
+            Event::EndVM { .. } => {
+                self.frames.pop();
+            }
+            Event::BeginStep {
+                opcode,
+                pc,
+                stack,
+                memory,
+                ..
+            } => {
+                self.steps = self.steps.saturating_add(1);
+                if let Some(frame) = self.frames.last_mut() {
+                    frame.pc = pc;
+                }
+
+                let watch_storage = self
+                    .breakpoints
+                    .iter()
+                    .any(|breakpoint| matches!(breakpoint, Breakpoint::StorageWrite(_)));
+                self.write_step = (opcode == SSTORE && watch_storage).then(|| WriteStep {
+                    pc,
+                    stack: stack[..stack.len().saturating_sub(2)].to_vec(),
+                    memory: memory.to_vec(),
+                    reason: None,
+                });
+
+                if let Some(reason) = self.should_stop(opcode, pc) {
+                    let step = Stop {
+                        opcode,
+                        pc,
+                        stack,
+                        memory,
+                    };
+                    self.stop(&reason, &step);
+                }
+            }
+            Event::StorageAccess { index, value } => {
+                if let Some(frame) = self.frames.last() {
+                    let slots = self.storage.entry(frame.contract).or_default();
+                    slots.insert(index, value);
+                }
+            }
+            Event::StorageSet { index, value } => {
+                if let Some(frame) = self.frames.last() {
+                    let slots = self.storage.entry(frame.contract).or_default();
+                    slots.insert(index, value);
+                }
+
+                let breakpoint = self.breakpoints.iter().find(|breakpoint| {
+                    matches!(breakpoint, Breakpoint::StorageWrite(slot) if slot.map_or(true, |slot| slot == index))
+                });
+                let reason = breakpoint.map(|breakpoint| {
+                    format!("{breakpoint}, 0x{index:x} = 0x{}", hex::encode(value))
+                });
+                if let Some(write_step) = &mut self.write_step {
+                    write_step.reason = reason;
+                }
+            }
+            Event::EndStep { .. } => {
+                if let Some(WriteStep {
+                    pc,
+                    stack,
+                    memory,
+                    reason: Some(reason),
+                }) = self.write_step.take()
+                {
+                    if self.mode != Mode::Run {
+                        let stop = Stop {
+                            opcode: SSTORE,
+                            pc,
+                            stack: &stack,
+                            memory: &memory,
+                        };
+                        self.stop(&reason, &stop);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
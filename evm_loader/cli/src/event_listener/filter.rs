@@ -0,0 +1,118 @@
+use evm_loader::evm::tracing::{Event, EventListener};
+use evm_loader::types::Address;
+
+use crate::commands::trace::TraceFilter;
+
+/// CREATE, CALL, CALLCODE, DELEGATECALL, CREATE2 and STATICCALL
+const FRAME_OPCODES: [u8; 6] = [0xF0, 0xF1, 0xF2, 0xF4, 0xF5, 0xFA];
+
+struct Frame {
+    contract: Address,
+    code_address: Option<Address>,
+    /// Current step of the frame passed the filter
+    matched: bool,
+}
+
+/// Forwards the steps that pass the filter to the inner listener.
+/// Frame events and the steps that open a frame are always forwarded to keep the frames
+/// of the listener consistent, a traced frame always has its calling step traced.
+pub struct FilteredListener<L: EventListener> {
+    filter: TraceFilter,
+    inner: L,
+    frames: Vec<Frame>,
+    /// Number of the steps started so far
+    steps: u64,
+}
+
+impl<L: EventListener> FilteredListener<L> {
+    #[must_use]
+    pub fn new(filter: TraceFilter, inner: L) -> Self {
+        Self {
+            filter,
+            inner,
+            frames: Vec::new(),
+            steps: 0,
+        }
+    }
+
+    #[must_use]
+    pub fn into_inner(self) -> L {
+        self.inner
+    }
+
+    fn matches(&self, step: u64, pc: usize) -> bool {
+        let filter = &self.filter;
+        let depth = self.frames.len();
+
+        if !filter.addresses.is_empty() {
+            let matches_address = self.frames.last().map_or(false, |frame| {
+                filter.addresses.contains(&frame.contract)
+                    || frame
+                        .code_address
+                        .map_or(false, |address| filter.addresses.contains(&address))
+            });
+            if !matches_address {
+                return false;
+            }
+        }
+
+        filter.min_depth.map_or(true, |min| depth >= min)
+            && filter.max_depth.map_or(true, |max| depth <= max)
+            && filter.min_pc.map_or(true, |min| pc >= min)
+            && filter.max_pc.map_or(true, |max| pc <= max)
+            && filter.first_step.map_or(true, |first| step >= first)
+            && filter.last_step.map_or(true, |last| step <= last)
+    }
+
+    fn step_matched(&self) -> bool {
+        self.frames.last().map_or(false, |frame| frame.matched)
+    }
+}
+
+impl<L: EventListener> EventListener for FilteredListener<L> {
+    fn event(&mut self, event: Event) {
+        match &event {
+            Event::BeginVM { context, .. } => {
+                self.frames.push(Frame {
+                    contract: context.contract,
+                    code_address: context.code_address,
+                    matched: false,
+                });
+                self.inner.event(event);
+            }
+            Event::EndVM { .. } => {
+                self.frames.pop();
+                self.inner.event(event);
+            }
+            Event::BeginStep { opcode, pc, .. } => {
+                let step = self.steps;
+                self.steps = self.steps.saturating_add(1);
+
+                let matched = FRAME_OPCODES.contains(opcode) || self.matches(step, *pc);
+                if let Some(frame) = self.frames.last_mut() {
+                    frame.matched = matched;
+                }
+
+                if matched {
+                    self.inner.event(event);
+                }
+            }
+            Event::EndStep { .. }
+            | Event::StepError { .. }
+            | Event::StackPush { .. }
+            | Event::MemorySet { .. }
+            | Event::StorageSet { .. }
+            | Event::StorageAccess { .. } => {
+                if self.step_matched() {
+                    self.inner.event(event);
+                }
+            }
+            Event::Log { .. }
+            | Event::SelfDestruct { .. }
+            | Event::PrecompileCall { .. }
+            | Event::ExternalInstruction { .. } => {
+                self.inner.event(event);
+            }
+        }
+    }
+}
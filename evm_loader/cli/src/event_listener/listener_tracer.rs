@@ -1,27 +1,38 @@
+use std::collections::HashMap;
+
 use super::tracer::Tracer;
 use crate::types::trace::FullTraceData;
 
 pub trait ListenerTracer {
-    fn begin_step(&mut self, stack: Vec<[u8; 32]>, memory: Vec<u8>);
+    fn begin_step(&mut self, stack: &[[u8; 32]], memory: &[u8]);
     fn end_step(&mut self);
 }
 
 impl ListenerTracer for Tracer {
-    fn begin_step(&mut self, stack: Vec<[u8; 32]>, memory: Vec<u8>) {
-        let storage = self
-            .data
-            .last()
-            .map(|d| d.storage.clone())
-            .unwrap_or_default();
+    fn begin_step(&mut self, stack: &[[u8; 32]], memory: &[u8]) {
+        let stack = if self.options.disable_stack {
+            Vec::new()
+        } else {
+            stack.to_vec()
+        };
+        let memory = if self.options.disable_memory {
+            Vec::new()
+        } else {
+            memory.to_vec()
+        };
 
         self.data.push(FullTraceData {
             stack,
             memory,
-            storage,
+            storage: HashMap::new(),
         });
     }
 
     fn end_step(&mut self) {
+        if self.options.disable_storage {
+            return;
+        }
+
         if let Some((index, value)) = self.vm.step_diff().storage_access {
             let data = self.data.last_mut().expect("data was pushed in begin_step");
             data.storage.insert(index, value);
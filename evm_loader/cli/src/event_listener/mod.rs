@@ -1,5 +1,13 @@
+pub mod action_tracer;
+pub mod call_tracer;
+pub mod debugger;
+pub mod filter;
 mod listener_tracer;
 mod listener_vm_tracer;
+mod opcode_names;
+pub mod prestate_tracer;
+pub mod stream_tracer;
+pub mod struct_logger;
 pub mod tracer;
 mod vm_tracer;
 
@@ -9,10 +17,10 @@ use {listener_tracer::ListenerTracer, listener_vm_tracer::ListenerVmTracer, trac
 impl EventListener for Tracer {
     fn event(&mut self, event: Event) {
         match event {
-            Event::BeginVM { context, code } => {
-                self.vm.begin_vm(context, code);
+            Event::BeginVM { context, code, .. } => {
+                self.vm.begin_vm(context, code.to_vec());
             }
-            Event::EndVM { status } => {
+            Event::EndVM { status, .. } => {
                 self.vm.end_vm(status);
             }
             Event::BeginStep {
@@ -20,6 +28,7 @@ impl EventListener for Tracer {
                 pc,
                 stack,
                 memory,
+                ..
             } => {
                 self.begin_step(stack, memory);
                 self.vm.begin_step(opcode, pc);
@@ -32,7 +41,7 @@ impl EventListener for Tracer {
                 self.vm.stack_push(value);
             }
             Event::MemorySet { offset, data } => {
-                self.vm.memory_set(offset, data);
+                self.vm.memory_set(offset, data.to_vec());
             }
             Event::StorageSet { index, value } => {
                 self.vm.storage_set(index, value);
@@ -40,6 +49,11 @@ impl EventListener for Tracer {
             Event::StorageAccess { index, value } => {
                 self.vm.storage_access(index, value);
             }
+            Event::StepError { .. }
+            | Event::Log { .. }
+            | Event::SelfDestruct { .. }
+            | Event::PrecompileCall { .. }
+            | Event::ExternalInstruction { .. } => {}
         };
     }
 }
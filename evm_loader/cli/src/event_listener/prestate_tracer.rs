@@ -0,0 +1,501 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use ethnum::U256;
+use evm_loader::{account_storage::AccountStorage, executor::Action, types::Address};
+use serde::Serialize;
+
+use crate::account_storage::EmulatorAccountStorage;
+use crate::types::trace::{AccountDiff, Diff};
+
+/// Account in the Geth `prestateTracer` format
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct AccountState {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub balance: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub storage: BTreeMap<String, String>,
+}
+
+/// Result of the prestate tracer in the diff mode
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct StateDiff {
+    pub pre: BTreeMap<Address, AccountState>,
+    pub post: BTreeMap<Address, AccountState>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct Account {
+    balance: U256,
+    nonce: u64,
+    code: Vec<u8>,
+    storage: BTreeMap<U256, [u8; 32]>,
+}
+
+impl Account {
+    fn load(storage: &EmulatorAccountStorage, address: &Address) -> Self {
+        Self {
+            balance: storage.balance(address),
+            nonce: storage.nonce(address),
+            code: storage.code(address).to_vec(),
+            storage: BTreeMap::new(),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        (self.balance == U256::ZERO)
+            && (self.nonce == 0)
+            && self.code.is_empty()
+            && self.storage.values().all(|value| *value == [0_u8; 32])
+    }
+
+    /// Zero nonce and empty code are omitted
+    fn to_state<'a>(&self, slots: impl Iterator<Item = &'a U256>) -> AccountState {
+        AccountState {
+            balance: Some(format!("0x{:x}", self.balance)),
+            nonce: if self.nonce == 0 {
+                None
+            } else {
+                Some(self.nonce)
+            },
+            code: if self.code.is_empty() {
+                None
+            } else {
+                Some(format!("0x{}", hex::encode(&self.code)))
+            },
+            storage: slots
+                .filter_map(|index| self.storage.get_key_value(index))
+                .map(storage_entry)
+                .collect(),
+        }
+    }
+
+    /// Fields that differ from `pre`
+    fn diff(&self, pre: &Self) -> AccountState {
+        AccountState {
+            balance: if self.balance == pre.balance {
+                None
+            } else {
+                Some(format!("0x{:x}", self.balance))
+            },
+            nonce: if self.nonce == pre.nonce {
+                None
+            } else {
+                Some(self.nonce)
+            },
+            code: if self.code == pre.code {
+                None
+            } else {
+                Some(format!("0x{}", hex::encode(&self.code)))
+            },
+            storage: self
+                .storage
+                .iter()
+                .filter(|(index, value)| pre.storage.get(*index) != Some(*value))
+                .map(storage_entry)
+                .collect(),
+        }
+    }
+
+    /// OpenEthereum account diff, `pre` or `post` is `None` if the account does not exist
+    fn account_diff(pre: Option<&Self>, post: Option<&Self>) -> AccountDiff {
+        let fields = |account: &Self| {
+            (
+                format!("0x{:x}", account.balance),
+                format!("{:#x}", account.nonce),
+                format!("0x{}", hex::encode(&account.code)),
+            )
+        };
+
+        let (balance, nonce, code) = match (pre.map(fields), post.map(fields)) {
+            (Some(pre), Some(post)) => (
+                Diff::new(pre.0, post.0),
+                Diff::new(pre.1, post.1),
+                Diff::new(pre.2, post.2),
+            ),
+            (None, Some(post)) => (Diff::Born(post.0), Diff::Born(post.1), Diff::Born(post.2)),
+            (Some(pre), None) => (Diff::Died(pre.0), Diff::Died(pre.1), Diff::Died(pre.2)),
+            (None, None) => (Diff::Same, Diff::Same, Diff::Same),
+        };
+
+        let empty = BTreeMap::new();
+        let pre_storage = pre.map_or(&empty, |account| &account.storage);
+        let post_storage = post.map_or(&empty, |account| &account.storage);
+
+        let storage = pre_storage
+            .keys()
+            .chain(post_storage.keys())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .filter_map(|index| {
+                let diff = match (pre_storage.get(index), post_storage.get(index)) {
+                    (Some(from), Some(to)) if from == to => return None,
+                    (Some(from), Some(to)) => Diff::new(hex_value(from), hex_value(to)),
+                    (None, Some(to)) => Diff::Born(hex_value(to)),
+                    (Some(from), None) => Diff::Died(hex_value(from)),
+                    (None, None) => return None,
+                };
+                Some((format!("0x{}", hex::encode(index.to_be_bytes())), diff))
+            })
+            .collect();
+
+        AccountDiff {
+            balance,
+            nonce,
+            code,
+            storage,
+        }
+    }
+}
+
+fn hex_value(value: &[u8; 32]) -> String {
+    format!("0x{}", hex::encode(value))
+}
+
+fn storage_entry((index, value): (&U256, &[u8; 32])) -> (String, String) {
+    (
+        format!("0x{}", hex::encode(index.to_be_bytes())),
+        format!("0x{}", hex::encode(value)),
+    )
+}
+
+/// Builds the state of the touched accounts before and after the transaction.
+/// Pre state is read from the emulator storage, post state is the pre state with the actions applied.
+pub struct PrestateTracer {
+    pre: BTreeMap<Address, Account>,
+    post: BTreeMap<Address, Account>,
+    destroyed: BTreeSet<Address>,
+}
+
+impl PrestateTracer {
+    #[must_use]
+    pub fn new(storage: &EmulatorAccountStorage, actions: &[Action]) -> Self {
+        let mut tracer = Self {
+            pre: BTreeMap::new(),
+            post: BTreeMap::new(),
+            destroyed: BTreeSet::new(),
+        };
+
+        let addresses: Vec<Address> = storage.accounts.borrow().keys().copied().collect();
+        for address in &addresses {
+            tracer.account(storage, address);
+        }
+
+        let slots: Vec<_> = storage
+            .storage_slots
+            .borrow()
+            .iter()
+            .map(|(&key, &value)| (key, value))
+            .collect();
+        for ((address, index), value) in slots {
+            tracer.account(storage, &address);
+            tracer
+                .pre
+                .get_mut(&address)
+                .unwrap()
+                .storage
+                .insert(index, value);
+            tracer
+                .post
+                .get_mut(&address)
+                .unwrap()
+                .storage
+                .insert(index, value);
+        }
+
+        for action in actions {
+            tracer.apply(storage, action);
+        }
+
+        tracer
+    }
+
+    /// Post state of the account, loads the pre state on the first access
+    fn account(&mut self, storage: &EmulatorAccountStorage, address: &Address) -> &mut Account {
+        let pre = self
+            .pre
+            .entry(*address)
+            .or_insert_with(|| Account::load(storage, address));
+
+        self.post.entry(*address).or_insert_with(|| pre.clone())
+    }
+
+    fn apply(&mut self, storage: &EmulatorAccountStorage, action: &Action) {
+        match action {
+            Action::NeonTransfer {
+                source,
+                target,
+                value,
+            } => {
+                let source = self.account(storage, source);
+                source.balance = source.balance.saturating_sub(*value);
+
+                let target = self.account(storage, target);
+                target.balance = target.balance.saturating_add(*value);
+            }
+            Action::NeonWithdraw { source, value } => {
+                let source = self.account(storage, source);
+                source.balance = source.balance.saturating_sub(*value);
+            }
+            Action::EvmIncrementNonce { address } => {
+                let account = self.account(storage, address);
+                account.nonce = account.nonce.saturating_add(1);
+            }
+            Action::EvmSetCode { address, code } => {
+                self.account(storage, address).code = code.to_vec();
+            }
+            Action::EvmSetStorage {
+                address,
+                index,
+                value,
+            } => {
+                self.account(storage, address)
+                    .storage
+                    .insert(*index, *value);
+            }
+            Action::EvmSelfDestruct { address } => {
+                self.account(storage, address);
+                self.destroyed.insert(*address);
+            }
+            Action::EvmLog { .. } | Action::ExternalInstruction { .. } => {}
+        }
+    }
+
+    /// State of every touched account before the transaction
+    #[must_use]
+    pub fn into_prestate(self) -> BTreeMap<Address, AccountState> {
+        self.pre
+            .iter()
+            .map(|(address, account)| (*address, account.to_state(account.storage.keys())))
+            .collect()
+    }
+
+    /// Modified accounts: complete state before the transaction and changed fields after it
+    #[must_use]
+    pub fn into_diff(self) -> StateDiff {
+        let mut diff = StateDiff::default();
+
+        for (address, pre) in &self.pre {
+            let post = &self.post[address];
+            let destroyed = self.destroyed.contains(address);
+            if (pre == post) && !destroyed {
+                continue;
+            }
+
+            if !pre.is_empty() {
+                let changed = pre
+                    .storage
+                    .iter()
+                    .filter(|(index, value)| post.storage.get(*index) != Some(*value))
+                    .map(|(index, _)| index);
+                diff.pre.insert(*address, pre.to_state(changed));
+            }
+
+            if !destroyed {
+                diff.post.insert(*address, post.diff(pre));
+            }
+        }
+
+        diff
+    }
+
+    /// OpenEthereum `stateDiff` of the modified accounts
+    #[must_use]
+    pub fn into_state_diff(self) -> BTreeMap<Address, AccountDiff> {
+        let mut diff = BTreeMap::new();
+
+        for (address, pre) in &self.pre {
+            let post = &self.post[address];
+            let destroyed = self.destroyed.contains(address);
+            if (pre == post) && !destroyed {
+                continue;
+            }
+
+            let pre = Some(pre).filter(|account| !account.is_empty());
+            let post = Some(post).filter(|account| !destroyed && !account.is_empty());
+            if pre.is_none() && post.is_none() {
+                continue;
+            }
+
+            diff.insert(*address, Account::account_diff(pre, post));
+        }
+
+        diff
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, BTreeSet};
+
+    use ethnum::U256;
+    use evm_loader::types::Address;
+    use serde_json::json;
+
+    use super::{Account, PrestateTracer};
+
+    const SENDER: Address = Address([0xaa; 20]);
+    const CONTRACT: Address = Address([0xbb; 20]);
+    const CREATED: Address = Address([0xcc; 20]);
+    const UNTOUCHED: Address = Address([0xdd; 20]);
+    const DESTROYED: Address = Address([0xee; 20]);
+
+    fn slot(index: u8) -> String {
+        format!("0x{}", hex::encode(U256::from(index).to_be_bytes()))
+    }
+
+    fn value(byte: u8) -> String {
+        format!("0x{}", hex::encode([byte; 32]))
+    }
+
+    /// Sender pays 10 to the contract, the contract changes slot 2, creates an account
+    /// and destroys another one
+    fn tracer() -> PrestateTracer {
+        let sender = Account {
+            balance: U256::new(100),
+            nonce: 1,
+            ..Account::default()
+        };
+        let contract = Account {
+            nonce: 1,
+            code: vec![0x60],
+            storage: BTreeMap::from([(U256::new(1), [1; 32]), (U256::new(2), [2; 32])]),
+            ..Account::default()
+        };
+        let untouched = Account {
+            balance: U256::new(5),
+            ..Account::default()
+        };
+        let destroyed = Account {
+            balance: U256::new(7),
+            ..Account::default()
+        };
+
+        let pre = BTreeMap::from([
+            (SENDER, sender.clone()),
+            (CONTRACT, contract.clone()),
+            (CREATED, Account::default()),
+            (UNTOUCHED, untouched.clone()),
+            (DESTROYED, destroyed.clone()),
+        ]);
+
+        let post = BTreeMap::from([
+            (
+                SENDER,
+                Account {
+                    balance: U256::new(90),
+                    nonce: 2,
+                    ..sender
+                },
+            ),
+            (
+                CONTRACT,
+                Account {
+                    balance: U256::new(10),
+                    storage: BTreeMap::from([(U256::new(1), [1; 32]), (U256::new(2), [3; 32])]),
+                    ..contract
+                },
+            ),
+            (
+                CREATED,
+                Account {
+                    nonce: 1,
+                    code: vec![0x00],
+                    ..Account::default()
+                },
+            ),
+            (UNTOUCHED, untouched),
+            (DESTROYED, destroyed),
+        ]);
+
+        PrestateTracer {
+            pre,
+            post,
+            destroyed: BTreeSet::from([DESTROYED]),
+        }
+    }
+
+    #[test]
+    fn prestate() {
+        let prestate = serde_json::to_value(tracer().into_prestate()).unwrap();
+
+        let expected = json!({
+            SENDER.to_string(): { "balance": "0x64", "nonce": 1 },
+            CONTRACT.to_string(): {
+                "balance": "0x0",
+                "nonce": 1,
+                "code": "0x60",
+                "storage": { slot(1): value(1), slot(2): value(2) },
+            },
+            CREATED.to_string(): { "balance": "0x0" },
+            UNTOUCHED.to_string(): { "balance": "0x5" },
+            DESTROYED.to_string(): { "balance": "0x7" },
+        });
+        assert_eq!(prestate, expected);
+    }
+
+    #[test]
+    fn diff_mode() {
+        let diff = serde_json::to_value(tracer().into_diff()).unwrap();
+
+        // Pre state has the changed slots only, created accounts are missing from it.
+        // Post state has the changed fields only, destroyed accounts are missing from it.
+        let expected = json!({
+            "pre": {
+                SENDER.to_string(): { "balance": "0x64", "nonce": 1 },
+                CONTRACT.to_string(): {
+                    "balance": "0x0",
+                    "nonce": 1,
+                    "code": "0x60",
+                    "storage": { slot(2): value(2) },
+                },
+                DESTROYED.to_string(): { "balance": "0x7" },
+            },
+            "post": {
+                SENDER.to_string(): { "balance": "0x5a", "nonce": 2 },
+                CONTRACT.to_string(): {
+                    "balance": "0xa",
+                    "storage": { slot(2): value(3) },
+                },
+                CREATED.to_string(): { "nonce": 1, "code": "0x00" },
+            },
+        });
+        assert_eq!(diff, expected);
+    }
+
+    #[test]
+    fn state_diff() {
+        let diff = serde_json::to_value(tracer().into_state_diff()).unwrap();
+
+        let expected = json!({
+            SENDER.to_string(): {
+                "balance": { "*": { "from": "0x64", "to": "0x5a" } },
+                "nonce": { "*": { "from": "0x1", "to": "0x2" } },
+                "code": "=",
+                "storage": {},
+            },
+            CONTRACT.to_string(): {
+                "balance": { "*": { "from": "0x0", "to": "0xa" } },
+                "nonce": "=",
+                "code": "=",
+                "storage": { slot(2): { "*": { "from": value(2), "to": value(3) } } },
+            },
+            CREATED.to_string(): {
+                "balance": { "+": "0x0" },
+                "nonce": { "+": "0x1" },
+                "code": { "+": "0x00" },
+                "storage": {},
+            },
+            DESTROYED.to_string(): {
+                "balance": { "-": "0x7" },
+                "nonce": { "-": "0x0" },
+                "code": { "-": "0x" },
+                "storage": {},
+            },
+        });
+        assert_eq!(diff, expected);
+    }
+}
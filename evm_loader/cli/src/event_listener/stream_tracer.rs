@@ -0,0 +1,296 @@
+use std::io::Write;
+
+use evm_loader::evm::tracing::{CallType, Event, EventListener};
+use evm_loader::evm::ExitStatus;
+use evm_loader::types::Address;
+use serde::Serialize;
+
+use super::struct_logger::{AccessedStorage, StructLog};
+use crate::commands::trace::TraceOptions;
+
+/// Line of the streamed trace
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+enum Record<'a> {
+    /// Frame started
+    #[serde(rename_all = "camelCase")]
+    Enter {
+        depth: usize,
+        call_type: &'static str,
+        from: Address,
+        to: Address,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        code_address: Option<Address>,
+        value: String,
+        gas: u64,
+        input: String,
+    },
+    /// Step in the `structLogger` format, written when the step is finished.
+    /// CALL and CREATE steps are written after the records of their frames.
+    Step(&'a StructLog),
+    /// Frame finished
+    #[serde(rename_all = "camelCase")]
+    Exit {
+        depth: usize,
+        gas_used: u64,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        output: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        error: Option<String>,
+    },
+    /// Last line of the stream, written even if the records are truncated
+    #[serde(rename_all = "camelCase")]
+    Result {
+        gas: u64,
+        failed: bool,
+        return_value: String,
+        records: usize,
+        truncated: bool,
+    },
+}
+
+/// Counters of the finished stream
+#[derive(Serialize, Debug, Clone, Copy)]
+pub struct StreamSummary {
+    pub records: usize,
+    pub truncated: bool,
+}
+
+struct Frame {
+    /// Storage address of the frame
+    contract: Address,
+    /// Step waiting for `EndStep`
+    pending: Option<StructLog>,
+}
+
+/// Writes frames and steps as newline delimited JSON records as soon as they are finished,
+/// so the memory usage does not depend on the trace length
+pub struct StreamTracer<W: Write> {
+    options: TraceOptions,
+    writer: W,
+    frames: Vec<Frame>,
+    storage: AccessedStorage,
+    records: usize,
+    truncated: bool,
+    /// First write error, the following records are dropped
+    error: Option<std::io::Error>,
+}
+
+impl<W: Write> StreamTracer<W> {
+    #[must_use]
+    pub fn new(options: TraceOptions, writer: W) -> Self {
+        Self {
+            options,
+            writer,
+            frames: Vec::new(),
+            storage: AccessedStorage::default(),
+            records: 0,
+            truncated: false,
+            error: None,
+        }
+    }
+
+    /// Closes the frames left open and writes the result record
+    pub fn finish(
+        mut self,
+        gas: u64,
+        failed: bool,
+        return_value: &[u8],
+    ) -> std::io::Result<StreamSummary> {
+        while !self.frames.is_empty() {
+            self.end_step();
+            self.exit(0, None, Some("step limit exceeded".to_string()));
+        }
+
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+
+        let result = Record::Result {
+            gas,
+            failed,
+            return_value: hex::encode(return_value),
+            records: self.records,
+            truncated: self.truncated,
+        };
+        serde_json::to_writer(&mut self.writer, &result)?;
+        self.writer.write_all(b"\n")?;
+        self.writer.flush()?;
+
+        Ok(StreamSummary {
+            records: self.records,
+            truncated: self.truncated,
+        })
+    }
+
+    fn write(&mut self, record: &Record) {
+        if self.error.is_some() || self.truncated {
+            return;
+        }
+
+        if let Some(max_records) = self.options.max_records {
+            if self.records >= max_records {
+                self.truncated = true;
+                return;
+            }
+        }
+
+        let result = serde_json::to_writer(&mut self.writer, record)
+            .map_err(std::io::Error::from)
+            .and_then(|_| self.writer.write_all(b"\n"));
+
+        match result {
+            Ok(_) => self.records += 1,
+            Err(e) => self.error = Some(e),
+        }
+    }
+
+    fn end_step(&mut self) {
+        let pending = self
+            .frames
+            .last_mut()
+            .and_then(|frame| frame.pending.take());
+
+        if let Some(log) = pending {
+            self.write(&Record::Step(&log));
+        }
+    }
+
+    fn exit(&mut self, gas_used: u64, output: Option<&[u8]>, error: Option<String>) {
+        let depth = self.frames.len();
+        self.frames.pop();
+
+        self.write(&Record::Exit {
+            depth,
+            gas_used,
+            output: output
+                .filter(|output| !output.is_empty())
+                .map(|output| format!("0x{}", hex::encode(output))),
+            error,
+        });
+    }
+
+    fn storage_access(&mut self, index: ethnum::U256, value: [u8; 32]) {
+        if self.options.disable_storage {
+            return;
+        }
+
+        let Self {
+            frames, storage, ..
+        } = self;
+
+        if let Some(frame) = frames.last_mut() {
+            let slots = storage.access(frame.contract, index, value);
+            if let Some(log) = frame.pending.as_mut() {
+                if log.is_storage_access() {
+                    log.storage = Some(slots);
+                }
+            }
+        }
+    }
+}
+
+impl<W: Write> EventListener for StreamTracer<W> {
+    fn event(&mut self, event: Event) {
+        match event {
+            Event::BeginVM {
+                context,
+                call_type,
+                code,
+                input,
+                gas,
+            } => {
+                let input = match call_type {
+                    CallType::Create | CallType::Create2 => code,
+                    _ => input,
+                };
+
+                self.frames.push(Frame {
+                    contract: context.contract,
+                    pending: None,
+                });
+
+                self.write(&Record::Enter {
+                    depth: self.frames.len(),
+                    call_type: call_type.name(),
+                    from: context.caller,
+                    to: context.contract,
+                    code_address: context.code_address,
+                    value: format!("0x{:x}", context.value),
+                    gas,
+                    input: format!("0x{}", hex::encode(input)),
+                });
+            }
+            Event::EndVM { status, gas_used } => match status {
+                ExitStatus::Stop | ExitStatus::Suicide => self.exit(gas_used, None, None),
+                ExitStatus::Return(output) => self.exit(gas_used, Some(output.as_slice()), None),
+                ExitStatus::Revert(output) => {
+                    let error = Some("execution reverted".to_string());
+                    self.exit(gas_used, Some(output.as_slice()), error);
+                }
+                ExitStatus::StepLimit => {
+                    // Reported by the top level frame, nested frames are still open
+                    while self.frames.len() > 1 {
+                        self.end_step();
+                        self.exit(0, None, Some("step limit exceeded".to_string()));
+                    }
+                    self.end_step();
+                    self.exit(gas_used, None, Some("step limit exceeded".to_string()));
+                }
+            },
+            Event::BeginStep {
+                opcode,
+                pc,
+                stack,
+                memory,
+                gas,
+                refund,
+                return_data,
+            } => {
+                let mut log = StructLog::new(
+                    &self.options,
+                    opcode,
+                    pc,
+                    stack,
+                    memory,
+                    gas,
+                    refund,
+                    return_data,
+                );
+                log.depth = self.frames.len();
+
+                if let Some(frame) = self.frames.last_mut() {
+                    frame.pending = Some(log);
+                }
+            }
+            Event::EndStep { gas_used } => {
+                if let Some(log) = self
+                    .frames
+                    .last_mut()
+                    .and_then(|frame| frame.pending.as_mut())
+                {
+                    log.gas_cost = gas_used;
+                }
+                self.end_step();
+            }
+            Event::StepError { message, .. } => {
+                if let Some(log) = self
+                    .frames
+                    .last_mut()
+                    .and_then(|frame| frame.pending.as_mut())
+                {
+                    log.error = Some(message);
+                }
+            }
+            Event::StorageAccess { index, value } | Event::StorageSet { index, value } => {
+                self.storage_access(index, value);
+            }
+            Event::StackPush { .. }
+            | Event::MemorySet { .. }
+            | Event::Log { .. }
+            | Event::SelfDestruct { .. }
+            | Event::PrecompileCall { .. }
+            | Event::ExternalInstruction { .. } => {}
+        }
+    }
+}
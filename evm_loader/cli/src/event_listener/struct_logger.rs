@@ -0,0 +1,467 @@
+use std::collections::{BTreeMap, HashMap};
+
+use ethnum::U256;
+use evm_loader::evm::tracing::{Event, EventListener};
+use evm_loader::types::Address;
+use serde::Serialize;
+
+use super::opcode_names::opcode_name;
+use crate::commands::trace::TraceOptions;
+
+const SLOAD: u8 = 0x54;
+const SSTORE: u8 = 0x55;
+
+/// Step in the Geth `structLogger` format
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct StructLog {
+    pub pc: usize,
+    pub op: &'static str,
+    pub gas: u64,
+    pub gas_cost: u64,
+    pub depth: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stack: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memory: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub storage: Option<BTreeMap<String, String>>,
+    #[serde(skip_serializing_if = "is_zero")]
+    pub refund: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub return_data: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip)]
+    opcode: u8,
+}
+
+impl StructLog {
+    /// Step with the data enabled by the options, `gas_cost` and `depth` are filled later
+    #[allow(clippy::too_many_arguments)]
+    pub(super) fn new(
+        options: &TraceOptions,
+        opcode: u8,
+        pc: usize,
+        stack: &[[u8; 32]],
+        memory: &[u8],
+        gas: u64,
+        refund: u64,
+        return_data: &[u8],
+    ) -> Self {
+        let stack = if options.disable_stack {
+            None
+        } else {
+            let words = stack
+                .iter()
+                .map(|word| format!("0x{:x}", U256::from_be_bytes(*word)));
+            Some(words.collect())
+        };
+
+        let memory = if options.disable_memory {
+            None
+        } else {
+            Some(memory.chunks(32).map(hex::encode).collect())
+        };
+
+        let return_data = if options.enable_return_data {
+            Some(format!("0x{}", hex::encode(return_data)))
+        } else {
+            None
+        };
+
+        Self {
+            pc,
+            op: opcode_name(opcode),
+            gas,
+            gas_cost: 0,
+            depth: 0,
+            stack,
+            memory,
+            storage: None,
+            refund,
+            return_data,
+            error: None,
+            opcode,
+        }
+    }
+
+    /// Geth attaches the storage only to SLOAD and SSTORE steps
+    pub(super) const fn is_storage_access(&self) -> bool {
+        (self.opcode == SLOAD) || (self.opcode == SSTORE)
+    }
+}
+
+/// Storage slots accessed so far, by contract
+#[derive(Default)]
+pub(super) struct AccessedStorage {
+    slots: HashMap<Address, BTreeMap<U256, [u8; 32]>>,
+}
+
+impl AccessedStorage {
+    /// Records the access and returns all the accessed slots of the contract
+    pub(super) fn access(
+        &mut self,
+        contract: Address,
+        index: U256,
+        value: [u8; 32],
+    ) -> BTreeMap<String, String> {
+        let storage = self.slots.entry(contract).or_default();
+        storage.insert(index, value);
+
+        storage
+            .iter()
+            .map(|(index, value)| (hex::encode(index.to_be_bytes()), hex::encode(value)))
+            .collect()
+    }
+}
+
+#[allow(clippy::trivially_copy_pass_by_ref)]
+fn is_zero(value: &u64) -> bool {
+    *value == 0
+}
+
+/// Result of the Geth `structLogger`
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct StructLoggerResult {
+    pub gas: u64,
+    pub failed: bool,
+    pub return_value: String,
+    pub struct_logs: Vec<StructLog>,
+}
+
+struct Frame {
+    /// Storage address of the frame
+    contract: Address,
+    /// Index of the step waiting for `EndStep`
+    pending: Option<usize>,
+}
+
+/// Collects steps in the Geth `structLogger` format
+pub struct StructLogger {
+    options: TraceOptions,
+    logs: Vec<StructLog>,
+    frames: Vec<Frame>,
+    storage: AccessedStorage,
+}
+
+impl StructLogger {
+    #[must_use]
+    pub fn new(options: TraceOptions) -> Self {
+        Self {
+            options,
+            logs: Vec::new(),
+            frames: Vec::new(),
+            storage: AccessedStorage::default(),
+        }
+    }
+
+    #[must_use]
+    pub fn into_result(self, gas: u64, failed: bool, return_value: &[u8]) -> StructLoggerResult {
+        StructLoggerResult {
+            gas,
+            failed,
+            return_value: hex::encode(return_value),
+            struct_logs: self.logs,
+        }
+    }
+
+    fn pending_step(&mut self) -> Option<&mut StructLog> {
+        let index = self.frames.last()?.pending?;
+        self.logs.get_mut(index)
+    }
+
+    fn begin_step(&mut self, mut log: StructLog) {
+        let Self { logs, frames, .. } = self;
+
+        log.depth = frames.len();
+        if let Some(frame) = frames.last_mut() {
+            frame.pending = Some(logs.len());
+        }
+
+        logs.push(log);
+    }
+
+    fn storage_access(&mut self, index: U256, value: [u8; 32]) {
+        if self.options.disable_storage {
+            return;
+        }
+
+        let contract = match self.frames.last() {
+            Some(frame) => frame.contract,
+            None => return,
+        };
+
+        let storage = self.storage.access(contract, index, value);
+
+        if let Some(log) = self.pending_step() {
+            if log.is_storage_access() {
+                log.storage = Some(storage);
+            }
+        }
+    }
+}
+
+impl EventListener for StructLogger {
+    fn event(&mut self, event: Event) {
+        match event {
+            Event::BeginVM { context, .. } => {
+                self.frames.push(Frame {
+                    contract: context.contract,
+                    pending: None,
+                });
+            }
+            Event::EndVM { .. } => {
+                self.frames.pop();
+            }
+            Event::BeginStep {
+                opcode,
+                pc,
+                stack,
+                memory,
+                gas,
+                refund,
+                return_data,
+            } => {
+                let log = StructLog::new(
+                    &self.options,
+                    opcode,
+                    pc,
+                    stack,
+                    memory,
+                    gas,
+                    refund,
+                    return_data,
+                );
+                self.begin_step(log);
+            }
+            Event::EndStep { gas_used } => {
+                if let Some(log) = self.pending_step() {
+                    log.gas_cost = gas_used;
+                }
+                if let Some(frame) = self.frames.last_mut() {
+                    frame.pending = None;
+                }
+            }
+            Event::StepError { message, .. } => {
+                if let Some(log) = self.pending_step() {
+                    log.error = Some(message);
+                }
+            }
+            Event::StorageAccess { index, value } | Event::StorageSet { index, value } => {
+                self.storage_access(index, value);
+            }
+            Event::StackPush { .. }
+            | Event::MemorySet { .. }
+            | Event::Log { .. }
+            | Event::SelfDestruct { .. }
+            | Event::PrecompileCall { .. }
+            | Event::ExternalInstruction { .. } => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ethnum::U256;
+    use evm_loader::evm::tracing::{CallType, Event, EventListener};
+    use evm_loader::evm::{Context, ExitStatus};
+    use evm_loader::types::Address;
+    use serde_json::json;
+
+    use super::{StructLogger, TraceOptions};
+
+    const CALLER: Address = Address([0xaa; 20]);
+    const CONTRACT: Address = Address([0xbb; 20]);
+    const CALLEE: Address = Address([0xcc; 20]);
+
+    fn word(value: u8) -> [u8; 32] {
+        U256::from(value).to_be_bytes()
+    }
+
+    fn begin_vm(logger: &mut StructLogger, caller: Address, contract: Address) {
+        logger.event(Event::BeginVM {
+            context: Context {
+                caller,
+                contract,
+                value: U256::ZERO,
+                code_address: None,
+            },
+            call_type: CallType::Call,
+            code: &[],
+            input: &[],
+            gas: 1000,
+        });
+    }
+
+    fn begin_step(
+        logger: &mut StructLogger,
+        opcode: u8,
+        pc: usize,
+        stack: &[[u8; 32]],
+        memory: &[u8],
+        gas: u64,
+    ) {
+        logger.event(Event::BeginStep {
+            opcode,
+            pc,
+            stack,
+            memory,
+            gas,
+            refund: 0,
+            return_data: &[0xab],
+        });
+    }
+
+    #[test]
+    fn nested_steps() {
+        let mut logger = StructLogger::new(TraceOptions::default());
+
+        begin_vm(&mut logger, CALLER, CONTRACT);
+        begin_step(&mut logger, 0x60, 0, &[], &[], 1000);
+        logger.event(Event::EndStep { gas_used: 3 });
+
+        begin_step(&mut logger, 0x54, 2, &[word(1)], &[0x11; 32], 997);
+        logger.event(Event::StorageAccess {
+            index: U256::new(1),
+            value: word(5),
+        });
+        logger.event(Event::EndStep { gas_used: 2100 });
+
+        // Storage of the nested frame is reported separately, CALL gets its cost after the frame
+        begin_step(&mut logger, 0xF1, 3, &[], &[], 800);
+        begin_vm(&mut logger, CONTRACT, CALLEE);
+        begin_step(&mut logger, 0x55, 0, &[word(1), word(7)], &[], 500);
+        logger.event(Event::StorageSet {
+            index: U256::new(1),
+            value: word(7),
+        });
+        logger.event(Event::EndStep { gas_used: 5000 });
+        logger.event(Event::EndVM {
+            status: ExitStatus::Stop,
+            gas_used: 5000,
+        });
+        logger.event(Event::EndStep { gas_used: 600 });
+
+        begin_step(&mut logger, 0xFE, 4, &[], &[], 100);
+        logger.event(Event::StepError {
+            message: "invalid opcode: INVALID".to_string(),
+            reason: Some("invalid opcode"),
+        });
+        logger.event(Event::EndVM {
+            status: ExitStatus::Revert(Vec::new()),
+            gas_used: 1000,
+        });
+
+        let result = logger.into_result(1000, true, &[0xde, 0xad]);
+        assert_eq!(
+            serde_json::to_value(result).unwrap(),
+            json!({
+                "gas": 1000,
+                "failed": true,
+                "returnValue": "dead",
+                "structLogs": [
+                    {
+                        "pc": 0,
+                        "op": "PUSH1",
+                        "gas": 1000,
+                        "gasCost": 3,
+                        "depth": 1,
+                        "stack": [],
+                        "memory": [],
+                    },
+                    {
+                        "pc": 2,
+                        "op": "SLOAD",
+                        "gas": 997,
+                        "gasCost": 2100,
+                        "depth": 1,
+                        "stack": ["0x1"],
+                        "memory": ["11".repeat(32)],
+                        "storage": { hex::encode(word(1)): hex::encode(word(5)) },
+                    },
+                    {
+                        "pc": 3,
+                        "op": "CALL",
+                        "gas": 800,
+                        "gasCost": 600,
+                        "depth": 1,
+                        "stack": [],
+                        "memory": [],
+                    },
+                    {
+                        "pc": 0,
+                        "op": "SSTORE",
+                        "gas": 500,
+                        "gasCost": 5000,
+                        "depth": 2,
+                        "stack": ["0x1", "0x7"],
+                        "memory": [],
+                        "storage": { hex::encode(word(1)): hex::encode(word(7)) },
+                    },
+                    {
+                        "pc": 4,
+                        "op": "INVALID",
+                        "gas": 100,
+                        "gasCost": 0,
+                        "depth": 1,
+                        "stack": [],
+                        "memory": [],
+                        "error": "invalid opcode: INVALID",
+                    },
+                ],
+            })
+        );
+    }
+
+    #[test]
+    fn disabled_data() {
+        let mut logger = StructLogger::new(TraceOptions {
+            disable_stack: true,
+            disable_memory: true,
+            disable_storage: true,
+            enable_return_data: true,
+            ..TraceOptions::default()
+        });
+
+        begin_vm(&mut logger, CALLER, CONTRACT);
+        logger.event(Event::BeginStep {
+            opcode: 0x54,
+            pc: 0,
+            stack: &[word(1)],
+            memory: &[0x11; 32],
+            gas: 1000,
+            refund: 4800,
+            return_data: &[0xab],
+        });
+        logger.event(Event::StorageAccess {
+            index: U256::new(1),
+            value: word(5),
+        });
+        logger.event(Event::EndStep { gas_used: 100 });
+        logger.event(Event::EndVM {
+            status: ExitStatus::Stop,
+            gas_used: 100,
+        });
+
+        let result = logger.into_result(100, false, &[]);
+        assert_eq!(
+            serde_json::to_value(result).unwrap(),
+            json!({
+                "gas": 100,
+                "failed": false,
+                "returnValue": "",
+                "structLogs": [{
+                    "pc": 0,
+                    "op": "SLOAD",
+                    "gas": 1000,
+                    "gasCost": 100,
+                    "depth": 1,
+                    "refund": 4800,
+                    "returnData": "0xab",
+                }],
+            })
+        );
+    }
+}
@@ -1,21 +1,38 @@
 use super::vm_tracer::VmTracer;
+use crate::commands::trace::TraceOptions;
+use crate::types::source_map::SourceMaps;
 use crate::types::trace::{FullTraceData, VMTrace, VMTracer};
+use std::collections::HashMap;
+use std::sync::Arc;
 
 pub struct Tracer {
     pub vm: VmTracer,
     pub data: Vec<FullTraceData>,
+    /// Disabled parts of the full trace data are not copied
+    pub options: TraceOptions,
 }
 
 impl Tracer {
-    pub fn new() -> Self {
+    /// Operations are annotated with the Solidity sources if the source maps are given
+    pub fn new(options: TraceOptions, source_maps: Option<Arc<SourceMaps>>) -> Self {
         Tracer {
-            vm: VmTracer::init(),
+            vm: VmTracer::init(source_maps),
             data: vec![],
+            options,
         }
     }
 
-    pub fn into_traces(self) -> (Option<VMTrace>, Vec<FullTraceData>) {
+    /// The steps record only the slot they accessed,
+    /// the storage of every step is extended here with the slots of the previous steps
+    pub fn into_traces(mut self) -> (Option<VMTrace>, Vec<FullTraceData>) {
         let vm = self.vm.tracer.drain();
+
+        let mut storage = HashMap::new();
+        for data in &mut self.data {
+            storage.extend(data.storage.drain());
+            data.storage.clone_from(&storage);
+        }
+
         (vm, self.data)
     }
 }
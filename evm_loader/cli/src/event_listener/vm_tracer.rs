@@ -1,5 +1,7 @@
+use crate::types::source_map::SourceMaps;
 use crate::types::trace::{ExecutiveVMTracer, MemoryDiff, StorageDiff};
 use ethnum::U256;
+use std::sync::Arc;
 
 #[derive(Debug, Default, Clone)]
 pub struct StepDiff {
@@ -15,9 +17,9 @@ pub struct VmTracer {
 }
 
 impl VmTracer {
-    pub fn init() -> Self {
+    pub fn init(source_maps: Option<Arc<SourceMaps>>) -> Self {
         VmTracer {
-            tracer: ExecutiveVMTracer::toplevel(),
+            tracer: ExecutiveVMTracer::toplevel(source_maps),
             step_diff: Vec::new(),
         }
     }
@@ -0,0 +1,469 @@
+//! Neon gas estimation mirroring the `Gasometer` charges of the program instructions
+
+use ethnum::U256;
+use evm_loader::{
+    config::{EVM_STEPS_LAST_ITERATION_MAX, PAYMENT_TO_TREASURE},
+    gasometer::{
+        address_lookup_table_cost, write_to_holder_cost, CANCEL_TRX_COST, LAMPORTS_PER_SIGNATURE,
+        LAST_ITERATION_COST, MIN_ACCOUNTS_TO_USE_ALT,
+    },
+    types::TransactionType,
+};
+use rlp::RlpStream;
+use serde::Serialize;
+use solana_sdk::packet::PACKET_DATA_SIZE;
+
+use crate::types::TxParams;
+
+/// Operator, treasury, operator ether account, system program and Neon program
+pub const EXECUTE_FIXED_ACCOUNTS: usize = 5;
+/// Holder or state account followed by the execute accounts
+pub const ITERATIVE_FIXED_ACCOUNTS: usize = 6;
+
+/// Instruction tag and treasury index
+const EXECUTE_INSTRUCTION_PREFIX: usize = 1 + 4;
+/// Instruction tag, treasury index, step count and unique index
+const STEP_INSTRUCTION_PREFIX: usize = 1 + 4 + 4 + 4;
+
+/// Instruction the operator executes the transaction with
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExecutionPath {
+    /// `TransactionExecuteFromInstruction`, a single Solana transaction
+    ExecuteFromInstruction,
+    /// `TransactionExecuteFromAccount`, a single Solana transaction after the holder writes
+    ExecuteFromAccount,
+    /// `TransactionStepFromInstruction`, iterative execution
+    StepFromInstruction,
+    /// `TransactionStepFromAccount`, iterative execution after the holder writes
+    StepFromAccount,
+}
+
+impl ExecutionPath {
+    #[must_use]
+    pub const fn is_iterative(self) -> bool {
+        matches!(self, Self::StepFromInstruction | Self::StepFromAccount)
+    }
+
+    #[must_use]
+    pub const fn uses_holder(self) -> bool {
+        matches!(self, Self::ExecuteFromAccount | Self::StepFromAccount)
+    }
+}
+
+/// Gas charged by the program, by the `Gasometer` record
+#[derive(Serialize, Debug, Default, Clone, Copy)]
+pub struct GasBreakdown {
+    /// `record_solana_transaction_cost` of every Solana transaction
+    pub signatures: u64,
+    /// `record_iterative_overhead`, the last iteration and the cancel reserve
+    pub iterative_overhead: u64,
+    /// `record_write_to_holder`
+    pub holder_writes: u64,
+    /// `record_address_lookup_table`
+    pub address_lookup_table: u64,
+    /// Treasury payment of every iteration executing the steps
+    pub treasury: u64,
+    /// Rent of the storage accounts and the external instruction allocations
+    pub actions: u64,
+    /// Rent of the created and resized Neon accounts
+    pub accounts: u64,
+}
+
+impl GasBreakdown {
+    #[must_use]
+    pub fn total(&self) -> u64 {
+        self.signatures
+            .saturating_add(self.iterative_overhead)
+            .saturating_add(self.holder_writes)
+            .saturating_add(self.address_lookup_table)
+            .saturating_add(self.treasury)
+            .saturating_add(self.actions)
+            .saturating_add(self.accounts)
+    }
+}
+
+/// Estimated gas of the recommended path
+#[allow(clippy::module_name_repetitions)]
+#[derive(Serialize, Debug, Clone, Copy)]
+pub struct GasEstimation {
+    /// Path the operator picks for the transaction
+    pub path: ExecutionPath,
+    /// Solana transactions executing the instruction, holder writes excluded
+    pub iterations: u64,
+    pub gas: u64,
+    pub breakdown: GasBreakdown,
+}
+
+/// Emulated transaction as seen by the operator
+pub struct EstimationInput {
+    pub steps_executed: u64,
+    /// Steps the operator executes in a single Solana transaction
+    pub iteration_steps: u64,
+    pub rlp_len: usize,
+    /// Neon and Solana accounts of the transaction
+    pub accounts: usize,
+    /// Iterations needed to resize the accounts by `MAX_PERMITTED_DATA_INCREASE`
+    pub resize_iterations: u64,
+    pub actions_rent: u64,
+    pub accounts_rent: u64,
+}
+
+/// Picks the execution path and charges the gas the same way the program does
+#[must_use]
+pub fn estimate(input: &EstimationInput) -> GasEstimation {
+    let iteration_steps = input.iteration_steps.max(1);
+
+    // Single execution runs all the steps and requires the accounts to be ready
+    let single = (input.steps_executed <= iteration_steps) && (input.resize_iterations == 0);
+    let path = if single {
+        let data_len = EXECUTE_INSTRUCTION_PREFIX + input.rlp_len;
+        let accounts = EXECUTE_FIXED_ACCOUNTS + input.accounts;
+        if solana_transaction_size(data_len, accounts) <= PACKET_DATA_SIZE {
+            ExecutionPath::ExecuteFromInstruction
+        } else {
+            ExecutionPath::ExecuteFromAccount
+        }
+    } else {
+        let data_len = STEP_INSTRUCTION_PREFIX + input.rlp_len;
+        let accounts = ITERATIVE_FIXED_ACCOUNTS + input.accounts;
+        if solana_transaction_size(data_len, accounts) <= PACKET_DATA_SIZE {
+            ExecutionPath::StepFromInstruction
+        } else {
+            ExecutionPath::StepFromAccount
+        }
+    };
+
+    let all_accounts = input.accounts
+        + match path {
+            ExecutionPath::ExecuteFromInstruction => EXECUTE_FIXED_ACCOUNTS,
+            _ => ITERATIVE_FIXED_ACCOUNTS,
+        };
+
+    let mut breakdown = GasBreakdown {
+        address_lookup_table: address_lookup_table_cost(all_accounts),
+        actions: input.actions_rent,
+        accounts: input.accounts_rent,
+        ..GasBreakdown::default()
+    };
+
+    if path.uses_holder() {
+        breakdown.holder_writes = write_to_holder_cost(input.rlp_len);
+    }
+
+    let iterations = if path.is_iterative() {
+        let step_iterations =
+            ((input.steps_executed + (iteration_steps - 1)) / iteration_steps).max(1);
+
+        // The results are applied by an iteration executing at most
+        // `EVM_STEPS_LAST_ITERATION_MAX` steps
+        let last_steps = input
+            .steps_executed
+            .saturating_sub((step_iterations - 1) * iteration_steps);
+        let apply_iterations = u64::from(last_steps > EVM_STEPS_LAST_ITERATION_MAX);
+
+        breakdown.iterative_overhead = LAST_ITERATION_COST + CANCEL_TRX_COST;
+        breakdown.treasury = step_iterations.saturating_mul(PAYMENT_TO_TREASURE);
+
+        // The first iteration only begins the transaction
+        1 + step_iterations + apply_iterations + input.resize_iterations
+    } else {
+        breakdown.treasury = PAYMENT_TO_TREASURE;
+        1
+    };
+
+    breakdown.signatures = iterations.saturating_mul(LAMPORTS_PER_SIGNATURE);
+
+    GasEstimation {
+        path,
+        iterations,
+        gas: breakdown.total(),
+        breakdown,
+    }
+}
+
+/// Size of the Solana transaction with the compute budget instructions,
+/// the accounts are moved to the lookup table when the program charges for it
+fn solana_transaction_size(data_len: usize, accounts: usize) -> usize {
+    // Compute budget program in addition to the instruction accounts
+    const PROGRAMS: usize = 1;
+    // Request units and request heap frame instructions
+    const COMPUTE_BUDGET_INSTRUCTIONS: usize = 2 * (1 + 1 + 1 + 5);
+
+    let with_alt = accounts >= MIN_ACCOUNTS_TO_USE_ALT;
+    let static_keys = if with_alt {
+        EXECUTE_FIXED_ACCOUNTS + PROGRAMS
+    } else {
+        accounts + PROGRAMS
+    };
+
+    let signatures = compact_len(1) + 64;
+    let header = 3;
+    let keys = compact_len(static_keys) + 32 * static_keys;
+    let blockhash = 32;
+    let instruction = 1 + compact_len(accounts) + accounts + compact_len(data_len) + data_len;
+    let instructions = compact_len(3) + COMPUTE_BUDGET_INSTRUCTIONS + instruction;
+    let lookup_table = if with_alt {
+        // Version prefix, table address and the writable and readonly indexes
+        let indexes = accounts - EXECUTE_FIXED_ACCOUNTS;
+        1 + compact_len(1) + 32 + compact_len(indexes) + compact_len(0) + indexes
+    } else {
+        0
+    };
+
+    signatures + header + keys + blockhash + instructions + lookup_table
+}
+
+/// Length of the Solana short vector length prefix
+const fn compact_len(len: usize) -> usize {
+    match len {
+        0..=0x7F => 1,
+        0x80..=0x3FFF => 2,
+        _ => 3,
+    }
+}
+
+/// Length of the signed transaction RLP, the signature is taken with 32 bytes `r` and `s`.
+/// Gas price of the legacy and access list transactions is not in the params,
+/// it is taken with 32 bytes too, so the length is never underestimated.
+#[must_use]
+pub fn transaction_rlp_len(tx: &TxParams, nonce: u64, chain_id: u64) -> usize {
+    let transaction_type = tx.transaction_type();
+
+    let mut stream = RlpStream::new();
+    match transaction_type {
+        TransactionType::Legacy => {
+            stream.begin_list(9);
+            stream.append(&nonce);
+            append_u256(&mut stream, U256::MAX);
+        }
+        TransactionType::AccessList => {
+            stream.begin_list(11);
+            stream.append(&chain_id);
+            stream.append(&nonce);
+            append_u256(&mut stream, U256::MAX);
+        }
+        TransactionType::DynamicFee => {
+            stream.begin_list(12);
+            stream.append(&chain_id);
+            stream.append(&nonce);
+            append_u256(&mut stream, tx.max_priority_fee_per_gas.unwrap_or_default());
+            append_u256(&mut stream, tx.max_fee_per_gas.unwrap_or_default());
+        }
+    }
+
+    append_u256(&mut stream, tx.gas_limit.unwrap_or_default());
+    match &tx.to {
+        Some(address) => stream.append(address),
+        None => stream.append_empty_data(),
+    };
+    append_u256(&mut stream, tx.value.unwrap_or_default());
+    stream.append(&tx.data.clone().unwrap_or_default());
+
+    if transaction_type == TransactionType::Legacy {
+        // EIP-155 `v`
+        stream.append(&chain_id.saturating_mul(2).saturating_add(36));
+    } else {
+        let access_list = tx.access_list.as_deref().unwrap_or_default();
+        stream.begin_list(access_list.len());
+        for item in access_list {
+            stream.begin_list(2);
+            stream.append(&item.address);
+            stream.begin_list(item.storage_keys.len());
+            for key in &item.storage_keys {
+                stream.append(&key.0.to_be_bytes().to_vec());
+            }
+        }
+        // y parity
+        stream.append(&1_u8);
+    }
+
+    append_u256(&mut stream, U256::MAX);
+    append_u256(&mut stream, U256::MAX);
+
+    let type_len = usize::from(transaction_type != TransactionType::Legacy);
+    type_len + stream.out().len()
+}
+
+/// Big-endian bytes without the leading zeros
+fn append_u256(stream: &mut RlpStream, value: U256) {
+    let bytes = value.to_be_bytes();
+    let leading_zeros = (value.leading_zeros() / 8) as usize;
+    stream.append(&bytes[leading_zeros..].to_vec());
+}
+
+#[cfg(test)]
+mod tests {
+    use ethnum::U256;
+    use evm_loader::{
+        config::{EVM_STEPS_LAST_ITERATION_MAX, PAYMENT_TO_TREASURE},
+        gasometer::write_to_holder_cost,
+        types::Address,
+    };
+
+    use super::{estimate, transaction_rlp_len, EstimationInput, ExecutionPath};
+    use crate::types::{AccessListItem, StorageKey, TxParams};
+
+    /// Example from EIP-155
+    const LEGACY: &str = "f86c098504a817c800825208943535353535353535353535353535353535353535880de0b6b3a76400008025a028ef61340bd939bc2195fe537567866003e1a15d3c71ff63e1590620aa636276a067cbe9d8997f761aecb703304b3800ccf555c9f3dc64214b297fb1966a3b6d83";
+    /// EIP-2930 transaction with a 20 gwei gas price
+    const ACCESS_LIST: &str = "01f8cd01808504a817c800827530943535353535353535353535353535353535353535880de0b6b3a764000083c0ffeef85bf85994de0b295669a9fd93d5f28d9ec85e40f4cb697baef842a00000000000000000000000000000000000000000000000000000000000000000a0000000000000000000000000000000000000000000000000000000000000000101a0d47644539acec3da5e3ecf5fe8863c628a9c97e8b71e9ea9167a6f4f83c03c32a07e3aaa895b7e8f7bbedacf854b40dc2a1ae22abdedfb785a7e84772e78854344";
+    /// EIP-1559 contract deployment
+    const DYNAMIC_FEE: &str = "02f85a01078477359400850ba43b7400830186a08080826000c001a0f30e4bd8094e53a679ddb8f55b5216b03c44623fc4279ef0791f9aa1f6930d49a035bd136867bc70a8d3a72ee24a396718439d3ff741125fa106746f1531732e23";
+
+    /// 20 gwei takes 6 bytes in the signed transaction and 33 bytes in the estimation
+    const GAS_PRICE_RESERVE: usize = 33 - 6;
+
+    fn tx_params(to: Option<Address>, value: u128, gas_limit: u128) -> TxParams {
+        TxParams {
+            from: Address::default(),
+            to,
+            data: None,
+            value: Some(U256::new(value)),
+            gas_limit: Some(U256::new(gas_limit)),
+            max_fee_per_gas: None,
+            max_priority_fee_per_gas: None,
+            access_list: None,
+            state_overrides: None,
+            block_overrides: None,
+        }
+    }
+
+    fn signed_len(raw: &str) -> usize {
+        hex::decode(raw).unwrap().len()
+    }
+
+    fn input(steps_executed: u64, rlp_len: usize, accounts: usize) -> EstimationInput {
+        EstimationInput {
+            steps_executed,
+            iteration_steps: 500,
+            rlp_len,
+            accounts,
+            resize_iterations: 0,
+            actions_rent: 0,
+            accounts_rent: 0,
+        }
+    }
+
+    #[test]
+    fn rlp_len_legacy() {
+        let tx = tx_params(
+            Some(Address::from([0x35; 20])),
+            1_000_000_000_000_000_000,
+            21_000,
+        );
+
+        assert_eq!(
+            transaction_rlp_len(&tx, 9, 1),
+            signed_len(LEGACY) + GAS_PRICE_RESERVE
+        );
+    }
+
+    #[test]
+    fn rlp_len_access_list() {
+        let mut tx = tx_params(
+            Some(Address::from([0x35; 20])),
+            1_000_000_000_000_000_000,
+            30_000,
+        );
+        tx.data = Some(vec![0xC0, 0xFF, 0xEE]);
+        tx.access_list = Some(vec![AccessListItem {
+            address: Address::from_hex("0xde0b295669a9fd93d5f28d9ec85e40f4cb697bae").unwrap(),
+            storage_keys: vec![StorageKey(U256::ZERO), StorageKey(U256::ONE)],
+        }]);
+
+        assert_eq!(
+            transaction_rlp_len(&tx, 0, 1),
+            signed_len(ACCESS_LIST) + GAS_PRICE_RESERVE
+        );
+    }
+
+    #[test]
+    fn rlp_len_dynamic_fee() {
+        let mut tx = tx_params(None, 0, 100_000);
+        tx.data = Some(vec![0x60, 0x00]);
+        tx.max_priority_fee_per_gas = Some(U256::new(2_000_000_000));
+        tx.max_fee_per_gas = Some(U256::new(50_000_000_000));
+
+        assert_eq!(transaction_rlp_len(&tx, 7, 1), signed_len(DYNAMIC_FEE));
+    }
+
+    #[test]
+    fn single_execution() {
+        let mut input = input(500, 110, 3);
+        input.actions_rent = 1_000;
+        input.accounts_rent = 2_000;
+        let estimation = estimate(&input);
+
+        assert_eq!(estimation.path, ExecutionPath::ExecuteFromInstruction);
+        assert_eq!(estimation.iterations, 1);
+        assert_eq!(estimation.breakdown.signatures, 5_000);
+        assert_eq!(estimation.breakdown.treasury, PAYMENT_TO_TREASURE);
+        assert_eq!(estimation.breakdown.iterative_overhead, 0);
+        assert_eq!(estimation.breakdown.holder_writes, 0);
+        assert_eq!(estimation.breakdown.address_lookup_table, 0);
+        assert_eq!(estimation.gas, 5_000 + PAYMENT_TO_TREASURE + 3_000);
+    }
+
+    #[test]
+    fn single_execution_from_holder() {
+        let estimation = estimate(&input(100, 2_000, 3));
+
+        assert_eq!(estimation.path, ExecutionPath::ExecuteFromAccount);
+        assert_eq!(estimation.iterations, 1);
+        assert_eq!(
+            estimation.breakdown.holder_writes,
+            write_to_holder_cost(2_000)
+        );
+        assert_eq!(
+            estimation.gas,
+            5_000 + PAYMENT_TO_TREASURE + write_to_holder_cost(2_000)
+        );
+    }
+
+    #[test]
+    fn single_execution_with_lookup_table() {
+        // 45 accounts with the fixed ones, two table extensions
+        let estimation = estimate(&input(100, 110, 40));
+
+        assert_eq!(estimation.path, ExecutionPath::ExecuteFromInstruction);
+        assert_eq!(estimation.breakdown.address_lookup_table, (2 + 3) * 5_000);
+        assert_eq!(estimation.gas, 5_000 + PAYMENT_TO_TREASURE + 25_000);
+    }
+
+    #[test]
+    fn iterative_execution() {
+        // 3 iterations of steps, the last one executes 200 steps
+        let estimation = estimate(&input(1_200, 110, 3));
+        let iterations = 1 + 3 + u64::from(200 > EVM_STEPS_LAST_ITERATION_MAX);
+
+        assert_eq!(estimation.path, ExecutionPath::StepFromInstruction);
+        assert_eq!(estimation.iterations, iterations);
+        assert_eq!(estimation.breakdown.signatures, iterations * 5_000);
+        assert_eq!(estimation.breakdown.iterative_overhead, 10_000);
+        assert_eq!(estimation.breakdown.treasury, 3 * PAYMENT_TO_TREASURE);
+        assert_eq!(
+            estimation.gas,
+            iterations * 5_000 + 10_000 + 3 * PAYMENT_TO_TREASURE
+        );
+    }
+
+    #[test]
+    fn resize_forces_iterative_execution() {
+        let mut input = input(0, 2_000, 3);
+        input.resize_iterations = 2;
+        let estimation = estimate(&input);
+
+        // Begin, a single step iteration and the resize iterations
+        assert_eq!(estimation.path, ExecutionPath::StepFromAccount);
+        assert_eq!(estimation.iterations, 4);
+        assert_eq!(
+            estimation.breakdown.holder_writes,
+            write_to_holder_cost(2_000)
+        );
+        assert_eq!(estimation.breakdown.treasury, PAYMENT_TO_TREASURE);
+        assert_eq!(
+            estimation.gas,
+            4 * 5_000 + 10_000 + PAYMENT_TO_TREASURE + write_to_holder_cost(2_000)
+        );
+    }
+}
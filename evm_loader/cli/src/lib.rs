@@ -1 +1,3 @@
+pub mod gas_estimation;
+pub mod transaction_plan;
 pub mod types;
@@ -7,10 +7,12 @@ pub mod config;
 pub mod context;
 mod errors;
 mod event_listener;
+mod gas_estimation;
 mod logs;
 mod program_options;
 mod rpc;
 mod syscall_stubs;
+mod transaction_plan;
 mod types;
 
 use clap::ArgMatches;
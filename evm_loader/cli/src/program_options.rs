@@ -5,6 +5,9 @@ use hex::FromHex;
 use solana_clap_utils::input_validators::{is_url_or_moniker, is_valid_pubkey};
 use std::fmt::Display;
 
+use crate::commands::trace::TracerType;
+use crate::types::{AccessListItem, AccountOverrides, BlockOverrides};
+
 pub fn truncate(in_str: &str) -> &str {
     if &in_str[..2] == "0x" {
         &in_str[2..]
@@ -51,6 +54,46 @@ where
         .map_err(|e| e.to_string())
 }
 
+// Return an error if string cannot be parsed as an EIP-2930 access list
+fn is_valid_access_list<T>(string: T) -> Result<(), String>
+where
+    T: AsRef<str>,
+{
+    serde_json::from_str::<Vec<AccessListItem>>(string.as_ref())
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+// Return an error if string cannot be parsed as the eth_call state overrides
+fn is_valid_state_overrides<T>(string: T) -> Result<(), String>
+where
+    T: AsRef<str>,
+{
+    serde_json::from_str::<AccountOverrides>(string.as_ref())
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+// Return an error if string cannot be parsed as the block context overrides
+fn is_valid_block_overrides<T>(string: T) -> Result<(), String>
+where
+    T: AsRef<str>,
+{
+    serde_json::from_str::<BlockOverrides>(string.as_ref())
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+// Return an error if string cannot be parsed as the hex bytes
+fn is_valid_hex<T>(string: T) -> Result<(), String>
+where
+    T: AsRef<str>,
+{
+    hex::decode(string.as_ref().trim_start_matches("0x"))
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
 fn is_valid_h256<T>(string: T) -> Result<(), String>
 where
     T: AsRef<str>,
@@ -124,6 +167,16 @@ macro_rules! trx_params {
                     .required(false)
                     .help("Network chain_id"),
             )
+            .arg(
+                Arg::with_name("evm_spec")
+                    .long("evm_spec")
+                    .value_name("HARDFORK")
+                    .takes_value(true)
+                    .required(false)
+                    .possible_values(&["Istanbul", "Berlin", "London", "Shanghai", "Cancun"])
+                    .case_insensitive(true)
+                    .help("EVM hardfork, overrides the network default"),
+            )
             .arg(
                 Arg::with_name("max_steps_to_execute")
                     .long("max_steps_to_execute")
@@ -143,6 +196,51 @@ macro_rules! trx_params {
                     .validator(is_valid_u256)
                     .help("Gas limit"),
             )
+            .arg(
+                Arg::with_name("max_fee_per_gas")
+                    .long("max_fee_per_gas")
+                    .value_name("MAX_FEE_PER_GAS")
+                    .takes_value(true)
+                    .required(false)
+                    .validator(is_valid_u256)
+                    .help("EIP-1559 max fee per gas"),
+            )
+            .arg(
+                Arg::with_name("max_priority_fee_per_gas")
+                    .long("max_priority_fee_per_gas")
+                    .value_name("MAX_PRIORITY_FEE_PER_GAS")
+                    .takes_value(true)
+                    .required(false)
+                    .validator(is_valid_u256)
+                    .help("EIP-1559 max priority fee per gas"),
+            )
+            .arg(
+                Arg::with_name("access_list")
+                    .long("access_list")
+                    .value_name("ACCESS_LIST")
+                    .takes_value(true)
+                    .required(false)
+                    .validator(is_valid_access_list)
+                    .help("EIP-2930 access list in JSON: [{\"address\":\"0x..\",\"storageKeys\":[\"0x..\"]}]"),
+            )
+            .arg(
+                Arg::with_name("state_overrides")
+                    .long("state_overrides")
+                    .value_name("STATE_OVERRIDES")
+                    .takes_value(true)
+                    .required(false)
+                    .validator(is_valid_state_overrides)
+                    .help("Account state overrides in JSON: {\"0x..\":{\"balance\":\"0x..\",\"nonce\":1,\"code\":\"0x..\",\"state\":{..},\"stateDiff\":{..}}}"),
+            )
+            .arg(
+                Arg::with_name("block_overrides")
+                    .long("block_overrides")
+                    .value_name("BLOCK_OVERRIDES")
+                    .takes_value(true)
+                    .required(false)
+                    .validator(is_valid_block_overrides)
+                    .help("Block context overrides in JSON: {\"number\":1,\"time\":1,\"coinbase\":\"0x..\",\"chainId\":1,\"blockHash\":{\"1\":\"0x..\"}}"),
+            )
             .arg(
                 Arg::with_name("cached_accounts")
                     .value_name("CACHED_ACCOUNTS")
@@ -166,6 +264,228 @@ macro_rules! trx_params {
     };
 }
 
+macro_rules! trace_options {
+    ($subcommand:expr) => {
+        $subcommand
+            .arg(
+                Arg::with_name("tracer")
+                    .long("tracer")
+                    .value_name("TRACER")
+                    .takes_value(true)
+                    .required(false)
+                    .possible_values(&TracerType::NAMES)
+                    .help("Trace output format, vmTrace by default"),
+            )
+            .arg(
+                Arg::with_name("diff_mode")
+                    .long("diff_mode")
+                    .takes_value(false)
+                    .required(false)
+                    .help("prestateTracer: report the state before and after the transaction"),
+            )
+            .arg(
+                Arg::with_name("with_log")
+                    .long("with_log")
+                    .takes_value(false)
+                    .required(false)
+                    .help("callTracer: include the logs of the frames"),
+            )
+            .arg(
+                Arg::with_name("disable_stack")
+                    .long("disable_stack")
+                    .takes_value(false)
+                    .required(false)
+                    .help("structLogger and vmTrace: do not capture the stack"),
+            )
+            .arg(
+                Arg::with_name("disable_memory")
+                    .long("disable_memory")
+                    .takes_value(false)
+                    .required(false)
+                    .help("structLogger and vmTrace: do not capture the memory"),
+            )
+            .arg(
+                Arg::with_name("disable_storage")
+                    .long("disable_storage")
+                    .takes_value(false)
+                    .required(false)
+                    .help("structLogger and vmTrace: do not capture the storage"),
+            )
+            .arg(
+                Arg::with_name("enable_return_data")
+                    .long("enable_return_data")
+                    .takes_value(false)
+                    .required(false)
+                    .help("structLogger: capture the return data"),
+            )
+            .arg(
+                Arg::with_name("stream")
+                    .long("stream")
+                    .value_name("FILE")
+                    .takes_value(true)
+                    .required(false)
+                    .conflicts_with("tracer")
+                    .help("Write the trace to the file as newline delimited JSON records"),
+            )
+            .arg(
+                Arg::with_name("max_records")
+                    .long("max_records")
+                    .value_name("NUMBER")
+                    .takes_value(true)
+                    .required(false)
+                    .validator(is_amount::<usize, _>)
+                    .help("Stop writing the streamed trace records after this number"),
+            )
+            .arg(
+                Arg::with_name("artifacts")
+                    .long("artifacts")
+                    .value_name("DIR")
+                    .takes_value(true)
+                    .required(false)
+                    .help("vmTrace and openEthereum: directory with the solc standard JSON output to annotate the pcs with the Solidity sources"),
+            )
+            .arg(
+                Arg::with_name("filter_address")
+                    .long("filter_address")
+                    .value_name("ADDRESS")
+                    .takes_value(true)
+                    .required(false)
+                    .multiple(true)
+                    .validator(is_valid_address)
+                    .help("Trace only the steps of these contracts"),
+            )
+            .arg(
+                Arg::with_name("min_depth")
+                    .long("min_depth")
+                    .value_name("NUMBER")
+                    .takes_value(true)
+                    .required(false)
+                    .validator(is_amount::<usize, _>)
+                    .help("Trace only the steps at this call depth or deeper, the top level is 1"),
+            )
+            .arg(
+                Arg::with_name("max_depth")
+                    .long("max_depth")
+                    .value_name("NUMBER")
+                    .takes_value(true)
+                    .required(false)
+                    .validator(is_amount::<usize, _>)
+                    .help("Trace only the steps at this call depth or above"),
+            )
+            .arg(
+                Arg::with_name("min_pc")
+                    .long("min_pc")
+                    .value_name("NUMBER")
+                    .takes_value(true)
+                    .required(false)
+                    .validator(is_amount::<usize, _>)
+                    .help("Trace only the steps with this pc or greater"),
+            )
+            .arg(
+                Arg::with_name("max_pc")
+                    .long("max_pc")
+                    .value_name("NUMBER")
+                    .takes_value(true)
+                    .required(false)
+                    .validator(is_amount::<usize, _>)
+                    .help("Trace only the steps with this pc or less"),
+            )
+            .arg(
+                Arg::with_name("first_step")
+                    .long("first_step")
+                    .value_name("NUMBER")
+                    .takes_value(true)
+                    .required(false)
+                    .validator(is_amount::<u64, _>)
+                    .help("Trace only the steps starting from this one, counted from 0 across all frames"),
+            )
+            .arg(
+                Arg::with_name("last_step")
+                    .long("last_step")
+                    .value_name("NUMBER")
+                    .takes_value(true)
+                    .required(false)
+                    .validator(is_amount::<u64, _>)
+                    .help("Trace only the steps up to this one, inclusive"),
+            )
+    };
+}
+
+macro_rules! estimate_options {
+    ($subcommand:expr) => {
+        $subcommand
+            .arg(
+                Arg::with_name("estimate_gas")
+                    .long("estimate_gas")
+                    .takes_value(false)
+                    .help("Estimate the gas of the path the operator picks for the transaction"),
+            )
+            .arg(
+                Arg::with_name("iteration_steps")
+                    .long("iteration_steps")
+                    .value_name("NUMBER_OF_STEPS")
+                    .takes_value(true)
+                    .required(false)
+                    .requires("estimate_gas")
+                    .validator(is_amount::<u64, _>)
+                    .help("Steps in a single Solana transaction for the gas estimation"),
+            )
+    };
+}
+
+macro_rules! plan_options {
+    ($subcommand:expr) => {
+        $subcommand
+            .arg(
+                Arg::with_name("plan")
+                    .long("plan")
+                    .value_name("RAW_TRANSACTION")
+                    .takes_value(true)
+                    .required(false)
+                    .requires_all(&["holder", "operator_ether"])
+                    .validator(is_valid_hex)
+                    .help("Signed transaction to plan the operator's Solana transactions for"),
+            )
+            .arg(
+                Arg::with_name("holder")
+                    .long("holder")
+                    .value_name("HOLDER_ACCOUNT")
+                    .takes_value(true)
+                    .required(false)
+                    .requires("plan")
+                    .validator(is_valid_pubkey)
+                    .help("Holder and state account of the planned transactions"),
+            )
+            .arg(
+                Arg::with_name("create_holder")
+                    .long("create_holder")
+                    .takes_value(false)
+                    .requires("plan")
+                    .help("Initialize the holder account before writing the transaction"),
+            )
+            .arg(
+                Arg::with_name("operator_ether")
+                    .long("operator_ether")
+                    .value_name("OPERATOR_ETHER_ADDRESS")
+                    .takes_value(true)
+                    .required(false)
+                    .requires("plan")
+                    .validator(is_valid_address)
+                    .help("Neon account of the operator receiving the gas payment"),
+            )
+            .arg(
+                Arg::with_name("treasury_index")
+                    .long("treasury_index")
+                    .value_name("TREASURY_INDEX")
+                    .takes_value(true)
+                    .required(false)
+                    .requires("plan")
+                    .validator(is_amount::<u32, _>)
+                    .help("Treasury pool of the planned transactions, 0 by default"),
+            )
+    };
+}
+
 macro_rules! trx_hash {
     ($cmd:expr, $desc:expr) => {
         SubCommand::with_name($cmd)
@@ -196,6 +516,69 @@ macro_rules! trx_hash {
                     .required(false)
                     .help("Network chain_id"),
             )
+            .arg(
+                Arg::with_name("evm_spec")
+                    .long("evm_spec")
+                    .value_name("HARDFORK")
+                    .takes_value(true)
+                    .required(false)
+                    .possible_values(&["Istanbul", "Berlin", "London", "Shanghai", "Cancun"])
+                    .case_insensitive(true)
+                    .help("EVM hardfork, overrides the network default"),
+            )
+            .arg(
+                Arg::with_name("max_steps_to_execute")
+                    .long("max_steps_to_execute")
+                    .value_name("NUMBER_OF_STEPS")
+                    .takes_value(true)
+                    .required(false)
+                    .default_value("100000")
+                    .help("Maximal number of steps to execute in a single run"),
+            )
+    };
+}
+
+macro_rules! trx_bundle {
+    ($cmd:expr, $desc:expr) => {
+        SubCommand::with_name($cmd)
+            .about($desc)
+            .arg(
+                Arg::with_name("transactions")
+                    .index(1)
+                    .value_name("FILE")
+                    .takes_value(true)
+                    .required(false)
+                    .help(
+                        "JSON array of the transactions in the emulation order, stdin by default",
+                    ),
+            )
+            .arg(
+                Arg::with_name("token_mint")
+                    .long("token_mint")
+                    .value_name("TOKEN_MINT")
+                    .takes_value(true)
+                    .global(true)
+                    .validator(is_valid_pubkey)
+                    .help("Pubkey for token_mint"),
+            )
+            .arg(
+                Arg::with_name("chain_id")
+                    .long("chain_id")
+                    .value_name("CHAIN_ID")
+                    .takes_value(true)
+                    .required(false)
+                    .help("Network chain_id"),
+            )
+            .arg(
+                Arg::with_name("evm_spec")
+                    .long("evm_spec")
+                    .value_name("HARDFORK")
+                    .takes_value(true)
+                    .required(false)
+                    .possible_values(&["Istanbul", "Berlin", "London", "Shanghai", "Cancun"])
+                    .case_insensitive(true)
+                    .help("EVM hardfork, overrides the network default"),
+            )
             .arg(
                 Arg::with_name("max_steps_to_execute")
                     .long("max_steps_to_execute")
@@ -205,6 +588,26 @@ macro_rules! trx_hash {
                     .default_value("100000")
                     .help("Maximal number of steps to execute in a single run"),
             )
+            .arg(
+                Arg::with_name("cached_accounts")
+                    .value_name("CACHED_ACCOUNTS")
+                    .long("cached_accounts")
+                    .takes_value(true)
+                    .required(false)
+                    .multiple(true)
+                    .validator(is_valid_address)
+                    .help("List of cached account addresses"),
+            )
+            .arg(
+                Arg::with_name("solana_accounts")
+                    .value_name("SOLANA_ACCOUNTS")
+                    .long("solana_accounts")
+                    .takes_value(true)
+                    .required(false)
+                    .multiple(true)
+                    .validator(is_valid_address)
+                    .help("List of cached solana account pubkeys"),
+            )
     };
 }
 
@@ -319,16 +722,31 @@ pub fn parse<'a>() -> ArgMatches<'a> {
                 .help("Logging level"),
         )
         .subcommand(
-            trx_params!("emulate", "Emulation transaction")
+            plan_options!(estimate_options!(trx_params!("emulate", "Emulation transaction")))
+        )
+        .subcommand(
+            trace_options!(trx_params!("trace", "Emulation transaction to collecting traces"))
+        )
+        .subcommand(
+            trx_params!("debug", "Emulation transaction in the interactive step debugger")
+                .arg(
+                    Arg::with_name("data")
+                        .long("data")
+                        .value_name("DATA")
+                        .takes_value(true)
+                        .required(false)
+                        .validator(is_valid_hex)
+                        .help("Transaction data in hex, the stdin is left for the debugger commands")
+                )
         )
         .subcommand(
-            trx_params!("trace", "Emulation transaction to collecting traces")
+            plan_options!(estimate_options!(trx_hash!("emulate_hash", "Emulation transaction by hash")))
         )
         .subcommand(
-            trx_hash!("emulate_hash", "Emulation transaction by hash")
+            trx_bundle!("emulate-bundle", "Emulation of the transactions sequence on the shared state")
         )
         .subcommand(
-            trx_hash!("trace_hash", "Emulation transaction by hash to collecting traces")
+            trace_options!(trx_hash!("trace_hash", "Emulation transaction by hash to collecting traces"))
         )
         .subcommand(
             SubCommand::with_name("create-ether-account")
@@ -0,0 +1,508 @@
+//! Unsigned Solana transactions the operator sends to execute the emulated transaction
+
+use evm_loader::{
+    account::Treasury,
+    config::{COMPUTE_BUDGET_HEAP_FRAME, COMPUTE_BUDGET_UNITS, HOLDER_MSG_SIZE},
+    gasometer::MIN_ACCOUNTS_TO_USE_ALT,
+};
+use solana_sdk::{
+    address_lookup_table_account::AddressLookupTableAccount,
+    compute_budget::ComputeBudgetInstruction,
+    hash::Hash,
+    instruction::{AccountMeta, Instruction},
+    keccak,
+    message::{v0, Message, VersionedMessage},
+    packet::PACKET_DATA_SIZE,
+    pubkey::Pubkey,
+    signature::Signature,
+    system_program,
+    transaction::VersionedTransaction,
+};
+
+use crate::gas_estimation::{ExecutionPath, GasEstimation, ITERATIVE_FIXED_ACCOUNTS};
+
+const TRANSACTION_EXECUTE_FROM_INSTRUCTION_TAG: u8 = 0x1f;
+const TRANSACTION_STEP_FROM_INSTRUCTION_TAG: u8 = 0x20;
+const TRANSACTION_STEP_FROM_ACCOUNT_TAG: u8 = 0x21;
+const HOLDER_CREATE_TAG: u8 = 0x24;
+const HOLDER_WRITE_TAG: u8 = 0x26;
+const TRANSACTION_EXECUTE_FROM_ACCOUNT_TAG: u8 = 0x2A;
+const ACCOUNT_BLOCK_ADD_TAG: u8 = 0x2B;
+
+/// Accounts of the first iteration, the transaction fits without the lookup table
+const BEGIN_ACCOUNTS: usize = MIN_ACCOUNTS_TO_USE_ALT - ITERATIVE_FIXED_ACCOUNTS - 1;
+/// Accounts of a single `AccountBlockAdd` in addition to the state, operator and program
+const BLOCK_ADD_ACCOUNTS: usize = MIN_ACCOUNTS_TO_USE_ALT - 3;
+
+/// Operator settings of the planned transactions
+#[derive(Debug, Clone)]
+pub struct PlanOptions {
+    /// Signed transaction RLP, must match the emulated transaction
+    pub raw_transaction: Vec<u8>,
+    pub operator: Pubkey,
+    pub operator_ether_account: Pubkey,
+    /// Holder of the transaction and the state account of the iterative execution
+    pub holder: Pubkey,
+    /// Initializes the holder, the account has to be allocated for the program beforehand
+    pub create_holder: bool,
+    pub treasury_index: u32,
+}
+
+/// Solana transaction of the plan
+pub struct PlannedTransaction {
+    pub instructions: Vec<Instruction>,
+    /// Compiled as a versioned transaction with the plan's address lookup table,
+    /// the legacy transaction exceeds `PACKET_DATA_SIZE`
+    pub use_lookup_table: bool,
+}
+
+/// Solana transactions in the order they are sent.
+/// The operator creates and extends the address lookup table with `lookup_table_addresses`
+/// before the transactions that use it, the gas estimation charges for the table.
+#[allow(clippy::module_name_repetitions)]
+pub struct TransactionPlan {
+    pub path: ExecutionPath,
+    /// Fee payer and the signer of the transactions
+    pub operator: Pubkey,
+    /// Empty if every transaction fits without the lookup table
+    pub lookup_table_addresses: Vec<Pubkey>,
+    pub transactions: Vec<PlannedTransaction>,
+}
+
+/// Builds the transactions of the estimated path.
+/// `accounts` are the Neon and Solana accounts of the transaction, `begin_accounts` are
+/// read by the first iteration: the caller and the target or the created contract.
+/// The iterative path blocks the accounts that don't fit the first iteration by `AccountBlockAdd`.
+/// Transactions exceeding `PACKET_DATA_SIZE` load the accounts from the lookup table.
+#[must_use]
+pub fn build(
+    program_id: &Pubkey,
+    options: &PlanOptions,
+    estimation: &GasEstimation,
+    iteration_steps: u64,
+    accounts: &[AccountMeta],
+    begin_accounts: &[Pubkey],
+) -> TransactionPlan {
+    let path = estimation.path;
+    let raw_transaction = options.raw_transaction.as_slice();
+    let mut transactions = Vec::new();
+
+    if options.create_holder {
+        let holder_create = Instruction::new_with_bytes(
+            *program_id,
+            &[HOLDER_CREATE_TAG],
+            vec![
+                AccountMeta::new(options.holder, false),
+                AccountMeta::new_readonly(options.operator, true),
+            ],
+        );
+        transactions.push(vec![holder_create]);
+    }
+
+    if path.uses_holder() {
+        let hash = keccak::hash(raw_transaction).to_bytes();
+        let chunk_size = usize::try_from(HOLDER_MSG_SIZE).expect("usize is 8 bytes");
+        for (index, chunk) in raw_transaction.chunks(chunk_size).enumerate() {
+            let offset = (index * chunk_size) as u64;
+            let data = [&[HOLDER_WRITE_TAG][..], &hash, &offset.to_le_bytes(), chunk].concat();
+            let holder_write = Instruction::new_with_bytes(
+                *program_id,
+                &data,
+                vec![
+                    AccountMeta::new(options.holder, false),
+                    AccountMeta::new_readonly(options.operator, true),
+                ],
+            );
+            transactions.push(vec![holder_write]);
+        }
+    }
+
+    let treasury_index = options.treasury_index.to_le_bytes();
+    let treasury = Treasury::address(program_id, options.treasury_index).0;
+    let execute_accounts = [
+        AccountMeta::new(options.operator, true),
+        AccountMeta::new(treasury, false),
+        AccountMeta::new(options.operator_ether_account, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new_readonly(*program_id, false),
+    ];
+    let holder = AccountMeta::new(options.holder, false);
+
+    match path {
+        ExecutionPath::ExecuteFromInstruction => {
+            let data = [
+                &[TRANSACTION_EXECUTE_FROM_INSTRUCTION_TAG][..],
+                &treasury_index,
+                raw_transaction,
+            ]
+            .concat();
+            let metas = [&execute_accounts[..], accounts].concat();
+            transactions.push(with_compute_budget(Instruction::new_with_bytes(
+                *program_id,
+                &data,
+                metas,
+            )));
+        }
+        ExecutionPath::ExecuteFromAccount => {
+            let data = [&[TRANSACTION_EXECUTE_FROM_ACCOUNT_TAG][..], &treasury_index].concat();
+            let metas = [&[holder][..], &execute_accounts, accounts].concat();
+            transactions.push(with_compute_budget(Instruction::new_with_bytes(
+                *program_id,
+                &data,
+                metas,
+            )));
+        }
+        ExecutionPath::StepFromInstruction | ExecutionPath::StepFromAccount => {
+            let (tag, message) = if path == ExecutionPath::StepFromInstruction {
+                (TRANSACTION_STEP_FROM_INSTRUCTION_TAG, raw_transaction)
+            } else {
+                (TRANSACTION_STEP_FROM_ACCOUNT_TAG, &[][..])
+            };
+            let step_count = u32::try_from(iteration_steps)
+                .unwrap_or(u32::MAX)
+                .to_le_bytes();
+            let step_instruction = |unique_index: u32, accounts: &[AccountMeta]| {
+                // The unique index makes the signatures of the iterations different
+                let data = [
+                    &[tag][..],
+                    &treasury_index,
+                    &step_count,
+                    &unique_index.to_le_bytes(),
+                    message,
+                ]
+                .concat();
+                let metas = [&[holder.clone()][..], &execute_accounts, accounts].concat();
+                with_compute_budget(Instruction::new_with_bytes(*program_id, &data, metas))
+            };
+
+            // The first iteration reads the caller, the target and the operator accounts
+            let (mut ordered, mut other): (Vec<AccountMeta>, Vec<AccountMeta>) =
+                accounts.iter().cloned().partition(|meta| {
+                    begin_accounts.contains(&meta.pubkey)
+                        || (meta.pubkey == options.operator_ether_account)
+                });
+            ordered.append(&mut other);
+
+            // Large account sets are loaded from the lookup table,
+            // the accounts exceeding `PACKET_DATA_SIZE` are blocked by `AccountBlockAdd`
+            let begin_len = if ordered.len() <= BEGIN_ACCOUNTS {
+                ordered.len()
+            } else {
+                let lookup_table = lookup_table_addresses(options, accounts);
+                (BEGIN_ACCOUNTS + 1..=ordered.len())
+                    .take_while(|len| {
+                        let instructions = step_instruction(0, &ordered[..*len]);
+                        serialized_size(&options.operator, &instructions, Some(&lookup_table))
+                            .map_or(false, |size| size <= PACKET_DATA_SIZE)
+                    })
+                    .last()
+                    .unwrap_or(BEGIN_ACCOUNTS)
+            };
+            let (begin, blocked_accounts) = ordered.split_at(begin_len);
+
+            let iterations = u32::try_from(estimation.iterations).expect("iterations fit u32");
+            for unique_index in 0..iterations {
+                if unique_index == 0 {
+                    transactions.push(step_instruction(unique_index, begin));
+
+                    for chunk in blocked_accounts.chunks(BLOCK_ADD_ACCOUNTS) {
+                        let metas = [
+                            &[holder.clone(), AccountMeta::new(options.operator, true)][..],
+                            chunk,
+                        ]
+                        .concat();
+                        transactions.push(vec![Instruction::new_with_bytes(
+                            *program_id,
+                            &[ACCOUNT_BLOCK_ADD_TAG],
+                            metas,
+                        )]);
+                    }
+                } else {
+                    transactions.push(step_instruction(unique_index, accounts));
+                }
+            }
+        }
+    }
+
+    let operator = options.operator;
+    let transactions: Vec<PlannedTransaction> = transactions
+        .into_iter()
+        .map(|instructions| {
+            let use_lookup_table = serialized_size(&operator, &instructions, None)
+                .map_or(true, |size| size > PACKET_DATA_SIZE);
+            PlannedTransaction {
+                instructions,
+                use_lookup_table,
+            }
+        })
+        .collect();
+
+    let lookup_table_addresses = if transactions.iter().any(|t| t.use_lookup_table) {
+        lookup_table_addresses(options, accounts)
+    } else {
+        Vec::new()
+    };
+
+    TransactionPlan {
+        path,
+        operator,
+        lookup_table_addresses,
+        transactions,
+    }
+}
+
+/// Holder and the transaction accounts, signers can't be loaded from the lookup table
+fn lookup_table_addresses(options: &PlanOptions, accounts: &[AccountMeta]) -> Vec<Pubkey> {
+    let mut addresses = Vec::new();
+    for address in std::iter::once(options.holder).chain(accounts.iter().map(|a| a.pubkey)) {
+        if (address != options.operator) && !addresses.contains(&address) {
+            addresses.push(address);
+        }
+    }
+
+    addresses
+}
+
+/// Size of the signed transaction, the lookup table is compiled with a placeholder key
+fn serialized_size(
+    payer: &Pubkey,
+    instructions: &[Instruction],
+    lookup_table_addresses: Option<&[Pubkey]>,
+) -> Option<usize> {
+    let message = match lookup_table_addresses {
+        Some(addresses) => {
+            let lookup_table = AddressLookupTableAccount {
+                key: Pubkey::default(),
+                addresses: addresses.to_vec(),
+            };
+            let message =
+                v0::Message::try_compile(payer, instructions, &[lookup_table], Hash::default())
+                    .ok()?;
+            VersionedMessage::V0(message)
+        }
+        None => VersionedMessage::Legacy(Message::new(instructions, Some(payer))),
+    };
+
+    let signatures = usize::from(message.header().num_required_signatures);
+    let transaction = VersionedTransaction {
+        signatures: vec![Signature::default(); signatures],
+        message,
+    };
+
+    let size = bincode::serialized_size(&transaction).ok()?;
+    usize::try_from(size).ok()
+}
+
+/// Compute budget requests followed by the execution instruction
+fn with_compute_budget(instruction: Instruction) -> Vec<Instruction> {
+    let units = u32::try_from(COMPUTE_BUDGET_UNITS).expect("compute budget units fit u32");
+    let heap_frame = u32::try_from(COMPUTE_BUDGET_HEAP_FRAME).expect("heap frame fits u32");
+
+    vec![
+        ComputeBudgetInstruction::set_compute_unit_limit(units),
+        ComputeBudgetInstruction::request_heap_frame(heap_frame),
+        instruction,
+    ]
+}
+
+impl TransactionPlan {
+    /// Size of the signed transaction as it is sent, `None` if it can't be compiled
+    #[must_use]
+    pub fn transaction_size(&self, transaction: &PlannedTransaction) -> Option<usize> {
+        let lookup_table = transaction
+            .use_lookup_table
+            .then(|| self.lookup_table_addresses.as_slice());
+        serialized_size(&self.operator, &transaction.instructions, lookup_table)
+    }
+
+    /// Plan with the base58 pubkeys and the hex instruction data
+    #[must_use]
+    pub fn to_json(&self) -> serde_json::Value {
+        let lookup_table_addresses: Vec<String> = self
+            .lookup_table_addresses
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+
+        let transactions: Vec<serde_json::Value> = self
+            .transactions
+            .iter()
+            .map(|transaction| {
+                let instructions: Vec<serde_json::Value> = transaction
+                    .instructions
+                    .iter()
+                    .map(instruction_json)
+                    .collect();
+                serde_json::json!({
+                    "use_lookup_table": transaction.use_lookup_table,
+                    "instructions": instructions,
+                })
+            })
+            .collect();
+
+        serde_json::json!({
+            "path": self.path,
+            "lookup_table_addresses": lookup_table_addresses,
+            "transactions": transactions,
+        })
+    }
+}
+
+fn instruction_json(instruction: &Instruction) -> serde_json::Value {
+    let accounts: Vec<serde_json::Value> = instruction
+        .accounts
+        .iter()
+        .map(|meta| {
+            serde_json::json!({
+                "pubkey": meta.pubkey.to_string(),
+                "is_signer": meta.is_signer,
+                "is_writable": meta.is_writable,
+            })
+        })
+        .collect();
+
+    serde_json::json!({
+        "program_id": instruction.program_id.to_string(),
+        "accounts": accounts,
+        "data": hex::encode(&instruction.data),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use solana_sdk::{instruction::AccountMeta, packet::PACKET_DATA_SIZE, pubkey::Pubkey};
+
+    use super::{
+        build, PlanOptions, ACCOUNT_BLOCK_ADD_TAG, BEGIN_ACCOUNTS,
+        TRANSACTION_STEP_FROM_INSTRUCTION_TAG,
+    };
+    use crate::gas_estimation::{ExecutionPath, GasBreakdown, GasEstimation};
+
+    /// Writable and readonly accounts in the pubkey order, as `account_metas` returns them
+    fn accounts(count: usize) -> Vec<AccountMeta> {
+        let mut accounts: Vec<AccountMeta> = (0..count)
+            .map(|i| {
+                if i % 2 == 0 {
+                    AccountMeta::new(Pubkey::new_unique(), false)
+                } else {
+                    AccountMeta::new_readonly(Pubkey::new_unique(), false)
+                }
+            })
+            .collect();
+        accounts.sort_by_key(|meta| meta.pubkey);
+        accounts
+    }
+
+    fn options(raw_transaction_len: usize) -> PlanOptions {
+        PlanOptions {
+            raw_transaction: vec![0xAB; raw_transaction_len],
+            operator: Pubkey::new_unique(),
+            operator_ether_account: Pubkey::new_unique(),
+            holder: Pubkey::new_unique(),
+            create_holder: true,
+            treasury_index: 0,
+        }
+    }
+
+    fn estimation(path: ExecutionPath) -> GasEstimation {
+        GasEstimation {
+            path,
+            iterations: 4,
+            gas: 0,
+            breakdown: GasBreakdown::default(),
+        }
+    }
+
+    #[test]
+    fn transactions_fit_packet() {
+        let program_id = Pubkey::new_unique();
+        let accounts = accounts(40);
+        let begin_accounts = [accounts[0].pubkey];
+        let options = options(300);
+
+        for path in [
+            ExecutionPath::ExecuteFromInstruction,
+            ExecutionPath::ExecuteFromAccount,
+            ExecutionPath::StepFromInstruction,
+            ExecutionPath::StepFromAccount,
+        ] {
+            let plan = build(
+                &program_id,
+                &options,
+                &estimation(path),
+                500,
+                &accounts,
+                &begin_accounts,
+            );
+
+            assert!(!plan.lookup_table_addresses.is_empty());
+            assert!(!plan.lookup_table_addresses.contains(&options.operator));
+
+            for transaction in &plan.transactions {
+                let size = plan.transaction_size(transaction).unwrap();
+                assert!(size <= PACKET_DATA_SIZE, "{path:?}: {size} bytes");
+            }
+
+            // The first iteration loads all the accounts from the lookup table
+            let blocks = plan
+                .transactions
+                .iter()
+                .flat_map(|t| &t.instructions)
+                .filter(|i| i.data == [ACCOUNT_BLOCK_ADD_TAG])
+                .count();
+            assert_eq!(blocks, 0, "{path:?}");
+        }
+    }
+
+    #[test]
+    fn begin_iteration_reads_caller_and_target() {
+        let program_id = Pubkey::new_unique();
+        let accounts = accounts(120);
+        let caller = accounts[100].pubkey;
+        let target = accounts[110].pubkey;
+        let mut options = options(700);
+        options.create_holder = false;
+        options.operator_ether_account = accounts[115].pubkey;
+
+        let plan = build(
+            &program_id,
+            &options,
+            &estimation(ExecutionPath::StepFromInstruction),
+            500,
+            &accounts,
+            &[caller, target],
+        );
+
+        let begin = &plan.transactions[0];
+        assert!(begin.use_lookup_table);
+        assert!(plan.transaction_size(begin).unwrap() <= PACKET_DATA_SIZE);
+
+        let instruction = begin.instructions.last().unwrap();
+        assert_eq!(instruction.data[0], TRANSACTION_STEP_FROM_INSTRUCTION_TAG);
+        assert_eq!(instruction.data[9..13], 0_u32.to_le_bytes());
+
+        // Holder and the execute accounts go before the transaction accounts
+        let begin_accounts: Vec<Pubkey> = instruction.accounts[6..]
+            .iter()
+            .map(|meta| meta.pubkey)
+            .collect();
+        assert!(begin_accounts.len() > BEGIN_ACCOUNTS);
+        assert!(begin_accounts.len() < accounts.len());
+        assert_eq!(
+            begin_accounts[..3],
+            [caller, target, options.operator_ether_account]
+        );
+
+        // The rest of the accounts is blocked right after the first iteration
+        let blocked: Vec<Pubkey> = plan.transactions[1..]
+            .iter()
+            .flat_map(|t| &t.instructions)
+            .filter(|i| i.data == [ACCOUNT_BLOCK_ADD_TAG])
+            .flat_map(|i| i.accounts[2..].iter().map(|meta| meta.pubkey))
+            .collect();
+        assert_eq!(begin_accounts.len() + blocked.len(), accounts.len());
+        for meta in &accounts {
+            assert!(begin_accounts.contains(&meta.pubkey) ^ blocked.contains(&meta.pubkey));
+        }
+    }
+}
@@ -109,6 +109,11 @@ impl IndexerDb {
             data: Some(data),
             value: Some(value),
             gas_limit: Some(gas_limit),
+            max_fee_per_gas: None,
+            max_priority_fee_per_gas: None,
+            access_list: None,
+            state_overrides: None,
+            block_overrides: None,
         })
     }
 }
@@ -1,4 +1,5 @@
 mod indexer_db;
+pub mod source_map;
 #[allow(clippy::all)]
 pub mod trace;
 mod tracer_ch_db;
@@ -11,8 +12,12 @@ pub use tracer_ch_db::{ChError, ChResult, ClickHouseDb as TracerDb};
 
 use {
     ethnum::U256,
-    evm_loader::types::Address,
+    evm_loader::{
+        executor::Action,
+        types::{Address, TransactionType},
+    },
     postgres::NoTls,
+    std::collections::HashMap,
     thiserror::Error,
     // tokio::task::block_in_place,
     tokio_postgres::{connect, Client},
@@ -39,6 +44,258 @@ pub struct TxParams {
     pub data: Option<Vec<u8>>,
     pub value: Option<U256>,
     pub gas_limit: Option<U256>,
+    pub max_fee_per_gas: Option<U256>,
+    pub max_priority_fee_per_gas: Option<U256>,
+    pub access_list: Option<Vec<AccessListItem>>,
+    /// Replaced account state, `eth_call` state override semantics
+    pub state_overrides: Option<AccountOverrides>,
+    /// Block context replacing the current Solana slot
+    pub block_overrides: Option<BlockOverrides>,
+}
+
+impl TxParams {
+    /// EIP-1559 fields take precedence over the access list
+    #[must_use]
+    pub fn transaction_type(&self) -> TransactionType {
+        if self.max_fee_per_gas.is_some() || self.max_priority_fee_per_gas.is_some() {
+            TransactionType::DynamicFee
+        } else if self.access_list.is_some() {
+            TransactionType::AccessList
+        } else {
+            TransactionType::Legacy
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct AccessListItem {
+    pub address: Address,
+    pub storage_keys: Vec<StorageKey>,
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq, Hash)]
+pub struct StorageKey(#[serde(with = "ethnum::serde::prefixed")] pub U256);
+
+/// Account state used by the emulation instead of the state stored in Solana
+#[derive(Clone, Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase", try_from = "AccountOverrideFields")]
+pub struct AccountOverride {
+    #[serde(default, with = "quantity_option")]
+    pub nonce: Option<u64>,
+    #[serde(default, with = "prefixed_option")]
+    pub balance: Option<U256>,
+    #[serde(default, with = "hex_option")]
+    pub code: Option<Vec<u8>>,
+    /// Replaces the whole storage, the missing slots are zero
+    pub state: Option<HashMap<StorageKey, StorageKey>>,
+    /// Replaces the listed slots, the rest of the storage is read from Solana
+    pub state_diff: Option<HashMap<StorageKey, StorageKey>>,
+}
+
+/// `AccountOverride` as received, `state` and `stateDiff` are not checked yet
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AccountOverrideFields {
+    #[serde(default, with = "quantity_option")]
+    nonce: Option<u64>,
+    #[serde(default, with = "prefixed_option")]
+    balance: Option<U256>,
+    #[serde(default, with = "hex_option")]
+    code: Option<Vec<u8>>,
+    state: Option<HashMap<StorageKey, StorageKey>>,
+    state_diff: Option<HashMap<StorageKey, StorageKey>>,
+}
+
+impl TryFrom<AccountOverrideFields> for AccountOverride {
+    type Error = String;
+
+    fn try_from(fields: AccountOverrideFields) -> Result<Self, Self::Error> {
+        if fields.state.is_some() && fields.state_diff.is_some() {
+            return Err("account override has both state and stateDiff".to_string());
+        }
+
+        Ok(Self {
+            nonce: fields.nonce,
+            balance: fields.balance,
+            code: fields.code,
+            state: fields.state,
+            state_diff: fields.state_diff,
+        })
+    }
+}
+
+pub type AccountOverrides = HashMap<Address, AccountOverride>;
+
+/// Block context used by the emulation instead of the current Solana slot
+#[derive(Clone, Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct BlockOverrides {
+    #[serde(default, with = "quantity_option")]
+    pub number: Option<u64>,
+    /// Unix timestamp in seconds
+    #[serde(default, with = "quantity_option")]
+    pub time: Option<u64>,
+    pub coinbase: Option<Address>,
+    #[serde(default, with = "quantity_option")]
+    pub chain_id: Option<u64>,
+    /// Hashes returned by BLOCKHASH, by block number
+    pub block_hash: Option<HashMap<u64, BlockHash>>,
+}
+
+/// 0x-prefixed 32 bytes hash
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BlockHash(pub [u8; 32]);
+
+impl Serialize for BlockHash {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format!("0x{}", hex::encode(self.0)))
+    }
+}
+
+impl<'de> Deserialize<'de> for BlockHash {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use hex::FromHex;
+
+        let value = String::deserialize(deserializer)?;
+        let value = value.strip_prefix("0x").unwrap_or(&value);
+        <[u8; 32]>::from_hex(value)
+            .map(Self)
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// `ethnum::serde::prefixed` for the optional values
+mod prefixed_option {
+    use ethnum::U256;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        value: &Option<U256>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match value {
+            Some(value) => ethnum::serde::prefixed::serialize(value, serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<U256>, D::Error> {
+        let value = Option::<super::StorageKey>::deserialize(deserializer)?;
+        Ok(value.map(|value| value.0))
+    }
+}
+
+/// Optional quantity, either a JSON number or a 0x-prefixed hex string
+mod quantity_option {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Quantity {
+        Number(u64),
+        Hex(String),
+    }
+
+    pub fn serialize<S: Serializer>(value: &Option<u64>, serializer: S) -> Result<S::Ok, S::Error> {
+        match value {
+            Some(value) => serializer.serialize_str(&format!("{value:#x}")),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<u64>, D::Error> {
+        let value = Option::<Quantity>::deserialize(deserializer)?;
+        value
+            .map(|value| match value {
+                Quantity::Number(value) => Ok(value),
+                Quantity::Hex(value) => {
+                    let digits = value
+                        .strip_prefix("0x")
+                        .ok_or_else(|| D::Error::custom(format!("quantity {value} without 0x")))?;
+                    u64::from_str_radix(digits, 16).map_err(D::Error::custom)
+                }
+            })
+            .transpose()
+    }
+}
+
+/// Optional 0x-prefixed hex bytes
+mod hex_option {
+    use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        value: &Option<Vec<u8>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        value
+            .as_ref()
+            .map(|value| format!("0x{}", hex::encode(value)))
+            .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Vec<u8>>, D::Error> {
+        let value = Option::<String>::deserialize(deserializer)?;
+        value
+            .map(|value| {
+                let value = value.strip_prefix("0x").unwrap_or(&value);
+                hex::decode(value).map_err(D::Error::custom)
+            })
+            .transpose()
+    }
+}
+
+impl From<&AccessListItem> for evm_loader::types::AccessListItem {
+    fn from(item: &AccessListItem) -> Self {
+        Self {
+            address: item.address,
+            storage_keys: item.storage_keys.iter().map(|key| key.0).collect(),
+        }
+    }
+}
+
+/// Event log in the Ethereum receipt format
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Log {
+    pub address: Address,
+    pub topics: Vec<String>,
+    pub data: String,
+    pub log_index: String,
+}
+
+impl Log {
+    /// Logs of the transaction, reverted sub-calls are already excluded from the actions
+    #[must_use]
+    pub fn from_actions(actions: &[Action]) -> Vec<Self> {
+        actions
+            .iter()
+            .filter_map(|action| match action {
+                Action::EvmLog {
+                    address,
+                    topics,
+                    data,
+                } => Some((address, topics, data)),
+                _ => None,
+            })
+            .enumerate()
+            .map(|(index, (address, topics, data))| Self {
+                address: *address,
+                topics: topics
+                    .iter()
+                    .map(|topic| format!("0x{}", hex::encode(topic)))
+                    .collect(),
+                data: format!("0x{}", hex::encode(data)),
+                log_index: format!("{index:#x}"),
+            })
+            .collect()
+    }
 }
 
 pub fn do_connect(
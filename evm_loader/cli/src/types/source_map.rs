@@ -0,0 +1,589 @@
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Location of the instruction in the Solidity source
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SourceLocation {
+    pub contract: String,
+    pub file: String,
+    /// Byte offset in the file
+    pub offset: usize,
+    /// 1-based line and column, `None` if the source file is not available
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub line: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub column: Option<usize>,
+    /// Innermost function or modifier containing the instruction
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub function: Option<String>,
+}
+
+struct SourceFile {
+    path: String,
+    /// Offsets of the line starts, `None` if the content is not available
+    line_starts: Option<Vec<usize>>,
+    /// Functions and modifiers as (start, end, name)
+    functions: Vec<(usize, usize, String)>,
+}
+
+impl SourceFile {
+    fn line_column(&self, offset: usize) -> Option<(usize, usize)> {
+        let line_starts = self.line_starts.as_ref()?;
+        let line = line_starts.partition_point(|start| *start <= offset);
+        let column = offset - line_starts[line - 1] + 1;
+        Some((line, column))
+    }
+
+    fn function(&self, offset: usize) -> Option<String> {
+        self.functions
+            .iter()
+            .filter(|(start, end, _)| (*start <= offset) && (offset < *end))
+            .min_by_key(|(start, end, _)| end - start)
+            .map(|(_, _, name)| name.clone())
+    }
+}
+
+/// Decompressed source map entry
+#[derive(Clone, Copy, Default)]
+struct SourceMapEntry {
+    start: usize,
+    file: i64,
+}
+
+/// Source map of the deployed or creation bytecode of a contract
+pub struct ContractSourceMap {
+    name: String,
+    code: Vec<u8>,
+    /// Bytes of the linked libraries and immutables, they differ from the compiled code
+    wildcard: Vec<bool>,
+    /// Creation code is followed by the constructor arguments
+    creation: bool,
+    /// Instruction number by pc
+    instructions: Vec<usize>,
+    entries: Vec<SourceMapEntry>,
+    files: Arc<HashMap<i64, SourceFile>>,
+}
+
+impl ContractSourceMap {
+    fn matches(&self, code: &[u8]) -> bool {
+        let length_matches = if self.creation {
+            code.len() >= self.code.len()
+        } else {
+            code.len() == self.code.len()
+        };
+
+        length_matches
+            && self
+                .code
+                .iter()
+                .zip(code)
+                .zip(&self.wildcard)
+                .all(|((expected, actual), wildcard)| *wildcard || (expected == actual))
+    }
+
+    /// Source location of the instruction at the pc
+    #[must_use]
+    pub fn location(&self, pc: usize) -> Option<SourceLocation> {
+        let entry = self.entries.get(*self.instructions.get(pc)?)?;
+        let file = self.files.get(&entry.file)?;
+
+        let (line, column) = match file.line_column(entry.start) {
+            Some((line, column)) => (Some(line), Some(column)),
+            None => (None, None),
+        };
+
+        Some(SourceLocation {
+            contract: self.name.clone(),
+            file: file.path.clone(),
+            offset: entry.start,
+            line,
+            column,
+            function: file.function(entry.start),
+        })
+    }
+}
+
+/// Source maps of the contracts compiled to the solc standard JSON output.
+/// Hardhat build info files, which also contain the compiler input, are supported too.
+#[derive(Default)]
+pub struct SourceMaps {
+    contracts: Vec<Arc<ContractSourceMap>>,
+}
+
+impl SourceMaps {
+    /// Loads all the JSON files of the directory and its subdirectories.
+    /// Sources are taken from the compiler input or from the directory.
+    pub fn load(dir: &Path) -> std::io::Result<Self> {
+        let mut maps = Self::default();
+
+        let mut files = Vec::new();
+        json_files(dir, &mut files)?;
+
+        for path in files {
+            let data = std::fs::read(&path)?;
+            // Other JSON files are skipped
+            if let Ok(json) = serde_json::from_slice::<Value>(&data) {
+                maps.add_compilation(dir, &json);
+            }
+        }
+
+        if maps.contracts.is_empty() {
+            let message = format!("No solc output found in {}", dir.display());
+            return Err(Error::new(ErrorKind::NotFound, message));
+        }
+
+        Ok(maps)
+    }
+
+    /// Source map of the code, `None` if the code is not compiled from the artifacts
+    #[must_use]
+    pub fn contract(&self, code: &[u8]) -> Option<Arc<ContractSourceMap>> {
+        self.contracts
+            .iter()
+            .find(|contract| contract.matches(code))
+            .cloned()
+    }
+
+    fn add_compilation(&mut self, dir: &Path, json: &Value) {
+        let null = Value::Null;
+        let (input, output) = match json.get("output") {
+            Some(output) => (&json["input"], output),
+            None => (&null, json),
+        };
+
+        let sources = match output["sources"].as_object() {
+            Some(sources) => sources,
+            None => return,
+        };
+
+        let mut files = HashMap::new();
+        for (path, source) in sources {
+            let id = match source["id"].as_i64() {
+                Some(id) => id,
+                None => continue,
+            };
+
+            let content = input["sources"][path]["content"]
+                .as_str()
+                .map(ToString::to_string)
+                .or_else(|| std::fs::read_to_string(dir.join(path)).ok());
+            let line_starts = content.map(|content| {
+                std::iter::once(0)
+                    .chain(content.match_indices('\n').map(|(i, _)| i + 1))
+                    .collect()
+            });
+
+            let mut functions = Vec::new();
+            collect_functions(&source["ast"], &mut functions);
+
+            files.insert(
+                id,
+                SourceFile {
+                    path: path.clone(),
+                    line_starts,
+                    functions,
+                },
+            );
+        }
+        let files = Arc::new(files);
+
+        let contracts = output["contracts"].as_object().into_iter().flatten();
+        for (_, file_contracts) in contracts {
+            let file_contracts = file_contracts.as_object().into_iter().flatten();
+            for (name, contract) in file_contracts {
+                let evm = &contract["evm"];
+                for (bytecode, creation) in
+                    [(&evm["deployedBytecode"], false), (&evm["bytecode"], true)]
+                {
+                    if let Some(map) = contract_source_map(name, bytecode, creation, &files) {
+                        self.contracts.push(Arc::new(map));
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn json_files(dir: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            json_files(&path, files)?;
+        } else if path
+            .extension()
+            .map_or(false, |extension| extension == "json")
+        {
+            files.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+/// Collects `FunctionDefinition` and `ModifierDefinition` nodes of the compact AST
+fn collect_functions(node: &Value, functions: &mut Vec<(usize, usize, String)>) {
+    match node {
+        Value::Object(object) => {
+            let node_type = object.get("nodeType").and_then(Value::as_str);
+            if let Some("FunctionDefinition" | "ModifierDefinition") = node_type {
+                let src = object
+                    .get("src")
+                    .and_then(Value::as_str)
+                    .unwrap_or_default();
+                let mut fields = src.split(':').map(str::parse::<usize>);
+                if let (Some(Ok(start)), Some(Ok(length))) = (fields.next(), fields.next()) {
+                    // Constructor, fallback and receive have empty names
+                    let name = match object.get("name").and_then(Value::as_str) {
+                        Some(name) if !name.is_empty() => name,
+                        _ => object
+                            .get("kind")
+                            .and_then(Value::as_str)
+                            .unwrap_or_default(),
+                    };
+                    functions.push((start, start + length, name.to_string()));
+                }
+            }
+
+            for value in object.values() {
+                collect_functions(value, functions);
+            }
+        }
+        Value::Array(array) => {
+            for value in array {
+                collect_functions(value, functions);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn contract_source_map(
+    name: &str,
+    bytecode: &Value,
+    creation: bool,
+    files: &Arc<HashMap<i64, SourceFile>>,
+) -> Option<ContractSourceMap> {
+    let object = bytecode["object"].as_str()?;
+    let source_map = bytecode["sourceMap"].as_str()?;
+    if object.is_empty() {
+        return None;
+    }
+
+    let (code, mut wildcard) = decode_bytecode(object)?;
+
+    let references = bytecode["immutableReferences"]
+        .as_object()
+        .into_iter()
+        .flatten();
+    for (_, ranges) in references {
+        for range in ranges.as_array().into_iter().flatten() {
+            let start = usize::try_from(range["start"].as_u64()?).ok()?;
+            let length = usize::try_from(range["length"].as_u64()?).ok()?;
+            for flag in wildcard.iter_mut().skip(start).take(length) {
+                *flag = true;
+            }
+        }
+    }
+
+    Some(ContractSourceMap {
+        name: name.to_string(),
+        instructions: instruction_numbers(&code),
+        code,
+        wildcard,
+        creation,
+        entries: parse_source_map(source_map),
+        files: Arc::clone(files),
+    })
+}
+
+/// Hex bytecode with the `__$...$__` library placeholders
+fn decode_bytecode(object: &str) -> Option<(Vec<u8>, Vec<bool>)> {
+    const PLACEHOLDER_LENGTH: usize = 40;
+
+    let object = object.strip_prefix("0x").unwrap_or(object);
+
+    let mut code = Vec::with_capacity(object.len() / 2);
+    let mut wildcard = Vec::with_capacity(object.len() / 2);
+
+    let mut position = 0;
+    while position < object.len() {
+        if object[position..].starts_with("__") {
+            code.extend_from_slice(&[0; PLACEHOLDER_LENGTH / 2]);
+            wildcard.extend_from_slice(&[true; PLACEHOLDER_LENGTH / 2]);
+            position += PLACEHOLDER_LENGTH;
+        } else {
+            let byte = object.get(position..position + 2)?;
+            code.push(u8::from_str_radix(byte, 16).ok()?);
+            wildcard.push(false);
+            position += 2;
+        }
+    }
+
+    Some((code, wildcard))
+}
+
+/// Instruction number of every pc, push data bytes get the number of their push
+fn instruction_numbers(code: &[u8]) -> Vec<usize> {
+    const PUSH1: u8 = 0x60;
+    const PUSH32: u8 = 0x7F;
+
+    let mut numbers = Vec::with_capacity(code.len());
+    let mut instruction = 0;
+    let mut pc = 0;
+    while pc < code.len() {
+        let length = match code[pc] {
+            opcode @ PUSH1..=PUSH32 => 1 + usize::from(opcode - PUSH1 + 1),
+            _ => 1,
+        };
+
+        for _ in 0..length {
+            numbers.push(instruction);
+        }
+
+        pc += length;
+        instruction += 1;
+    }
+
+    numbers.truncate(code.len());
+    numbers
+}
+
+/// Decompresses the `s:l:f:j:m;...` source map, empty fields repeat the previous entry
+fn parse_source_map(source_map: &str) -> Vec<SourceMapEntry> {
+    let mut entries = Vec::new();
+    let mut last = SourceMapEntry::default();
+
+    for item in source_map.split(';') {
+        let mut fields = item.split(':');
+        if let Some(Ok(start)) = fields.next().map(str::parse::<usize>) {
+            last.start = start;
+        }
+        // Length is skipped, the location is the start of the range
+        if let Some(Ok(file)) = fields.nth(1).map(str::parse::<i64>) {
+            last.file = file;
+        }
+
+        entries.push(last);
+    }
+
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use serde_json::{json, Value};
+
+    use super::{
+        collect_functions, decode_bytecode, instruction_numbers, parse_source_map, SourceLocation,
+        SourceMaps,
+    };
+
+    const PATH: &str = "contracts/Counter.sol";
+    const SOURCE: &str = "// SPDX-License-Identifier: MIT
+pragma solidity ^0.8.0;
+
+contract Counter {
+    uint256 public count;
+
+    modifier positive(uint256 value) {
+        require(value > 0);
+        _;
+    }
+
+    function add(uint256 value) public positive(value) {
+        count += value;
+    }
+}
+";
+
+    /// PUSH1 0x80 PUSH1 0x40 MSTORE PUSH1 0x01 PUSH1 0x00 SSTORE STOP
+    const DEPLOYED: &str = "6080604052600160005500";
+    /// PUSH1 0x80 PUSH1 0x40 MSTORE
+    const CREATION: &str = "6080604052";
+
+    /// Hardhat build info with the solc standard JSON input and output of `SOURCE`
+    fn build_info() -> Value {
+        json!({
+            "input": {
+                "language": "Solidity",
+                "sources": { PATH: { "content": SOURCE } }
+            },
+            "output": {
+                "sources": {
+                    PATH: {
+                        "id": 0,
+                        "ast": {
+                            "nodeType": "SourceUnit",
+                            "src": "32:245:0",
+                            "nodes": [{
+                                "nodeType": "ContractDefinition",
+                                "name": "Counter",
+                                "src": "57:219:0",
+                                "nodes": [
+                                    { "nodeType": "VariableDeclaration", "name": "count", "src": "80:20:0" },
+                                    { "nodeType": "ModifierDefinition", "name": "positive", "src": "107:79:0" },
+                                    { "nodeType": "FunctionDefinition", "name": "add", "kind": "function", "src": "192:82:0" }
+                                ]
+                            }]
+                        }
+                    }
+                },
+                "contracts": {
+                    PATH: {
+                        "Counter": {
+                            "evm": {
+                                "bytecode": {
+                                    "object": CREATION,
+                                    "sourceMap": "57:219:0:-:0;;"
+                                },
+                                "deployedBytecode": {
+                                    "object": DEPLOYED,
+                                    "sourceMap": "57:219:0:-:0;;;150:18;253:14:0:i;;0:0:-1",
+                                    "immutableReferences": {}
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        })
+    }
+
+    fn location(
+        offset: usize,
+        line: usize,
+        column: usize,
+        function: Option<&str>,
+    ) -> Option<SourceLocation> {
+        Some(SourceLocation {
+            contract: "Counter".to_string(),
+            file: PATH.to_string(),
+            offset,
+            line: Some(line),
+            column: Some(column),
+            function: function.map(ToString::to_string),
+        })
+    }
+
+    #[test]
+    fn instruction_numbers_push_data() {
+        let code = hex::decode(DEPLOYED).unwrap();
+        assert_eq!(
+            instruction_numbers(&code),
+            vec![0, 0, 1, 1, 2, 3, 3, 4, 4, 5, 6]
+        );
+
+        // PUSH32 truncated by the end of the code
+        assert_eq!(instruction_numbers(&[0x00, 0x7F, 0x01]), vec![0, 1, 1]);
+    }
+
+    #[test]
+    fn parse_source_map_repeats_fields() {
+        let entries = parse_source_map("57:219:0:-:0;;;150:18;253:14:1:i;;0:0:-1;:5");
+        let entries: Vec<(usize, i64)> = entries.iter().map(|e| (e.start, e.file)).collect();
+
+        assert_eq!(
+            entries,
+            vec![
+                (57, 0),
+                (57, 0),
+                (57, 0),
+                (150, 0),
+                (253, 1),
+                (253, 1),
+                (0, -1),
+                (0, -1),
+            ]
+        );
+    }
+
+    #[test]
+    fn decode_library_placeholder() {
+        // PUSH20 <library address> ADDRESS EQ
+        let object = "73__$8c3b3a3b6a3f3e3a3b3d3e3a3f3b3a3e3a$__3014";
+        let (code, wildcard) = decode_bytecode(object).unwrap();
+
+        assert_eq!(code.len(), 23);
+        assert_eq!(code[0], 0x73);
+        assert_eq!(&code[1..21], &[0; 20]);
+        assert_eq!(&code[21..], &[0x30, 0x14]);
+        assert_eq!(wildcard.iter().filter(|w| **w).count(), 20);
+        assert!(!wildcard[0] && wildcard[1] && wildcard[20] && !wildcard[21]);
+
+        assert!(decode_bytecode("6080604").is_none());
+    }
+
+    #[test]
+    fn special_function_names() {
+        let ast = json!([
+            { "nodeType": "FunctionDefinition", "name": "", "kind": "receive", "src": "10:20:0" },
+            { "nodeType": "FunctionDefinition", "name": "", "kind": "constructor", "src": "40:20:0" }
+        ]);
+        let mut functions = Vec::new();
+        collect_functions(&ast, &mut functions);
+
+        assert_eq!(
+            functions,
+            vec![
+                (10, 30, "receive".to_string()),
+                (40, 60, "constructor".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn deployed_locations() {
+        let mut maps = SourceMaps::default();
+        maps.add_compilation(Path::new("."), &build_info());
+
+        let code = hex::decode(DEPLOYED).unwrap();
+        let map = maps.contract(&code).unwrap();
+
+        assert_eq!(map.location(0), location(57, 4, 1, None));
+        assert_eq!(map.location(4), location(57, 4, 1, None));
+        assert_eq!(map.location(5), location(150, 8, 9, Some("positive")));
+        assert_eq!(map.location(7), location(253, 13, 9, Some("add")));
+        assert_eq!(map.location(9), location(253, 13, 9, Some("add")));
+        // Compiler generated code has no source file
+        assert_eq!(map.location(10), None);
+        assert_eq!(map.location(11), None);
+
+        let mut other = code;
+        other[3] = 0x60;
+        assert!(maps.contract(&other).is_none());
+    }
+
+    #[test]
+    fn creation_with_constructor_arguments() {
+        let mut maps = SourceMaps::default();
+        maps.add_compilation(Path::new("."), &build_info());
+
+        let mut code = hex::decode(CREATION).unwrap();
+        code.extend_from_slice(&[0x01; 32]);
+        let map = maps.contract(&code).unwrap();
+
+        assert_eq!(map.location(4), location(57, 4, 1, None));
+        assert_eq!(map.location(5), None);
+    }
+
+    #[test]
+    fn solc_output_without_sources() {
+        let mut maps = SourceMaps::default();
+        maps.add_compilation(Path::new("/nonexistent"), &build_info()["output"]);
+
+        let code = hex::decode(DEPLOYED).unwrap();
+        let location = maps.contract(&code).unwrap().location(7).unwrap();
+
+        assert_eq!(location.offset, 253);
+        assert_eq!(location.line, None);
+        assert_eq!(location.column, None);
+        assert_eq!(location.function.as_deref(), Some("add"));
+    }
+}
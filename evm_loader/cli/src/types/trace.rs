@@ -1,4 +1,13 @@
-use {crate::types::Bytes, ethnum::U256, std::collections::HashMap};
+use {
+    crate::types::{
+        source_map::{ContractSourceMap, SourceLocation, SourceMaps},
+        Bytes,
+    },
+    ethnum::U256,
+    evm_loader::types::Address,
+    std::collections::{BTreeMap, HashMap},
+    std::sync::Arc,
+};
 
 #[derive(
     serde::Serialize,
@@ -67,6 +76,9 @@ pub struct VMOperation {
     pub gas_cost: U256,
     /// Information concerning the execution of the operation.
     pub executed: Option<VMExecutedOperation>,
+    /// Solidity source of the instruction, if the contract artifacts are provided.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub source: Option<SourceLocation>,
 }
 
 #[derive(
@@ -91,6 +103,23 @@ pub struct VMTrace {
     pub subs: Vec<VMTrace>,
 }
 
+impl VMTrace {
+    /// Source location of the REVERT that failed the call.
+    /// A revert is attributed to the last nested call if that call reverted too.
+    pub fn revert_location(&self) -> Option<&SourceLocation> {
+        const REVERT: u8 = 0xFD;
+
+        let operation = self.operations.last()?;
+        if operation.instruction != REVERT {
+            return None;
+        }
+
+        let nested = self.subs.last().and_then(VMTrace::revert_location);
+
+        nested.or(operation.source.as_ref())
+    }
+}
+
 // OpenEthereum tracer ethcore/src/trace/executive_tracer.rs
 #[allow(clippy::module_name_repetitions)]
 pub struct TraceData {
@@ -102,6 +131,7 @@ pub struct TraceData {
 pub struct FullTraceData {
     pub stack: Vec<[u8; 32]>,
     pub memory: Vec<u8>,
+    /// Storage slots accessed by the step and the previous steps
     pub storage: HashMap<U256, [u8; 32]>,
 }
 
@@ -110,11 +140,15 @@ pub struct ExecutiveVMTracer {
     data: VMTrace,
     pub depth: usize,
     trace_stack: Vec<TraceData>,
+    /// Annotates the operations with the Solidity source locations.
+    source_maps: Option<Arc<SourceMaps>>,
+    /// Source maps of the frames, `None` for the unknown contracts.
+    contracts: Vec<Option<Arc<ContractSourceMap>>>,
 }
 
 impl ExecutiveVMTracer {
     /// Create a new top-level instance.
-    pub fn toplevel() -> Self {
+    pub fn toplevel(source_maps: Option<Arc<SourceMaps>>) -> Self {
         ExecutiveVMTracer {
             data: VMTrace {
                 parent_step: 0,
@@ -124,6 +158,8 @@ impl ExecutiveVMTracer {
             },
             depth: 0,
             trace_stack: vec![],
+            source_maps,
+            contracts: vec![],
         }
     }
 
@@ -140,12 +176,19 @@ impl VMTracer for ExecutiveVMTracer {
     type Output = VMTrace;
 
     fn trace_prepare_execute(&mut self, pc: usize, instruction: u8) {
+        let source = self
+            .contracts
+            .last()
+            .and_then(Option::as_ref)
+            .and_then(|contract| contract.location(pc));
+
         Self::with_trace_in_depth(&mut self.data, self.depth, move |trace| {
             trace.operations.push(VMOperation {
                 pc,
                 instruction,
                 gas_cost: U256::ZERO,
                 executed: None,
+                source,
             });
         });
     }
@@ -164,6 +207,7 @@ impl VMTracer for ExecutiveVMTracer {
 
         Self::with_trace_in_depth(&mut self.data, self.depth, move |trace| {
             let operation = trace.operations.last_mut().expect("trace_executed is always called after a trace_prepare_execute; trace.operations cannot be empty; qed");
+            operation.gas_cost = gas_used;
             operation.executed = Some(VMExecutedOperation {
                 gas_used,
                 stack_push,
@@ -174,8 +218,15 @@ impl VMTracer for ExecutiveVMTracer {
     }
 
     fn prepare_subtrace(&mut self, code: Vec<u8>) {
+        let contract = self
+            .source_maps
+            .as_ref()
+            .and_then(|source_maps| source_maps.contract(&code));
+        self.contracts.push(contract);
+
         Self::with_trace_in_depth(&mut self.data, self.depth, move |trace| {
-            let parent_step = trace.operations.len() - 1; // won't overflow since we must already have pushed an operation in trace_prepare_execute.
+            // The filter always forwards the calling operation, it's the last traced one
+            let parent_step = trace.operations.len().saturating_sub(1);
             trace.subs.push(VMTrace {
                 parent_step,
                 code,
@@ -187,6 +238,7 @@ impl VMTracer for ExecutiveVMTracer {
     }
 
     fn done_subtrace(&mut self) {
+        self.contracts.pop();
         self.depth -= 1;
     }
 
@@ -223,9 +275,138 @@ pub trait VMTracer: Send {
     fn drain(self) -> Option<Self::Output>;
 }
 
+// OpenEthereum rpc/src/v1/types/account_diff.rs
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Eq)]
+/// Serde-friendly `Diff` shadow.
+pub enum Diff<T> {
+    #[serde(rename = "=")]
+    Same,
+    #[serde(rename = "+")]
+    Born(T),
+    #[serde(rename = "-")]
+    Died(T),
+    #[serde(rename = "*")]
+    Changed(ChangedType<T>),
+}
+
+impl<T: PartialEq> Diff<T> {
+    /// Difference of the values of an account that exists before and after the transaction.
+    pub fn new(from: T, to: T) -> Self {
+        if from == to {
+            Diff::Same
+        } else {
+            Diff::Changed(ChangedType { from, to })
+        }
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Eq)]
+/// Serde-friendly `ChangedType` shadow.
+pub struct ChangedType<T> {
+    pub from: T,
+    pub to: T,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Eq)]
+/// Serde-friendly `AccountDiff` shadow, values are hex strings.
+pub struct AccountDiff {
+    pub balance: Diff<String>,
+    pub nonce: Diff<String>,
+    pub code: Diff<String>,
+    pub storage: BTreeMap<String, Diff<String>>,
+}
+
+// OpenEthereum rpc/src/v1/types/trace.rs
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+/// Call action.
+pub struct CallAction {
+    pub from: Address,
+    pub to: Address,
+    pub value: String,
+    pub gas: String,
+    pub input: String,
+    /// `call`, `callcode`, `delegatecall` or `staticcall`
+    pub call_type: String,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+/// Create action.
+pub struct CreateAction {
+    pub from: Address,
+    pub value: String,
+    pub gas: String,
+    pub init: String,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+/// Suicide action.
+pub struct SuicideAction {
+    pub address: Address,
+    pub refund_address: Address,
+    pub balance: String,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+#[serde(untagged)]
+/// Action of the flat trace, the kind is in `ActionTrace::action_type`.
+pub enum TraceAction {
+    Call(CallAction),
+    Create(CreateAction),
+    Suicide(SuicideAction),
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+#[serde(untagged)]
+/// Result of the successful action.
+pub enum ActionResult {
+    #[serde(rename_all = "camelCase")]
+    Call { gas_used: String, output: String },
+    #[serde(rename_all = "camelCase")]
+    Create {
+        gas_used: String,
+        code: String,
+        address: Address,
+    },
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+/// Trace of a single action, `trace_transaction` returns these in the execution order.
+pub struct ActionTrace {
+    pub action: TraceAction,
+    /// `None` if the action failed
+    pub result: Option<ActionResult>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Number of the direct nested actions
+    pub subtraces: usize,
+    /// Indices of the action and its parents among the nested actions of their parents
+    pub trace_address: Vec<usize>,
+    /// `call`, `create` or `suicide`
+    #[serde(rename = "type")]
+    pub action_type: String,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+/// Result of `trace_replayTransaction` with all the trace types.
+pub struct TraceResults {
+    pub output: String,
+    pub trace: Vec<ActionTrace>,
+    pub vm_trace: Option<VMTrace>,
+    pub state_diff: BTreeMap<Address, AccountDiff>,
+}
+
 #[derive(serde::Serialize, serde::Deserialize, Debug)]
 pub struct TracedCall {
     pub vm_trace: Option<VMTrace>,
     pub full_trace_data: Vec<FullTraceData>,
     pub used_gas: u64,
+    #[serde(default)]
+    pub logs: Vec<super::Log>,
+    /// Solidity source of the revert, if the contract artifacts are provided.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub revert_location: Option<SourceLocation>,
 }
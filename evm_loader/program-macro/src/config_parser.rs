@@ -12,6 +12,7 @@ use syn::{
 #[derive(Deserialize)]
 pub struct NetSpecificConfig {
     pub chain_id: u64,
+    pub evm_spec: String,
     pub operators_whitelist: Vec<String>,
     pub token_mint: TokenMint,
 }
@@ -33,10 +34,26 @@ impl Parse for NetSpecificConfig {
                 format!("{} should be a valid path", file_path.display()),
             )
         })?;
-        toml::from_slice(&file_contents).map_err(|e| syn::Error::new(input.span(), e.to_string()))
+        let config: Self = toml::from_slice(&file_contents)
+            .map_err(|e| syn::Error::new(input.span(), e.to_string()))?;
+
+        if !EVM_SPECS.contains(&config.evm_spec.as_str()) {
+            return Err(syn::Error::new(
+                input.span(),
+                format!(
+                    "Unknown evm_spec {}, expected one of {EVM_SPECS:?}",
+                    config.evm_spec
+                ),
+            ));
+        }
+
+        Ok(config)
     }
 }
 
+/// Variants of `evm_loader::evm::Spec`
+const EVM_SPECS: [&str; 5] = ["Istanbul", "Berlin", "London", "Shanghai", "Cancun"];
+
 #[derive(Deserialize)]
 pub struct TokenMint {
     pub neon_token_mint: String,
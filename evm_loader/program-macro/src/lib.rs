@@ -138,6 +138,7 @@ pub fn declare_param_id(tokens: TokenStream) -> TokenStream {
 pub fn net_specific_config_parser(tokens: TokenStream) -> TokenStream {
     let NetSpecificConfig {
         chain_id,
+        evm_spec,
         operators_whitelist,
         token_mint: TokenMint {
             neon_token_mint,
@@ -145,10 +146,16 @@ pub fn net_specific_config_parser(tokens: TokenStream) -> TokenStream {
         },
     } = parse_macro_input!(tokens as NetSpecificConfig);
 
+    let evm_spec_variant: Ident = syn::parse_str(&evm_spec).expect("evm_spec is validated");
+
     quote! {
         /// Supported CHAIN_ID value for transactions
         pub const CHAIN_ID: u64 = #chain_id;
 
+        /// EVM hardfork of the network
+        pub const EVM_SPEC: crate::evm::Spec = crate::evm::Spec::#evm_spec_variant;
+        neon_elf_param!(NEON_EVM_SPEC, #evm_spec);
+
         operators_whitelist![#(#operators_whitelist),*];
 
         /// Token Mint ID
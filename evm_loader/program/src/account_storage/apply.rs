@@ -5,6 +5,7 @@ use std::convert::TryInto;
 use ethnum::U256;
 use solana_program::entrypoint::{ProgramResult, MAX_PERMITTED_DATA_INCREASE};
 use solana_program::instruction::Instruction;
+use solana_program::log::sol_log_data;
 use solana_program::program::{invoke, invoke_signed_unchecked};
 use solana_program::program_error::ProgramError;
 use solana_program::rent::Rent;
@@ -131,6 +132,13 @@ impl<'a> ProgramAccountStorage<'a> {
 
                     self.delete_account(address)?;
                 }
+                Action::EvmLog {
+                    address,
+                    topics,
+                    data,
+                } => {
+                    Self::emit_log(&address, &topics, &data);
+                }
                 Action::ExternalInstruction {
                     program_id,
                     accounts,
@@ -163,6 +171,22 @@ impl<'a> ProgramAccountStorage<'a> {
         Ok(AccountsReadiness::Ready)
     }
 
+    /// `LOGn` record in the format expected by the indexer
+    fn emit_log(address: &Address, topics: &[[u8; 32]], data: &[u8]) {
+        const MNEMONICS: [&[u8]; 5] = [b"LOG0", b"LOG1", b"LOG2", b"LOG3", b"LOG4"];
+
+        let count = [u8::try_from(topics.len()).expect("LOG has at most 4 topics")];
+
+        let mut fields: Vec<&[u8]> = Vec::with_capacity(topics.len() + 4);
+        fields.push(MNEMONICS[topics.len()]);
+        fields.push(address.as_bytes());
+        fields.push(&count);
+        fields.extend(topics.iter().map(<[u8; 32]>::as_slice));
+        fields.push(data);
+
+        sol_log_data(&fields);
+    }
+
     fn rearrange_actions(actions: Vec<Action>) -> Vec<Action> {
         // Find all the account addresses which are scheduled to EvmSelfDestruct
         let accounts_to_destroy: std::collections::HashSet<_> = actions
@@ -178,11 +202,12 @@ impl<'a> ProgramAccountStorage<'a> {
         let mut evm_self_destruct_actions = Vec::new();
         for action in actions {
             match action {
-                // We always apply ExternalInstruction for Solana accounts
-                // and NeonTransfer + NeonWithdraw
+                // We always apply ExternalInstruction for Solana accounts,
+                // NeonTransfer + NeonWithdraw and emit logs
                 Action::ExternalInstruction { .. }
                 | Action::NeonTransfer { .. }
-                | Action::NeonWithdraw { .. } => {
+                | Action::NeonWithdraw { .. }
+                | Action::EvmLog { .. } => {
                     rearranged_actions.push(action);
                 }
                 // We remove EvmSetStorage|EvmIncrementNonce|EvmSetCode
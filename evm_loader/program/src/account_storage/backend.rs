@@ -34,6 +34,11 @@ impl<'a> AccountStorage for ProgramAccountStorage<'a> {
             .expect("Timestamp is positive")
     }
 
+    fn block_coinbase(&self) -> Address {
+        // Solana has no block miner
+        Address::default()
+    }
+
     fn block_hash(&self, slot: u64) -> [u8; 32] {
         let slot_hashes_account = self
             .solana_accounts
@@ -143,4 +148,8 @@ impl<'a> AccountStorage for ProgramAccountStorage<'a> {
     fn chain_id(&self) -> u64 {
         crate::config::CHAIN_ID
     }
+
+    fn spec(&self) -> crate::evm::Spec {
+        crate::config::EVM_SPEC
+    }
 }
@@ -61,8 +61,12 @@ pub trait AccountStorage {
     fn block_timestamp(&self) -> U256;
     /// Get block hash
     fn block_hash(&self, number: u64) -> [u8; 32];
+    /// Get block miner address
+    fn block_coinbase(&self) -> Address;
     /// Get chain id
     fn chain_id(&self) -> u64;
+    /// Get EVM hardfork
+    fn spec(&self) -> crate::evm::Spec;
 
     /// Check if ethereum account exists
     fn exists(&self, address: &Address) -> bool;
@@ -77,6 +81,10 @@ pub trait AccountStorage {
     fn code_hash(&self, address: &Address) -> [u8; 32];
     /// Get code data
     fn code(&self, address: &Address) -> crate::evm::Buffer;
+    /// Get valid jump destinations of the code
+    fn jump_destinations(&self, code: &[u8]) -> crate::evm::JumpDestinations {
+        crate::evm::JumpDestinations::new(code)
+    }
     /// Get contract generation
     fn generation(&self, address: &Address) -> u32;
 
@@ -77,6 +77,9 @@ pub enum Error {
     #[error("Unknown extension method selector {1:?}, contract {0}")]
     UnknownPrecompileMethodSelector(Address, [u8; 4]),
 
+    #[error("EVM precompile {0} - invalid input")]
+    PrecompileInvalidInput(&'static str),
+
     #[error("Insufficient balance for transfer, account = {0}, required = {1}")]
     InsufficientBalance(Address, U256),
 
@@ -128,6 +131,9 @@ pub enum Error {
     #[error("New contract code size exceeds 24kb (EIP-170), contract = {0}, size = {1}")]
     ContractCodeSizeLimit(Address, usize),
 
+    #[error("Init code size exceeds 48kb (EIP-3860), contract = {0}, size = {1}")]
+    InitCodeSizeLimit(Address, usize),
+
     #[error("Checked Integer Math Overflow")]
     IntegerOverflow,
 
@@ -143,6 +149,29 @@ pub enum Error {
 
 pub type Result<T> = std::result::Result<T, Error>;
 
+impl Error {
+    /// Geth error of the exceptional halt, `None` if Geth has no equivalent
+    #[must_use]
+    pub const fn halt_reason(&self) -> Option<&'static str> {
+        Some(match self {
+            Error::OutOfGas(_, _) => "out of gas",
+            Error::StackOverflow => "stack limit reached",
+            Error::StackUnderflow => "stack underflow",
+            Error::InvalidJump(_, _) => "invalid jump destination",
+            Error::InvalidOpcode(_, _) | Error::UnknownOpcode(_, _) => "invalid opcode",
+            Error::StaticModeViolation(_) => "write protection",
+            Error::ReturnDataCopyOverflow(_, _) => "return data out of bounds",
+            Error::InsufficientBalance(_, _) => "insufficient balance for transfer",
+            Error::DeployToExistingAccount(_, _) => "contract address collision",
+            Error::EVMObjectFormatNotSupported(_) => "invalid code: must not begin with 0xef",
+            Error::ContractCodeSizeLimit(_, _) => "max code size exceeded",
+            Error::InitCodeSizeLimit(_, _) => "max initcode size exceeded",
+            Error::NonceOverflow(_) => "nonce uint64 overflow",
+            _ => return None,
+        })
+    }
+}
+
 impl From<Error> for ProgramError {
     fn from(e: Error) -> Self {
         solana_program::msg!("{}", e);
@@ -0,0 +1,91 @@
+use serde::{Deserialize, Serialize};
+
+const JUMPDEST: u8 = 0x5B;
+const PUSH1: u8 = 0x60;
+const PUSH32: u8 = 0x7F;
+
+/// Bitmap of valid jump destinations.
+/// A `JUMPDEST` byte inside `PUSH` immediate data is not a valid destination.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct JumpDestinations {
+    #[serde(with = "serde_bytes")]
+    bitmap: Vec<u8>,
+}
+
+impl JumpDestinations {
+    #[must_use]
+    pub fn new(code: &[u8]) -> Self {
+        let mut bitmap = vec![0_u8; (code.len() + 7) / 8];
+
+        let mut pc = 0_usize;
+        while pc < code.len() {
+            let opcode = code[pc];
+
+            if opcode == JUMPDEST {
+                bitmap[pc / 8] |= 1 << (pc % 8);
+            }
+
+            if (PUSH1..=PUSH32).contains(&opcode) {
+                pc += usize::from(opcode - PUSH1) + 1;
+            }
+
+            pc += 1;
+        }
+
+        Self { bitmap }
+    }
+
+    #[must_use]
+    pub fn is_valid(&self, pc: usize) -> bool {
+        self.bitmap
+            .get(pc / 8)
+            .map_or(false, |byte| (byte & (1 << (pc % 8))) != 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::JumpDestinations;
+
+    #[test]
+    fn jumpdest() {
+        // JUMPDEST STOP JUMPDEST
+        let destinations = JumpDestinations::new(&[0x5B, 0x00, 0x5B]);
+
+        assert!(destinations.is_valid(0));
+        assert!(!destinations.is_valid(1));
+        assert!(destinations.is_valid(2));
+        assert!(!destinations.is_valid(3));
+    }
+
+    #[test]
+    fn jumpdest_in_push_data() {
+        // PUSH1 0x5B JUMPDEST
+        let destinations = JumpDestinations::new(&[0x60, 0x5B, 0x5B]);
+
+        assert!(!destinations.is_valid(1));
+        assert!(destinations.is_valid(2));
+    }
+
+    #[test]
+    fn jumpdest_after_push32() {
+        // PUSH32 with 32 JUMPDEST bytes, then JUMPDEST
+        let mut code = vec![0x7F];
+        code.extend([0x5B; 32]);
+        code.push(0x5B);
+
+        let destinations = JumpDestinations::new(&code);
+
+        assert!((1..=32).all(|pc| !destinations.is_valid(pc)));
+        assert!(destinations.is_valid(33));
+    }
+
+    #[test]
+    fn truncated_push_data() {
+        // PUSH2 with one byte of data
+        let destinations = JumpDestinations::new(&[0x61, 0x5B]);
+
+        assert!(!destinations.is_valid(1));
+        assert!(!destinations.is_valid(2));
+    }
+}
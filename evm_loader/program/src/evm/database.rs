@@ -1,9 +1,10 @@
-use super::{Buffer, Context};
+use super::{Buffer, Context, JumpDestinations, Spec};
 use crate::{error::Result, types::Address};
 use ethnum::U256;
 
 pub trait Database {
     fn chain_id(&self) -> U256;
+    fn spec(&self) -> Spec;
 
     fn nonce(&self, address: &Address) -> Result<u64>;
     fn increment_nonce(&mut self, address: Address) -> Result<()>;
@@ -14,25 +15,49 @@ pub trait Database {
     fn code_size(&self, address: &Address) -> Result<usize>;
     fn code_hash(&self, address: &Address) -> Result<[u8; 32]>;
     fn code(&self, address: &Address) -> Result<Buffer>;
+    fn jump_destinations(&self, code: &Buffer) -> JumpDestinations;
     fn set_code(&mut self, address: Address, code: Buffer) -> Result<()>;
     fn selfdestruct(&mut self, address: Address) -> Result<()>;
 
     fn storage(&self, address: &Address, index: &U256) -> Result<[u8; 32]>;
+    /// Storage value at the beginning of the transaction
+    fn original_storage(&self, address: &Address, index: &U256) -> Result<[u8; 32]>;
     fn set_storage(&mut self, address: Address, index: U256, value: [u8; 32]) -> Result<()>;
 
+    /// EIP-1153: storage that is discarded at the end of the transaction
+    fn transient_storage(&self, address: &Address, index: &U256) -> Result<[u8; 32]>;
+    fn set_transient_storage(
+        &mut self,
+        address: Address,
+        index: U256,
+        value: [u8; 32],
+    ) -> Result<()>;
+
+    /// EIP-2929: marks the address as accessed, returns `true` if it was accessed before
+    fn access_address(&mut self, address: Address) -> bool;
+    /// EIP-2929: marks the storage slot as accessed, returns `true` if it was accessed before
+    fn access_storage(&mut self, address: Address, index: U256) -> bool;
+
     fn block_hash(&self, number: U256) -> Result<[u8; 32]>;
     fn block_number(&self) -> Result<U256>;
     fn block_timestamp(&self) -> Result<U256>;
+    fn block_coinbase(&self) -> Result<Address>;
+
+    /// Event log, emitted when the transaction is finalized
+    fn log(&mut self, address: Address, topics: &[[u8; 32]], data: &[u8]);
 
     fn snapshot(&mut self);
     fn revert_snapshot(&mut self);
     fn commit_snapshot(&mut self);
 
-    fn precompile_extension(
+    /// Precompiled contracts, see `crate::evm::Registry`
+    fn is_precompile(&self, address: &Address) -> bool;
+    fn precompile_gas(&self, address: &Address, data: &[u8]) -> u64;
+    fn call_precompile(
         &mut self,
         context: &Context,
         address: &Address,
         data: &[u8],
         is_static: bool,
-    ) -> Option<Result<Vec<u8>>>;
+    ) -> Result<Vec<u8>>;
 }
@@ -0,0 +1,814 @@
+/// Ethereum gas schedule (Istanbul and the later hardfork changes)
+/// <https://ethereum.github.io/yellowpaper/paper.pdf>, Appendix G
+use ethnum::U256;
+
+use super::{database::Database, utils::checked_next_multiple_of_32, Machine, Spec};
+use crate::{
+    error::{Error, Result},
+    types::{Address, Transaction},
+};
+
+pub const ZERO: u64 = 0;
+pub const JUMPDEST: u64 = 1;
+pub const BASE: u64 = 2;
+pub const VERYLOW: u64 = 3;
+pub const LOW: u64 = 5;
+pub const MID: u64 = 8;
+pub const HIGH: u64 = 10;
+
+pub const EXP: u64 = 10;
+pub const EXP_BYTE: u64 = 50;
+pub const SHA3: u64 = 30;
+pub const SHA3_WORD: u64 = 6;
+pub const COPY_WORD: u64 = 3;
+pub const MEMORY_WORD: u64 = 3;
+pub const QUAD_COEFF_DIV: u64 = 512;
+
+pub const BALANCE: u64 = 700;
+pub const EXTCODE: u64 = 700;
+pub const BLOCKHASH: u64 = 20;
+
+pub const SLOAD: u64 = 800;
+/// EIP-2929: warm access, also the price of TLOAD and TSTORE (EIP-1153)
+pub const WARM_STORAGE_READ: u64 = 100;
+pub const COLD_SLOAD: u64 = 2100;
+pub const COLD_ACCOUNT_ACCESS: u64 = 2600;
+pub const SSTORE_SET: u64 = 20000;
+pub const SSTORE_RESET: u64 = 5000;
+pub const SSTORE_CLEARS_REFUND: u64 = 15000;
+/// EIP-3529: `SSTORE_RESET - COLD_SLOAD + TX_ACCESS_LIST_STORAGE_KEY`
+pub const SSTORE_CLEARS_REFUND_LONDON: u64 = 4800;
+/// EIP-2200: SSTORE is not allowed with less than the call stipend left
+pub const SSTORE_SENTRY: u64 = 2300;
+
+pub const LOG: u64 = 375;
+pub const LOG_TOPIC: u64 = 375;
+pub const LOG_DATA: u64 = 8;
+
+pub const CREATE: u64 = 32000;
+pub const CODE_DEPOSIT: u64 = 200;
+pub const CALL: u64 = 700;
+pub const CALL_VALUE: u64 = 9000;
+pub const CALL_STIPEND: u64 = 2300;
+pub const NEW_ACCOUNT: u64 = 25000;
+pub const SELFDESTRUCT: u64 = 5000;
+pub const SELFDESTRUCT_REFUND: u64 = 24000;
+
+pub const TRANSACTION: u64 = 21000;
+pub const TRANSACTION_CREATE: u64 = 32000;
+pub const TX_DATA_ZERO: u64 = 4;
+pub const TX_DATA_NON_ZERO: u64 = 16;
+/// EIP-2930
+pub const TX_ACCESS_LIST_ADDRESS: u64 = 2400;
+pub const TX_ACCESS_LIST_STORAGE_KEY: u64 = 1900;
+
+/// EIP-3860: limit and meter initcode
+pub const MAX_INITCODE_SIZE: usize = 2 * 24576;
+pub const INITCODE_WORD: u64 = 2;
+
+/// Refunds are capped by `gas_used / MAX_REFUND_QUOTIENT` at the end of the transaction
+pub const MAX_REFUND_QUOTIENT: u64 = 2;
+/// EIP-3529
+pub const MAX_REFUND_QUOTIENT_LONDON: u64 = 5;
+
+/// Gas charged before the opcode is executed.
+/// Dynamic parts (memory expansion, copying, storage, calls) are charged by the opcode itself.
+#[rustfmt::skip]
+pub const STATIC_COSTS: [u64; 256] = {
+    let mut costs = [ZERO; 256];
+
+    costs[0x01] = VERYLOW;  // ADD
+    costs[0x02] = LOW;      // MUL
+    costs[0x03] = VERYLOW;  // SUB
+    costs[0x04] = LOW;      // DIV
+    costs[0x05] = LOW;      // SDIV
+    costs[0x06] = LOW;      // MOD
+    costs[0x07] = LOW;      // SMOD
+    costs[0x08] = MID;      // ADDMOD
+    costs[0x09] = MID;      // MULMOD
+    costs[0x0A] = EXP;      // EXP
+    costs[0x0B] = LOW;      // SIGNEXTEND
+
+    let mut i = 0x10;
+    while i <= 0x1D {
+        costs[i] = VERYLOW; // LT .. SAR
+        i += 1;
+    }
+
+    costs[0x20] = SHA3;
+
+    costs[0x30] = BASE;     // ADDRESS
+    costs[0x31] = BALANCE;
+    costs[0x32] = BASE;     // ORIGIN
+    costs[0x33] = BASE;     // CALLER
+    costs[0x34] = BASE;     // CALLVALUE
+    costs[0x35] = VERYLOW;  // CALLDATALOAD
+    costs[0x36] = BASE;     // CALLDATASIZE
+    costs[0x37] = VERYLOW;  // CALLDATACOPY
+    costs[0x38] = BASE;     // CODESIZE
+    costs[0x39] = VERYLOW;  // CODECOPY
+    costs[0x3A] = BASE;     // GASPRICE
+    costs[0x3B] = EXTCODE;  // EXTCODESIZE
+    costs[0x3C] = EXTCODE;  // EXTCODECOPY
+    costs[0x3D] = BASE;     // RETURNDATASIZE
+    costs[0x3E] = VERYLOW;  // RETURNDATACOPY
+    costs[0x3F] = EXTCODE;  // EXTCODEHASH
+    costs[0x40] = BLOCKHASH;
+    costs[0x41] = BASE;     // COINBASE
+    costs[0x42] = BASE;     // TIMESTAMP
+    costs[0x43] = BASE;     // NUMBER
+    costs[0x44] = BASE;     // DIFFICULTY
+    costs[0x45] = BASE;     // GASLIMIT
+    costs[0x46] = BASE;     // CHAINID
+    costs[0x47] = LOW;      // SELFBALANCE
+    costs[0x48] = BASE;     // BASEFEE
+
+    costs[0x50] = BASE;     // POP
+    costs[0x51] = VERYLOW;  // MLOAD
+    costs[0x52] = VERYLOW;  // MSTORE
+    costs[0x53] = VERYLOW;  // MSTORE8
+    costs[0x54] = SLOAD;
+    costs[0x56] = MID;      // JUMP
+    costs[0x57] = HIGH;     // JUMPI
+    costs[0x58] = BASE;     // PC
+    costs[0x59] = BASE;     // MSIZE
+    costs[0x5A] = BASE;     // GAS
+    costs[0x5B] = JUMPDEST;
+    costs[0x5C] = WARM_STORAGE_READ; // TLOAD
+    costs[0x5D] = WARM_STORAGE_READ; // TSTORE
+    costs[0x5E] = VERYLOW;  // MCOPY
+    costs[0x5F] = BASE;     // PUSH0
+
+    let mut i = 0x60;
+    while i <= 0x9F {
+        costs[i] = VERYLOW; // PUSH1 .. PUSH32, DUP1 .. DUP16, SWAP1 .. SWAP16
+        i += 1;
+    }
+
+    costs[0xA0] = LOG;
+    costs[0xA1] = LOG + LOG_TOPIC;
+    costs[0xA2] = LOG + 2 * LOG_TOPIC;
+    costs[0xA3] = LOG + 3 * LOG_TOPIC;
+    costs[0xA4] = LOG + 4 * LOG_TOPIC;
+
+    costs[0xF0] = CREATE;
+    costs[0xF1] = CALL;
+    costs[0xF2] = CALL;     // CALLCODE
+    costs[0xF4] = CALL;     // DELEGATECALL
+    costs[0xF5] = CREATE;   // CREATE2
+    costs[0xFA] = CALL;     // STATICCALL
+    costs[0xFF] = SELFDESTRUCT;
+
+    costs
+};
+
+/// EIP-2929: state access opcodes are charged as warm access,
+/// cold access surcharge is charged by the opcode itself.
+#[rustfmt::skip]
+pub const STATIC_COSTS_BERLIN: [u64; 256] = {
+    let mut costs = STATIC_COSTS;
+
+    costs[0x31] = WARM_STORAGE_READ; // BALANCE
+    costs[0x3B] = WARM_STORAGE_READ; // EXTCODESIZE
+    costs[0x3C] = WARM_STORAGE_READ; // EXTCODECOPY
+    costs[0x3F] = WARM_STORAGE_READ; // EXTCODEHASH
+    costs[0x54] = WARM_STORAGE_READ; // SLOAD
+    costs[0xF1] = WARM_STORAGE_READ; // CALL
+    costs[0xF2] = WARM_STORAGE_READ; // CALLCODE
+    costs[0xF4] = WARM_STORAGE_READ; // DELEGATECALL
+    costs[0xFA] = WARM_STORAGE_READ; // STATICCALL
+
+    costs
+};
+
+#[must_use]
+pub fn static_costs(spec: Spec) -> &'static [u64; 256] {
+    if spec.is_enabled(Spec::Berlin) {
+        &STATIC_COSTS_BERLIN
+    } else {
+        &STATIC_COSTS
+    }
+}
+
+#[must_use]
+pub fn words(length: usize) -> u64 {
+    (length as u64).saturating_add(31) / 32
+}
+
+#[must_use]
+pub fn memory_cost(size_in_words: u64) -> u64 {
+    let linear = size_in_words.saturating_mul(MEMORY_WORD);
+    let quadratic = size_in_words.saturating_mul(size_in_words) / QUAD_COEFF_DIV;
+
+    linear.saturating_add(quadratic)
+}
+
+#[must_use]
+pub fn copy_cost(length: usize) -> u64 {
+    words(length).saturating_mul(COPY_WORD)
+}
+
+/// EIP-150: all but one 64th of the available gas
+#[must_use]
+pub fn all_but_one_64th(gas: u64) -> u64 {
+    gas - gas / 64
+}
+
+/// EIP-3860: initcode is charged per word starting from Shanghai
+#[must_use]
+pub fn initcode_cost(spec: Spec, length: usize) -> u64 {
+    if spec.is_enabled(Spec::Shanghai) {
+        words(length).saturating_mul(INITCODE_WORD)
+    } else {
+        0
+    }
+}
+
+#[must_use]
+pub fn intrinsic_gas(trx: &Transaction, spec: Spec) -> u64 {
+    let zero_bytes = trx.call_data.iter().filter(|b| **b == 0).count() as u64;
+    let non_zero_bytes = (trx.call_data.len() as u64) - zero_bytes;
+
+    let mut gas = TRANSACTION;
+    if trx.target.is_none() {
+        gas = gas
+            .saturating_add(TRANSACTION_CREATE)
+            .saturating_add(initcode_cost(spec, trx.call_data.len()));
+    }
+
+    for item in &trx.access_list {
+        let storage_keys = item.storage_keys.len() as u64;
+
+        gas = gas
+            .saturating_add(TX_ACCESS_LIST_ADDRESS)
+            .saturating_add(storage_keys.saturating_mul(TX_ACCESS_LIST_STORAGE_KEY));
+    }
+
+    gas.saturating_add(zero_bytes.saturating_mul(TX_DATA_ZERO))
+        .saturating_add(non_zero_bytes.saturating_mul(TX_DATA_NON_ZERO))
+}
+
+/// EIP-2200 gas and refund change of SSTORE, the cold access surcharge is not included
+#[must_use]
+pub fn sstore_cost(
+    spec: Spec,
+    original: &[u8; 32],
+    current: &[u8; 32],
+    new: &[u8; 32],
+) -> (u64, i64) {
+    const ZERO_VALUE: [u8; 32] = [0_u8; 32];
+
+    // EIP-2929 and EIP-3529 reprice the constants, the algorithm stays the same
+    let (sload, sstore_reset, clears_refund) = match spec {
+        Spec::Istanbul => (SLOAD, SSTORE_RESET, SSTORE_CLEARS_REFUND),
+        Spec::Berlin => (
+            WARM_STORAGE_READ,
+            SSTORE_RESET - COLD_SLOAD,
+            SSTORE_CLEARS_REFUND,
+        ),
+        _ => (
+            WARM_STORAGE_READ,
+            SSTORE_RESET - COLD_SLOAD,
+            SSTORE_CLEARS_REFUND_LONDON,
+        ),
+    };
+    let clears_refund = i64::try_from(clears_refund).unwrap_or(i64::MAX);
+
+    if current == new {
+        return (sload, 0);
+    }
+
+    if original == current {
+        if original == &ZERO_VALUE {
+            return (SSTORE_SET, 0);
+        }
+
+        let refund = if new == &ZERO_VALUE { clears_refund } else { 0 };
+        return (sstore_reset, refund);
+    }
+
+    let mut refund = 0_i64;
+    if original != &ZERO_VALUE {
+        if current == &ZERO_VALUE {
+            refund -= clears_refund;
+        } else if new == &ZERO_VALUE {
+            refund += clears_refund;
+        }
+    }
+
+    if original == new {
+        let restored = if original == &ZERO_VALUE {
+            SSTORE_SET - sload
+        } else {
+            sstore_reset - sload
+        };
+        refund += i64::try_from(restored).unwrap_or(i64::MAX);
+    }
+
+    (sload, refund)
+}
+
+impl<B: Database> Machine<B> {
+    #[inline]
+    pub(super) fn use_gas(&mut self, amount: u64) -> Result<()> {
+        if amount > self.gas_left {
+            return Err(Error::OutOfGas(self.gas_left.into(), amount.into()));
+        }
+
+        self.gas_left -= amount;
+
+        Ok(())
+    }
+
+    /// Charge memory expansion cost and grow the memory to cover `offset..offset+length`
+    pub(super) fn expand_memory(&mut self, offset: usize, length: usize) -> Result<()> {
+        if length == 0 {
+            return Ok(());
+        }
+
+        let new_size = offset
+            .checked_add(length)
+            .and_then(checked_next_multiple_of_32)
+            .ok_or(Error::MemoryAccessOutOfLimits(offset, length))?;
+
+        let old_size = self.memory.size();
+        if new_size <= old_size {
+            return Ok(());
+        }
+
+        let cost = memory_cost(words(new_size)) - memory_cost(words(old_size));
+        self.use_gas(cost)?;
+
+        self.memory.grow(offset, length)
+    }
+
+    /// EIP-2200: Structured Definitions for Net Gas Metering
+    pub(super) fn sstore_gas(
+        &mut self,
+        original: &[u8; 32],
+        current: &[u8; 32],
+        new: &[u8; 32],
+    ) -> Result<()> {
+        if self.gas_left <= SSTORE_SENTRY {
+            return Err(Error::OutOfGas(self.gas_left.into(), SSTORE_SENTRY.into()));
+        }
+
+        let (gas, refund) = sstore_cost(self.spec, original, current, new);
+        self.use_gas(gas)?;
+        self.gas_refund = self.gas_refund.saturating_add(refund);
+
+        Ok(())
+    }
+
+    /// Gas that is passed to the sub-call, already deducted from the current frame
+    pub(super) fn call_gas(&mut self, requested: U256) -> u64 {
+        let available = all_but_one_64th(self.gas_left);
+        let gas = u64::try_from(requested).map_or(available, |r| r.min(available));
+
+        self.gas_left -= gas;
+
+        gas
+    }
+
+    /// EIP-161: account is empty when it has no code, zero nonce and zero balance
+    pub(super) fn account_exists(backend: &B, address: &Address) -> Result<bool> {
+        let exists = (backend.nonce(address)? != 0)
+            || (backend.balance(address)? != U256::ZERO)
+            || (backend.code_size(address)? != 0);
+
+        Ok(exists)
+    }
+
+    /// EIP-2929: charge the cold account access surcharge on top of the warm access cost
+    pub(super) fn access_address(&mut self, backend: &mut B, address: &Address) -> Result<()> {
+        if self.is_cold_address(backend, address) {
+            self.use_gas(COLD_ACCOUNT_ACCESS - WARM_STORAGE_READ)?;
+        }
+
+        Ok(())
+    }
+
+    /// EIP-2929: mark the address as accessed, returns `true` on the first access
+    pub(super) fn is_cold_address(&self, backend: &mut B, address: &Address) -> bool {
+        if !self.spec.is_enabled(Spec::Berlin) || backend.is_precompile(address) {
+            return false;
+        }
+
+        !backend.access_address(*address)
+    }
+
+    /// EIP-2929: mark the storage slot as accessed, returns `true` on the first access
+    pub(super) fn is_cold_storage(&self, backend: &mut B, address: &Address, index: U256) -> bool {
+        if !self.spec.is_enabled(Spec::Berlin) {
+            return false;
+        }
+
+        !backend.access_storage(*address, index)
+    }
+
+    /// EIP-3860: initcode size limit and per word cost
+    pub(super) fn use_initcode_gas(&mut self, length: usize) -> Result<()> {
+        if self.spec.is_enabled(Spec::Shanghai) && (length > MAX_INITCODE_SIZE) {
+            return Err(Error::InitCodeSizeLimit(self.context.contract, length));
+        }
+
+        self.use_gas(initcode_cost(self.spec, length))
+    }
+
+    /// EIP-3529: no refund for SELFDESTRUCT since London
+    pub(super) fn selfdestruct_refund(&mut self) {
+        if !self.spec.is_enabled(Spec::London) {
+            self.add_refund(SELFDESTRUCT_REFUND);
+        }
+    }
+
+    pub(super) fn add_refund(&mut self, amount: u64) {
+        let amount = i64::try_from(amount).unwrap_or(i64::MAX);
+        self.gas_refund = self.gas_refund.saturating_add(amount);
+    }
+
+    /// Gas consumed by the current step, used only for tracing
+    #[cfg(feature = "tracing")]
+    pub(super) fn step_gas_used(&self) -> u64 {
+        self.step_gas.saturating_sub(self.gas_left)
+    }
+
+    /// Gas consumed by the current frame, used only for tracing
+    #[cfg(feature = "tracing")]
+    pub(super) fn frame_gas_used(&self) -> u64 {
+        self.gas_limit.saturating_sub(self.gas_left)
+    }
+
+    /// Transaction gas usage with the refund applied.
+    /// Meaningful only for the top level frame after the execution is finished.
+    #[must_use]
+    pub fn used_gas(&self) -> u64 {
+        let used = self.gas_limit.saturating_sub(self.gas_left);
+
+        let refund = u64::try_from(self.gas_refund).unwrap_or(0);
+        let quotient = if self.spec.is_enabled(Spec::London) {
+            MAX_REFUND_QUOTIENT_LONDON
+        } else {
+            MAX_REFUND_QUOTIENT
+        };
+        let refund = refund.min(used / quotient);
+
+        used - refund
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ethnum::U256;
+
+    use std::collections::{BTreeMap, BTreeSet};
+
+    use super::{
+        all_but_one_64th, copy_cost, intrinsic_gas, memory_cost, sstore_cost, static_costs, words,
+        VERYLOW,
+    };
+    use crate::error::Result;
+    use crate::evm::{
+        database::Database, Buffer, Context, ExitStatus, JumpDestinations, Machine, Spec,
+    };
+    use crate::types::{AccessListItem, Address, Transaction};
+
+    fn word(value: u8) -> [u8; 32] {
+        let mut word = [0_u8; 32];
+        word[31] = value;
+        word
+    }
+
+    /// Gas and refund of the SSTOREs to the same slot, `values` are the stored values
+    fn sstores(spec: Spec, original: u8, values: &[u8]) -> (u64, i64) {
+        let original = word(original);
+        let mut current = original;
+
+        let mut gas = 0;
+        let mut refund = 0;
+        for value in values {
+            let new = word(*value);
+            let (cost, change) = sstore_cost(spec, &original, &current, &new);
+            gas += cost;
+            refund += change;
+            current = new;
+        }
+
+        (gas, refund)
+    }
+
+    /// EIP-2200 test cases, the used gas without the PUSH1 costs
+    #[test]
+    fn eip2200_net_gas_metering() {
+        let cases: [(u8, &[u8], u64, i64); 17] = [
+            (0, &[0, 0], 1612, 0),
+            (0, &[0, 1], 20812, 0),
+            (0, &[1, 0], 20812, 19200),
+            (0, &[1, 2], 20812, 0),
+            (0, &[1, 1], 20812, 0),
+            (1, &[0, 0], 5812, 15000),
+            (1, &[0, 1], 5812, 4200),
+            (1, &[0, 2], 5812, 0),
+            (1, &[2, 0], 5812, 15000),
+            (1, &[2, 3], 5812, 0),
+            (1, &[2, 1], 5812, 4200),
+            (1, &[2, 2], 5812, 0),
+            (1, &[1, 0], 5812, 15000),
+            (1, &[1, 2], 5812, 0),
+            (1, &[1, 1], 1612, 0),
+            (0, &[1, 0, 1], 40818, 19200),
+            (1, &[0, 1, 0], 10818, 19200),
+        ];
+
+        for (original, values, used_gas, refund) in cases {
+            let pushes = 2 * VERYLOW * values.len() as u64;
+            assert_eq!(
+                sstores(Spec::Istanbul, original, values),
+                (used_gas - pushes, refund),
+                "original {original} values {values:?}"
+            );
+        }
+    }
+
+    /// EIP-3529 test cases with the warm slot, the used gas without the PUSH1 costs
+    #[test]
+    fn eip3529_refunds() {
+        let cases: [(u8, &[u8], u64, i64); 8] = [
+            (0, &[0, 0], 212, 0),
+            (0, &[0, 1], 20112, 0),
+            (0, &[1, 0], 20112, 19900),
+            (1, &[0, 0], 3012, 4800),
+            (1, &[0, 1], 3012, 2800),
+            (1, &[1, 1], 212, 0),
+            (0, &[1, 0, 1], 40118, 19900),
+            (1, &[0, 1, 0], 5918, 7600),
+        ];
+
+        for (original, values, used_gas, refund) in cases {
+            let pushes = 2 * VERYLOW * values.len() as u64;
+            assert_eq!(
+                sstores(Spec::London, original, values),
+                (used_gas - pushes, refund),
+                "original {original} values {values:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn memory_expansion() {
+        assert_eq!(words(0), 0);
+        assert_eq!(words(1), 1);
+        assert_eq!(words(33), 2);
+
+        // 3 * words + words ^ 2 / 512
+        assert_eq!(memory_cost(1), 3);
+        assert_eq!(memory_cost(32), 98);
+        assert_eq!(memory_cost(1024), 5120);
+
+        assert_eq!(copy_cost(65), 9);
+    }
+
+    #[test]
+    fn eip150_call_gas() {
+        assert_eq!(all_but_one_64th(6400), 6300);
+        assert_eq!(all_but_one_64th(63), 63);
+    }
+
+    #[test]
+    fn eip2929_static_costs() {
+        const SLOAD_OPCODE: usize = 0x54;
+        const CALL_OPCODE: usize = 0xF1;
+
+        assert_eq!(static_costs(Spec::Istanbul)[SLOAD_OPCODE], 800);
+        assert_eq!(static_costs(Spec::Istanbul)[CALL_OPCODE], 700);
+        assert_eq!(static_costs(Spec::Berlin)[SLOAD_OPCODE], 100);
+        assert_eq!(static_costs(Spec::Berlin)[CALL_OPCODE], 100);
+    }
+
+    #[test]
+    fn intrinsic_gas_of_transactions() {
+        let transfer = Transaction {
+            target: Some(Address::default()),
+            ..Transaction::default()
+        };
+        assert_eq!(intrinsic_gas(&transfer, Spec::London), 21000);
+
+        let call = Transaction {
+            target: Some(Address::default()),
+            call_data: Buffer::new(&[0, 1, 0, 2]),
+            ..Transaction::default()
+        };
+        assert_eq!(intrinsic_gas(&call, Spec::London), 21000 + 2 * 4 + 2 * 16);
+
+        // EIP-2930: 2400 per address, 1900 per storage key
+        let access_list = Transaction {
+            target: Some(Address::default()),
+            access_list: vec![AccessListItem {
+                address: Address::default(),
+                storage_keys: vec![U256::ZERO, U256::ONE],
+            }],
+            ..Transaction::default()
+        };
+        assert_eq!(
+            intrinsic_gas(&access_list, Spec::Berlin),
+            21000 + 2400 + 2 * 1900
+        );
+    }
+
+    #[test]
+    fn eip3860_initcode_gas() {
+        let create = Transaction {
+            target: None,
+            call_data: Buffer::new(&[0xFF; 64]),
+            ..Transaction::default()
+        };
+
+        assert_eq!(intrinsic_gas(&create, Spec::London), 53000 + 64 * 16);
+        assert_eq!(
+            intrinsic_gas(&create, Spec::Shanghai),
+            53000 + 64 * 16 + 2 * 2
+        );
+    }
+
+    /// Accounts without storage, enough to run the calls and creations
+    #[derive(Default)]
+    struct Backend {
+        nonces: BTreeMap<Address, u64>,
+        balances: BTreeMap<Address, U256>,
+        codes: BTreeMap<Address, Vec<u8>>,
+        accessed: BTreeSet<Address>,
+    }
+
+    impl Database for Backend {
+        fn chain_id(&self) -> U256 {
+            U256::ONE
+        }
+        fn spec(&self) -> Spec {
+            Spec::LATEST
+        }
+        fn nonce(&self, address: &Address) -> Result<u64> {
+            Ok(self.nonces.get(address).copied().unwrap_or_default())
+        }
+        fn increment_nonce(&mut self, address: Address) -> Result<()> {
+            *self.nonces.entry(address).or_default() += 1;
+            Ok(())
+        }
+        fn balance(&self, address: &Address) -> Result<U256> {
+            Ok(self.balances.get(address).copied().unwrap_or_default())
+        }
+        fn transfer(&mut self, source: Address, target: Address, value: U256) -> Result<()> {
+            *self.balances.entry(source).or_default() -= value;
+            *self.balances.entry(target).or_default() += value;
+            Ok(())
+        }
+        fn code_size(&self, address: &Address) -> Result<usize> {
+            Ok(self.codes.get(address).map_or(0, Vec::len))
+        }
+        fn code_hash(&self, _address: &Address) -> Result<[u8; 32]> {
+            Ok([0; 32])
+        }
+        fn code(&self, address: &Address) -> Result<Buffer> {
+            Ok(Buffer::new(
+                self.codes.get(address).map_or(&[][..], Vec::as_slice),
+            ))
+        }
+        fn jump_destinations(&self, code: &Buffer) -> JumpDestinations {
+            JumpDestinations::new(code)
+        }
+        fn set_code(&mut self, address: Address, code: Buffer) -> Result<()> {
+            self.codes.insert(address, code.to_vec());
+            Ok(())
+        }
+        fn selfdestruct(&mut self, _address: Address) -> Result<()> {
+            Ok(())
+        }
+        fn storage(&self, _address: &Address, _index: &U256) -> Result<[u8; 32]> {
+            Ok([0; 32])
+        }
+        fn original_storage(&self, _address: &Address, _index: &U256) -> Result<[u8; 32]> {
+            Ok([0; 32])
+        }
+        fn set_storage(&mut self, _address: Address, _index: U256, _value: [u8; 32]) -> Result<()> {
+            Ok(())
+        }
+        fn transient_storage(&self, _address: &Address, _index: &U256) -> Result<[u8; 32]> {
+            Ok([0; 32])
+        }
+        fn set_transient_storage(
+            &mut self,
+            _address: Address,
+            _index: U256,
+            _value: [u8; 32],
+        ) -> Result<()> {
+            Ok(())
+        }
+        fn access_address(&mut self, address: Address) -> bool {
+            !self.accessed.insert(address)
+        }
+        fn access_storage(&mut self, _address: Address, _index: U256) -> bool {
+            true
+        }
+        fn block_hash(&self, _number: U256) -> Result<[u8; 32]> {
+            Ok([0; 32])
+        }
+        fn block_number(&self) -> Result<U256> {
+            Ok(U256::ZERO)
+        }
+        fn block_timestamp(&self) -> Result<U256> {
+            Ok(U256::ZERO)
+        }
+        fn block_coinbase(&self) -> Result<Address> {
+            Ok(Address::default())
+        }
+        fn log(&mut self, _address: Address, _topics: &[[u8; 32]], _data: &[u8]) {}
+        fn snapshot(&mut self) {}
+        fn revert_snapshot(&mut self) {}
+        fn commit_snapshot(&mut self) {}
+        fn is_precompile(&self, _address: &Address) -> bool {
+            false
+        }
+        fn precompile_gas(&self, _address: &Address, _data: &[u8]) -> u64 {
+            0
+        }
+        fn call_precompile(
+            &mut self,
+            _context: &Context,
+            _address: &Address,
+            _data: &[u8],
+            _is_static: bool,
+        ) -> Result<Vec<u8>> {
+            unreachable!()
+        }
+    }
+
+    /// Runs the contract `code` with 100000 gas, the contract has no balance.
+    /// The code returns the result of the call and the gas left after it.
+    fn run(backend: &mut Backend, contract: Address, code: &str) -> (U256, u64) {
+        let origin = Address::from([0x01; 20]);
+        backend.balances.insert(origin, U256::new(1_000_000));
+        backend.nonces.insert(contract, 1);
+        // PUSH1 0x00 MSTORE GAS PUSH1 0x20 MSTORE PUSH1 0x40 PUSH1 0x00 RETURN
+        let code = [
+            hex::decode(code).unwrap(),
+            hex::decode("6000525a60205260406000f3").unwrap(),
+        ];
+        backend.codes.insert(contract, code.concat());
+
+        let trx = Transaction {
+            nonce: 0,
+            gas_limit: U256::new(100_000),
+            target: Some(contract),
+            ..Transaction::default()
+        };
+        let mut machine = Machine::new(trx, origin, backend).unwrap();
+        let (status, _) = machine.execute(1_000, backend).unwrap();
+
+        match status {
+            ExitStatus::Return(value) => {
+                let result = U256::from_be_bytes(value[..32].try_into().unwrap());
+                let gas = U256::from_be_bytes(value[32..].try_into().unwrap());
+                (result, gas.as_u64())
+            }
+            status => panic!("unexpected exit status {status:?}"),
+        }
+    }
+
+    /// CALL with the value above the balance pushes 0 without entering the callee,
+    /// the frame keeps the forwarded gas and gets the stipend
+    #[test]
+    fn call_insufficient_balance() {
+        let contract = Address::from([0xCC; 20]);
+        let callee = Address::from([0xAA; 20]);
+        let mut backend = Backend::default();
+
+        // CALL(0xFFFF, 0xAA..AA, 1, 0, 0, 0, 0)
+        let code = format!(
+            "6000600060006000600173{}61fffff1",
+            hex::encode(callee.as_bytes())
+        );
+        let (result, gas) = run(&mut backend, contract, &code);
+
+        // 21000 intrinsic, 7 pushes, warm CALL, cold access, value transfer and new account
+        let call_cost = 100 + 2500 + 9000 + 25000;
+        // PUSH1 MSTORE with the memory expansion, GAS
+        let store_cost = 3 + 6 + 2;
+        assert_eq!(result, U256::ZERO);
+        assert_eq!(gas, 100_000 - 21000 - 7 * 3 - call_cost + 2300 - store_cost);
+        assert_eq!(backend.balance(&callee).unwrap(), U256::ZERO);
+    }
+
+    /// CREATE with the value above the balance pushes 0, the frame keeps the gas
+    /// and the nonce of the creator is not incremented
+    #[test]
+    fn create_insufficient_balance() {
+        let contract = Address::from([0xCC; 20]);
+        let mut backend = Backend::default();
+
+        // CREATE(1, 0, 0)
+        let (result, gas) = run(&mut backend, contract, "600060006001f0");
+
+        assert_eq!(result, U256::ZERO);
+        assert_eq!(gas, 100_000 - 21000 - 3 * 3 - 32000 - (3 + 6 + 2));
+        assert_eq!(backend.nonce(&contract).unwrap(), 1);
+    }
+}
@@ -60,9 +60,8 @@ impl Memory {
     }
 
     #[allow(dead_code)]
-    pub fn to_vec(&self) -> Vec<u8> {
-        let slice = unsafe { std::slice::from_raw_parts(self.data, self.size) };
-        slice.to_vec()
+    pub fn as_slice(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.data, self.size) }
     }
 
     #[inline]
@@ -113,6 +112,15 @@ impl Memory {
         self.size
     }
 
+    #[inline]
+    pub fn grow(&mut self, offset: usize, length: usize) -> Result<(), Error> {
+        if length == 0_usize {
+            return Ok(());
+        }
+
+        self.realloc(offset, length)
+    }
+
     pub fn read(&mut self, offset: usize, length: usize) -> Result<&[u8], Error> {
         if length == 0_usize {
             return Ok(&[]);
@@ -142,7 +150,7 @@ impl Memory {
     pub fn write_32(&mut self, offset: usize, value: &[u8; 32]) -> Result<(), Error> {
         tracing_event!(super::tracing::Event::MemorySet {
             offset,
-            data: value.to_vec()
+            data: value
         });
 
         self.realloc(offset, 32)?;
@@ -158,7 +166,7 @@ impl Memory {
     pub fn write_byte(&mut self, offset: usize, value: u8) -> Result<(), Error> {
         tracing_event!(super::tracing::Event::MemorySet {
             offset,
-            data: vec![value]
+            data: std::slice::from_ref(&value)
         });
 
         self.realloc(offset, 1)?;
@@ -191,40 +199,23 @@ impl Memory {
 
         match source_offset {
             source_offset if source_offset >= source.len() => {
-                tracing_event!(super::tracing::Event::MemorySet {
-                    offset,
-                    data: vec![0; length]
-                });
-
                 sol_memset(data, 0, length);
             }
             source_offset if (source_offset + length) > source.len() => {
                 let source = &source[source_offset..];
 
-                tracing_event!(super::tracing::Event::MemorySet {
-                    offset,
-                    data: {
-                        let mut buffer = vec![0_u8; length];
-                        buffer[..source.len()].copy_from_slice(source);
-                        buffer
-                    }
-                });
-
                 data[..source.len()].copy_from_slice(source);
                 data[source.len()..].fill(0_u8);
             }
             source_offset => {
                 let source = &source[source_offset..source_offset + length];
 
-                tracing_event!(super::tracing::Event::MemorySet {
-                    offset,
-                    data: source.to_vec()
-                });
-
                 sol_memcpy(data, source, length);
             }
         }
 
+        tracing_event!(super::tracing::Event::MemorySet { offset, data });
+
         Ok(())
     }
 
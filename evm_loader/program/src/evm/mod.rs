@@ -14,20 +14,25 @@ use crate::{
     types::{Address, Transaction},
 };
 
+mod analysis;
 mod buffer;
 pub mod database;
+mod gas;
 mod memory;
 mod opcode;
 mod opcode_table;
 mod precompile;
+mod spec;
 mod stack;
 #[cfg(feature = "tracing")]
 pub mod tracing;
 mod utils;
 
 use self::{database::Database, memory::Memory, stack::Stack};
+pub use analysis::JumpDestinations;
 pub use buffer::Buffer;
-pub use precompile::is_precompile_address;
+pub use precompile::{Policy, Precompile, Registry};
+pub use spec::Spec;
 
 macro_rules! tracing_event {
     ($x:expr) => {
@@ -58,6 +63,31 @@ pub enum Reason {
     Create,
 }
 
+/// Instruction that entered the frame
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum CallType {
+    Call,
+    CallCode,
+    DelegateCall,
+    StaticCall,
+    Create,
+    Create2,
+}
+
+impl CallType {
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Call => "CALL",
+            Self::CallCode => "CALLCODE",
+            Self::DelegateCall => "DELEGATECALL",
+            Self::StaticCall => "STATICCALL",
+            Self::Create => "CREATE",
+            Self::Create2 => "CREATE2",
+        }
+    }
+}
+
 #[derive(Debug, Copy, Clone, Serialize, Deserialize)]
 pub struct Context {
     pub caller: Address,
@@ -71,15 +101,22 @@ pub struct Context {
 #[derive(Serialize, Deserialize)]
 #[serde(bound = "B: Database")]
 pub struct Machine<B: Database> {
+    spec: Spec,
     origin: Address,
     context: Context,
 
     #[serde(with = "ethnum::serde::bytes::le")]
     gas_price: U256,
-    #[serde(with = "ethnum::serde::bytes::le")]
-    gas_limit: U256,
+    gas_limit: u64,
+    gas_left: u64,
+    gas_refund: i64,
+    /// Gas left at the beginning of the current step, used only for tracing
+    #[serde(skip)]
+    #[cfg_attr(not(feature = "tracing"), allow(dead_code))]
+    step_gas: u64,
 
     execution_code: Buffer,
+    jump_destinations: JumpDestinations,
     call_data: Buffer,
     return_data: Buffer,
     return_range: Range<usize>,
@@ -148,14 +185,25 @@ impl<B: Database> Machine<B> {
         let target = trx.target.unwrap();
         sol_log_data(&[b"ENTER", b"CALL", target.as_bytes()]);
 
+        let spec = backend.spec();
+
+        let gas_limit = u64::try_from(trx.gas_limit).unwrap_or(u64::MAX);
+        let intrinsic_gas = gas::intrinsic_gas(&trx, spec);
+        if gas_limit < intrinsic_gas {
+            return Err(Error::OutOfGas(trx.gas_limit, intrinsic_gas.into()));
+        }
+
         backend.increment_nonce(origin)?;
+        Self::warm_up(spec, &trx, origin, target, backend)?;
         backend.snapshot();
 
         backend.transfer(origin, target, trx.value)?;
 
         let execution_code = backend.code(&target)?;
+        let jump_destinations = backend.jump_destinations(&execution_code);
 
         Ok(Self {
+            spec,
             origin,
             context: Context {
                 caller: origin,
@@ -164,8 +212,12 @@ impl<B: Database> Machine<B> {
                 code_address: Some(target),
             },
             gas_price: trx.gas_price,
-            gas_limit: trx.gas_limit,
+            gas_limit,
+            gas_left: gas_limit - intrinsic_gas,
+            gas_refund: 0,
+            step_gas: 0,
             execution_code,
+            jump_destinations,
             call_data: trx.call_data,
             return_data: Buffer::empty(),
             return_range: 0..0,
@@ -189,13 +241,28 @@ impl<B: Database> Machine<B> {
             return Err(Error::DeployToExistingAccount(target, origin));
         }
 
+        let spec = backend.spec();
+        if spec.is_enabled(Spec::Shanghai) && (trx.call_data.len() > gas::MAX_INITCODE_SIZE) {
+            return Err(Error::InitCodeSizeLimit(target, trx.call_data.len()));
+        }
+
+        let gas_limit = u64::try_from(trx.gas_limit).unwrap_or(u64::MAX);
+        let intrinsic_gas = gas::intrinsic_gas(&trx, spec);
+        if gas_limit < intrinsic_gas {
+            return Err(Error::OutOfGas(trx.gas_limit, intrinsic_gas.into()));
+        }
+
         backend.increment_nonce(origin)?;
+        Self::warm_up(spec, &trx, origin, target, backend)?;
         backend.snapshot();
 
         backend.increment_nonce(target)?;
         backend.transfer(origin, target, trx.value)?;
 
+        let jump_destinations = backend.jump_destinations(&trx.call_data);
+
         Ok(Self {
+            spec,
             origin,
             context: Context {
                 caller: origin,
@@ -204,7 +271,10 @@ impl<B: Database> Machine<B> {
                 code_address: None,
             },
             gas_price: trx.gas_price,
-            gas_limit: trx.gas_limit,
+            gas_limit,
+            gas_left: gas_limit - intrinsic_gas,
+            gas_refund: 0,
+            step_gas: 0,
             return_data: Buffer::empty(),
             return_range: 0..0,
             stack: Stack::new(),
@@ -213,6 +283,7 @@ impl<B: Database> Machine<B> {
             is_static: false,
             reason: Reason::Create,
             execution_code: trx.call_data,
+            jump_destinations,
             call_data: Buffer::empty(),
             parent: None,
             phantom: PhantomData,
@@ -224,7 +295,13 @@ impl<B: Database> Machine<B> {
 
         tracing_event!(tracing::Event::BeginVM {
             context: self.context,
-            code: self.execution_code.to_vec()
+            call_type: match self.reason {
+                Reason::Call => tracing::CallType::Call,
+                Reason::Create => tracing::CallType::Create,
+            },
+            code: &self.execution_code,
+            input: &self.call_data,
+            gas: self.gas_limit
         });
 
         let status = loop {
@@ -234,27 +311,48 @@ impl<B: Database> Machine<B> {
             }
 
             let opcode = self.execution_code.get_or_default(self.pc);
+            self.step_gas = self.gas_left;
 
             tracing_event!(tracing::Event::BeginStep {
                 opcode,
                 pc: self.pc,
-                stack: self.stack.to_vec(),
-                memory: self.memory.to_vec()
+                stack: self.stack.as_slice(),
+                memory: self.memory.as_slice(),
+                gas: self.gas_left,
+                refund: u64::try_from(self.gas_refund).unwrap_or(0),
+                return_data: &self.return_data
             });
 
-            // SAFETY: OPCODES.len() == 256, opcode <= 255
-            let opcode_fn = unsafe { Self::OPCODES.get_unchecked(opcode as usize) };
+            let opcode_fn = if self.spec.has_opcode(opcode) {
+                // SAFETY: OPCODES.len() == 256, opcode <= 255
+                unsafe { *Self::OPCODES.get_unchecked(opcode as usize) }
+            } else {
+                Self::opcode_unknown
+            };
+            let opcode_gas = gas::static_costs(self.spec)[opcode as usize];
+
+            let opcode_result = self
+                .use_gas(opcode_gas)
+                .and_then(|()| opcode_fn(self, backend));
 
-            let opcode_result = match opcode_fn(self, backend) {
+            let opcode_result = match opcode_result {
                 Ok(result) => result,
                 Err(e) => {
+                    tracing_event!(tracing::Event::StepError {
+                        message: e.to_string(),
+                        reason: e.halt_reason()
+                    });
+
+                    // Exceptional halt consumes all gas of the frame
+                    self.gas_left = 0;
+
                     let message = build_revert_message(&e.to_string());
                     self.opcode_revert_impl(Buffer::new(&message), backend)?
                 }
             };
 
             tracing_event!(opcode_result != Action::Noop; tracing::Event::EndStep {
-                gas_used: 0_u64
+                gas_used: self.step_gas_used()
             });
 
             match opcode_result {
@@ -269,26 +367,67 @@ impl<B: Database> Machine<B> {
         };
 
         tracing_event!(tracing::Event::EndVM {
-            status: status.clone()
+            status: status.clone(),
+            gas_used: self.frame_gas_used()
         });
 
         Ok((status, step))
     }
 
+    /// EIP-2929: transaction sender, recipient and the access list are warm.
+    /// Precompiles are always warm, see `is_cold_address`.
+    /// EIP-3651: COINBASE is warm starting from Shanghai.
+    fn warm_up(
+        spec: Spec,
+        trx: &Transaction,
+        origin: Address,
+        target: Address,
+        backend: &mut B,
+    ) -> Result<()> {
+        if !spec.is_enabled(Spec::Berlin) {
+            return Ok(());
+        }
+
+        backend.access_address(origin);
+        backend.access_address(target);
+
+        for item in &trx.access_list {
+            backend.access_address(item.address);
+            for key in &item.storage_keys {
+                backend.access_storage(item.address, *key);
+            }
+        }
+
+        if spec.is_enabled(Spec::Shanghai) {
+            let coinbase = backend.block_coinbase()?;
+            backend.access_address(coinbase);
+        }
+
+        Ok(())
+    }
+
     fn fork(
         &mut self,
         reason: Reason,
         context: Context,
         execution_code: Buffer,
         call_data: Buffer,
-        gas_limit: Option<U256>,
+        gas_limit: u64,
+        backend: &B,
     ) {
+        let jump_destinations = backend.jump_destinations(&execution_code);
+
         let mut other = Self {
+            spec: self.spec,
             origin: self.origin,
             context,
             gas_price: self.gas_price,
-            gas_limit: gas_limit.unwrap_or(self.gas_limit),
+            gas_limit,
+            gas_left: gas_limit,
+            gas_refund: 0,
+            step_gas: 0,
             execution_code,
+            jump_destinations,
             call_data,
             return_data: Buffer::empty(),
             return_range: 0..0,
@@ -311,6 +450,9 @@ impl<B: Database> Machine<B> {
         let mut other = *self.parent.take().unwrap();
         core::mem::swap(self, &mut other);
 
+        // Unused gas is returned to the caller
+        self.gas_left = self.gas_left.saturating_add(other.gas_left);
+
         other
     }
 }
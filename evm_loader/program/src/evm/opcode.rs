@@ -2,11 +2,11 @@
 use ethnum::{I256, U256};
 use solana_program::log::sol_log_data;
 
-use super::{database::Database, tracing_event, Context, Machine, Reason};
+use super::{database::Database, gas, tracing_event, CallType, Context, Machine, Reason};
 use crate::{
     error::{Error, Result},
     evm::Buffer,
-    types::Address,
+    types::{Address, BASE_FEE_PER_GAS},
 };
 
 #[derive(Eq, PartialEq)]
@@ -206,6 +206,9 @@ impl<B: Database> Machine<B> {
         let mut a = self.stack.pop_u256()?;
         let mut b = self.stack.pop_u256()?;
 
+        let exponent_bytes = u64::from(32 - b.leading_zeros() / 8);
+        self.use_gas(exponent_bytes * gas::EXP_BYTE)?;
+
         let mut result = U256::ONE;
 
         // exponentiation by squaring
@@ -428,6 +431,9 @@ impl<B: Database> Machine<B> {
         let offset = self.stack.pop_usize()?;
         let length = self.stack.pop_usize()?;
 
+        self.use_gas(gas::words(length) * gas::SHA3_WORD)?;
+        self.expand_memory(offset, length)?;
+
         let data = self.memory.read(offset, length)?;
         let Hash(hash) = hash(data);
 
@@ -445,10 +451,10 @@ impl<B: Database> Machine<B> {
 
     /// address balance in wei
     pub fn opcode_balance(&mut self, backend: &mut B) -> Result<Action> {
-        let balance = {
-            let address = self.stack.pop_address()?;
-            backend.balance(address)?
-        };
+        let address = *self.stack.pop_address()?;
+        self.access_address(backend, &address)?;
+
+        let balance = backend.balance(&address)?;
 
         self.stack.push_u256(balance)?;
 
@@ -514,6 +520,9 @@ impl<B: Database> Machine<B> {
         let data_offset = self.stack.pop_usize()?;
         let length = self.stack.pop_usize()?;
 
+        self.use_gas(gas::copy_cost(length))?;
+        self.expand_memory(memory_offset, length)?;
+
         self.memory
             .write_buffer(memory_offset, length, &self.call_data, data_offset)?;
 
@@ -534,6 +543,9 @@ impl<B: Database> Machine<B> {
         let data_offset = self.stack.pop_usize()?;
         let length = self.stack.pop_usize()?;
 
+        self.use_gas(gas::copy_cost(length))?;
+        self.expand_memory(memory_offset, length)?;
+
         self.memory
             .write_buffer(memory_offset, length, &self.execution_code, data_offset)?;
 
@@ -551,10 +563,10 @@ impl<B: Database> Machine<B> {
     /// length of the contract bytecode at addr, in bytes
     /// address(addr).code.size
     pub fn opcode_extcodesize(&mut self, backend: &mut B) -> Result<Action> {
-        let code_size = {
-            let address = self.stack.pop_address()?;
-            backend.code_size(address)?
-        };
+        let address = *self.stack.pop_address()?;
+        self.access_address(backend, &address)?;
+
+        let code_size = backend.code_size(&address)?;
 
         self.stack.push_usize(code_size)?;
 
@@ -568,6 +580,10 @@ impl<B: Database> Machine<B> {
         let data_offset = self.stack.pop_usize()?;
         let length = self.stack.pop_usize()?;
 
+        self.access_address(backend, &address)?;
+        self.use_gas(gas::copy_cost(length))?;
+        self.expand_memory(memory_offset, length)?;
+
         let code = backend.code(&address)?;
 
         self.memory
@@ -593,6 +609,9 @@ impl<B: Database> Machine<B> {
             return Err(Error::ReturnDataCopyOverflow(data_offset, length));
         }
 
+        self.use_gas(gas::copy_cost(length))?;
+        self.expand_memory(memory_offset, length)?;
+
         self.memory
             .write_buffer(memory_offset, length, &self.return_data, data_offset)?;
 
@@ -601,10 +620,10 @@ impl<B: Database> Machine<B> {
 
     /// Constantinople hardfork, EIP-1052: hash of the contract bytecode at addr
     pub fn opcode_extcodehash(&mut self, backend: &mut B) -> Result<Action> {
-        let code_hash = {
-            let address = self.stack.pop_address()?;
-            backend.code_hash(address)?
-        };
+        let address = *self.stack.pop_address()?;
+        self.access_address(backend, &address)?;
+
+        let code_hash = backend.code_hash(&address)?;
 
         self.stack.push_array(&code_hash)?;
 
@@ -625,10 +644,11 @@ impl<B: Database> Machine<B> {
         Ok(Action::Continue)
     }
 
-    /// address of the current block's miner
-    /// NOT SUPPORTED
-    pub fn opcode_coinbase(&mut self, _backend: &mut B) -> Result<Action> {
-        self.stack.push_zero()?;
+    /// address of the current block's miner, zero on Solana
+    pub fn opcode_coinbase(&mut self, backend: &mut B) -> Result<Action> {
+        let coinbase = backend.block_coinbase()?;
+
+        self.stack.push_address(&coinbase)?;
 
         Ok(Action::Continue)
     }
@@ -686,9 +706,9 @@ impl<B: Database> Machine<B> {
     }
 
     /// London hardfork, EIP-3198: current block's base fee
-    /// NOT SUPPORTED
+    /// NOT SUPPORTED, Neon has no base fee
     pub fn opcode_basefee(&mut self, _backend: &mut B) -> Result<Action> {
-        self.stack.push_zero()?;
+        self.stack.push_u256(BASE_FEE_PER_GAS)?;
 
         Ok(Action::Continue)
     }
@@ -703,6 +723,8 @@ impl<B: Database> Machine<B> {
     /// reads a (u)int256 from memory
     pub fn opcode_mload(&mut self, _backend: &mut B) -> Result<Action> {
         let offset = self.stack.pop_usize()?;
+        self.expand_memory(offset, 32)?;
+
         let value = self.memory.read_32(offset)?;
 
         self.stack.push_array(value)?;
@@ -713,9 +735,10 @@ impl<B: Database> Machine<B> {
     /// writes a (u)int256 to memory
     pub fn opcode_mstore(&mut self, _backend: &mut B) -> Result<Action> {
         let offset = self.stack.pop_usize()?;
-        let value = self.stack.pop_array()?;
+        let value = *self.stack.pop_array()?;
 
-        self.memory.write_32(offset, value)?;
+        self.expand_memory(offset, 32)?;
+        self.memory.write_32(offset, &value)?;
 
         Ok(Action::Continue)
     }
@@ -723,9 +746,10 @@ impl<B: Database> Machine<B> {
     /// writes a uint8 to memory
     pub fn opcode_mstore8(&mut self, _backend: &mut B) -> Result<Action> {
         let offset = self.stack.pop_usize()?;
-        let value = self.stack.pop_array()?;
+        let value = self.stack.pop_array()?[31];
 
-        self.memory.write_byte(offset, value[31])?;
+        self.expand_memory(offset, 1)?;
+        self.memory.write_byte(offset, value)?;
 
         Ok(Action::Continue)
     }
@@ -733,6 +757,11 @@ impl<B: Database> Machine<B> {
     /// reads a (u)int256 from storage
     pub fn opcode_sload(&mut self, backend: &mut B) -> Result<Action> {
         let index = self.stack.pop_u256()?;
+
+        if self.is_cold_storage(backend, &self.context.contract, index) {
+            self.use_gas(gas::COLD_SLOAD - gas::WARM_STORAGE_READ)?;
+        }
+
         let value = backend.storage(&self.context.contract, &index)?;
 
         tracing_event!(super::tracing::Event::StorageAccess { index, value });
@@ -751,6 +780,14 @@ impl<B: Database> Machine<B> {
         let index = self.stack.pop_u256()?;
         let value = *self.stack.pop_array()?;
 
+        let original = backend.original_storage(&self.context.contract, &index)?;
+        let current = backend.storage(&self.context.contract, &index)?;
+        self.sstore_gas(&original, &current, &value)?;
+
+        if self.is_cold_storage(backend, &self.context.contract, index) {
+            self.use_gas(gas::COLD_SLOAD)?;
+        }
+
         tracing_event!(super::tracing::Event::StorageSet { index, value });
         tracing_event!(super::tracing::Event::StorageAccess { index, value });
 
@@ -761,11 +798,9 @@ impl<B: Database> Machine<B> {
 
     /// unconditional jump
     pub fn opcode_jump(&mut self, _backend: &mut B) -> Result<Action> {
-        const JUMPDEST: u8 = 0x5B;
-
         let value = self.stack.pop_usize()?;
 
-        if self.execution_code.get(value) == Some(&JUMPDEST) {
+        if self.jump_destinations.is_valid(value) {
             Ok(Action::Jump(value))
         } else {
             Err(Error::InvalidJump(self.context.contract, value))
@@ -774,8 +809,6 @@ impl<B: Database> Machine<B> {
 
     /// conditional jump
     pub fn opcode_jumpi(&mut self, _backend: &mut B) -> Result<Action> {
-        const JUMPDEST: u8 = 0x5B;
-
         let value = self.stack.pop_usize()?;
         let condition = self.stack.pop_array()?;
 
@@ -783,7 +816,7 @@ impl<B: Database> Machine<B> {
             return Ok(Action::Continue);
         }
 
-        if self.execution_code.get(value) == Some(&JUMPDEST) {
+        if self.jump_destinations.is_valid(value) {
             Ok(Action::Jump(value))
         } else {
             Err(Error::InvalidJump(self.context.contract, value))
@@ -806,7 +839,7 @@ impl<B: Database> Machine<B> {
 
     /// remaining gas
     pub fn opcode_gas(&mut self, _backend: &mut B) -> Result<Action> {
-        self.stack.push_u256(self.gas_limit)?;
+        self.stack.push_u256(U256::from(self.gas_left))?;
 
         Ok(Action::Continue)
     }
@@ -816,6 +849,53 @@ impl<B: Database> Machine<B> {
         Ok(Action::Continue)
     }
 
+    /// reads a (u)int256 from transient storage
+    pub fn opcode_tload(&mut self, backend: &mut B) -> Result<Action> {
+        let index = self.stack.pop_u256()?;
+        let value = backend.transient_storage(&self.context.contract, &index)?;
+
+        self.stack.push_array(&value)?;
+
+        Ok(Action::Continue)
+    }
+
+    /// writes a (u)int256 to transient storage
+    pub fn opcode_tstore(&mut self, backend: &mut B) -> Result<Action> {
+        if self.is_static {
+            return Err(Error::StaticModeViolation(self.context.contract));
+        }
+
+        let index = self.stack.pop_u256()?;
+        let value = *self.stack.pop_array()?;
+
+        backend.set_transient_storage(self.context.contract, index, value)?;
+
+        Ok(Action::Continue)
+    }
+
+    /// copy memory areas
+    pub fn opcode_mcopy(&mut self, _backend: &mut B) -> Result<Action> {
+        let dst_offset = self.stack.pop_usize()?;
+        let src_offset = self.stack.pop_usize()?;
+        let length = self.stack.pop_usize()?;
+
+        self.use_gas(gas::copy_cost(length))?;
+        self.expand_memory(src_offset, length)?;
+        self.expand_memory(dst_offset, length)?;
+
+        let data = self.memory.read_buffer(src_offset, length)?;
+        self.memory.write_buffer(dst_offset, length, &data, 0)?;
+
+        Ok(Action::Continue)
+    }
+
+    /// place zero on stack
+    pub fn opcode_push_0(&mut self, _backend: &mut B) -> Result<Action> {
+        self.stack.push_zero()?;
+
+        Ok(Action::Continue)
+    }
+
     /// Place 1 byte item on stack
     /// ~50% of contract bytecode are PUSH opcodes
     pub fn opcode_push_1(&mut self, _backend: &mut B) -> Result<Action> {
@@ -878,8 +958,7 @@ impl<B: Database> Machine<B> {
     }
 
     /// Append log record with N topics
-    #[rustfmt::skip]
-    pub fn opcode_log_0_4<const N: usize>(&mut self, _backend: &mut B) -> Result<Action> {
+    pub fn opcode_log_0_4<const N: usize>(&mut self, backend: &mut B) -> Result<Action> {
         if self.is_static {
             return Err(Error::StaticModeViolation(self.context.contract));
         }
@@ -887,6 +966,9 @@ impl<B: Database> Machine<B> {
         let offset = self.stack.pop_usize()?;
         let length = self.stack.pop_usize()?;
 
+        self.use_gas((length as u64).saturating_mul(gas::LOG_DATA))?;
+        self.expand_memory(offset, length)?;
+
         let data = self.memory.read(offset, length)?;
         let topics: [[u8; 32]; N] = {
             let mut topics = [[0_u8; 32]; N];
@@ -897,16 +979,13 @@ impl<B: Database> Machine<B> {
             topics
         };
 
-        let address = self.context.contract.as_bytes();
+        backend.log(self.context.contract, &topics, data);
 
-        match N {
-            0 => sol_log_data(&[b"LOG0", address, &[0], data]),                                                
-            1 => sol_log_data(&[b"LOG1", address, &[1], &topics[0], data]),                                    
-            2 => sol_log_data(&[b"LOG2", address, &[2], &topics[0], &topics[1], data]),                        
-            3 => sol_log_data(&[b"LOG3", address, &[3], &topics[0], &topics[1], &topics[2], data]),            
-            4 => sol_log_data(&[b"LOG4", address, &[4], &topics[0], &topics[1], &topics[2], &topics[3], data]),
-            _ => unreachable!(),
-        }
+        tracing_event!(super::tracing::Event::Log {
+            address: self.context.contract,
+            topics: &topics,
+            data
+        });
 
         Ok(Action::Continue)
     }
@@ -921,12 +1000,22 @@ impl<B: Database> Machine<B> {
         let offset = self.stack.pop_usize()?;
         let length = self.stack.pop_usize()?;
 
+        self.use_initcode_gas(length)?;
+        self.expand_memory(offset, length)?;
+
         let created_address = {
             let nonce = backend.nonce(&self.context.contract)?;
             Address::from_create(&self.context.contract, nonce)
         };
 
-        self.opcode_create_impl(created_address, value, offset, length, backend)
+        self.opcode_create_impl(
+            CallType::Create,
+            created_address,
+            value,
+            offset,
+            length,
+            backend,
+        )
     }
 
     /// Constantinople harfork, EIP-1014: creates a create a new account with a deterministic address
@@ -940,30 +1029,50 @@ impl<B: Database> Machine<B> {
         let length = self.stack.pop_usize()?;
         let salt = *self.stack.pop_array()?;
 
+        self.use_initcode_gas(length)?;
+        self.use_gas(gas::words(length) * gas::SHA3_WORD)?;
+        self.expand_memory(offset, length)?;
+
         let created_address = {
             let initialization_code = self.memory.read(offset, length)?;
             Address::from_create2(&self.context.contract, &salt, initialization_code)
         };
 
-        self.opcode_create_impl(created_address, value, offset, length, backend)
+        self.opcode_create_impl(
+            CallType::Create2,
+            created_address,
+            value,
+            offset,
+            length,
+            backend,
+        )
     }
 
+    #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
     fn opcode_create_impl(
         &mut self,
+        call_type: CallType,
         address: Address,
         value: U256,
         offset: usize,
         length: usize,
         backend: &mut B,
     ) -> Result<Action> {
+        self.return_data = Buffer::empty();
+        self.return_range = 0..0;
+
+        // The creation is not entered, the frame keeps the gas and the nonce is not changed
+        if backend.balance(&self.context.contract)? < value {
+            self.stack.push_zero()?;
+            return Ok(Action::Continue);
+        }
+
         if backend.nonce(&self.context.contract)? == u64::MAX {
             return Err(Error::NonceOverflow(self.context.contract));
         }
 
         backend.increment_nonce(self.context.contract)?;
-
-        self.return_data = Buffer::empty();
-        self.return_range = 0..0;
+        backend.access_address(address);
 
         let init_code = self.memory.read_buffer(offset, length)?;
 
@@ -974,12 +1083,24 @@ impl<B: Database> Machine<B> {
             code_address: None,
         };
 
+        let gas_limit = self.call_gas(U256::MAX);
+
         tracing_event!(super::tracing::Event::BeginVM {
             context,
-            code: init_code.to_vec()
+            call_type,
+            code: &init_code,
+            input: &[],
+            gas: gas_limit
         });
 
-        self.fork(Reason::Create, context, init_code, Buffer::empty(), None);
+        self.fork(
+            Reason::Create,
+            context,
+            init_code,
+            Buffer::empty(),
+            gas_limit,
+            backend,
+        );
         backend.snapshot();
 
         sol_log_data(&[b"ENTER", b"CREATE", address.as_bytes()]);
@@ -988,10 +1109,6 @@ impl<B: Database> Machine<B> {
             return Err(Error::DeployToExistingAccount(address, self.context.caller));
         }
 
-        if backend.balance(&self.context.caller)? < value {
-            return Err(Error::InsufficientBalance(self.context.caller, value));
-        }
-
         backend.increment_nonce(address)?;
         backend.transfer(self.context.caller, address, value)?;
 
@@ -1008,9 +1125,29 @@ impl<B: Database> Machine<B> {
         let return_offset = self.stack.pop_usize()?;
         let return_length = self.stack.pop_usize()?;
 
+        if self.is_static && (value != U256::ZERO) {
+            return Err(Error::StaticModeViolation(self.context.contract));
+        }
+
+        self.expand_memory(args_offset, args_length)?;
+        self.expand_memory(return_offset, return_length)?;
+        self.access_address(backend, &address)?;
+
+        if value != U256::ZERO {
+            self.use_gas(gas::CALL_VALUE)?;
+
+            if !Self::account_exists(backend, &address)? {
+                self.use_gas(gas::NEW_ACCOUNT)?;
+            }
+        }
+
         self.return_data = Buffer::empty();
         self.return_range = return_offset..(return_offset + return_length);
 
+        if backend.balance(&self.context.contract)? < value {
+            return self.call_insufficient_balance();
+        }
+
         let call_data = self.memory.read_buffer(args_offset, args_length)?;
         let code = backend.code(&address)?;
 
@@ -1021,24 +1158,24 @@ impl<B: Database> Machine<B> {
             code_address: Some(address),
         };
 
+        let mut gas_limit = self.call_gas(gas_limit);
+        if value != U256::ZERO {
+            gas_limit = gas_limit.saturating_add(gas::CALL_STIPEND);
+        }
+
         tracing_event!(super::tracing::Event::BeginVM {
             context,
-            code: code.to_vec()
+            call_type: CallType::Call,
+            code: &code,
+            input: &call_data,
+            gas: gas_limit
         });
 
-        self.fork(Reason::Call, context, code, call_data, Some(gas_limit));
+        self.fork(Reason::Call, context, code, call_data, gas_limit, backend);
         backend.snapshot();
 
         sol_log_data(&[b"ENTER", b"CALL", address.as_bytes()]);
 
-        if self.is_static && (value != U256::ZERO) {
-            return Err(Error::StaticModeViolation(self.context.caller));
-        }
-
-        if backend.balance(&self.context.caller)? < value {
-            return Err(Error::InsufficientBalance(self.context.caller, value));
-        }
-
         backend.transfer(self.context.caller, self.context.contract, value)?;
 
         self.opcode_call_precompile_impl(backend, &address)
@@ -1054,9 +1191,21 @@ impl<B: Database> Machine<B> {
         let return_offset = self.stack.pop_usize()?;
         let return_length = self.stack.pop_usize()?;
 
+        self.expand_memory(args_offset, args_length)?;
+        self.expand_memory(return_offset, return_length)?;
+        self.access_address(backend, &address)?;
+
+        if value != U256::ZERO {
+            self.use_gas(gas::CALL_VALUE)?;
+        }
+
         self.return_data = Buffer::empty();
         self.return_range = return_offset..(return_offset + return_length);
 
+        if backend.balance(&self.context.contract)? < value {
+            return self.call_insufficient_balance();
+        }
+
         let call_data = self.memory.read_buffer(args_offset, args_length)?;
         let code = backend.code(&address)?;
 
@@ -1067,23 +1216,36 @@ impl<B: Database> Machine<B> {
             code_address: Some(address),
         };
 
+        let mut gas_limit = self.call_gas(gas_limit);
+        if value != U256::ZERO {
+            gas_limit = gas_limit.saturating_add(gas::CALL_STIPEND);
+        }
+
         tracing_event!(super::tracing::Event::BeginVM {
             context,
-            code: code.to_vec()
+            call_type: CallType::CallCode,
+            code: &code,
+            input: &call_data,
+            gas: gas_limit
         });
 
-        self.fork(Reason::Call, context, code, call_data, Some(gas_limit));
+        self.fork(Reason::Call, context, code, call_data, gas_limit, backend);
         backend.snapshot();
 
         sol_log_data(&[b"ENTER", b"CALLCODE", address.as_bytes()]);
 
-        if backend.balance(&self.context.caller)? < value {
-            return Err(Error::InsufficientBalance(self.context.caller, value));
-        }
-
         self.opcode_call_precompile_impl(backend, &address)
     }
 
+    /// The callee is not entered when the value exceeds the balance.
+    /// The frame keeps the forwarded gas along with the stipend, as the Ethereum clients do.
+    fn call_insufficient_balance(&mut self) -> Result<Action> {
+        self.gas_left = self.gas_left.saturating_add(gas::CALL_STIPEND);
+        self.stack.push_zero()?;
+
+        Ok(Action::Continue)
+    }
+
     /// Homestead hardfork, EIP-7: Message-call into this account with an alternative account’s code,
     /// but persisting the current values for sender and value
     pub fn opcode_delegatecall(&mut self, backend: &mut B) -> Result<Action> {
@@ -1094,6 +1256,10 @@ impl<B: Database> Machine<B> {
         let return_offset = self.stack.pop_usize()?;
         let return_length = self.stack.pop_usize()?;
 
+        self.expand_memory(args_offset, args_length)?;
+        self.expand_memory(return_offset, return_length)?;
+        self.access_address(backend, &address)?;
+
         self.return_data = Buffer::empty();
         self.return_range = return_offset..(return_offset + return_length);
 
@@ -1105,12 +1271,17 @@ impl<B: Database> Machine<B> {
             ..self.context
         };
 
+        let gas_limit = self.call_gas(gas_limit);
+
         tracing_event!(super::tracing::Event::BeginVM {
             context,
-            code: code.to_vec()
+            call_type: CallType::DelegateCall,
+            code: &code,
+            input: &call_data,
+            gas: gas_limit
         });
 
-        self.fork(Reason::Call, context, code, call_data, Some(gas_limit));
+        self.fork(Reason::Call, context, code, call_data, gas_limit, backend);
         backend.snapshot();
 
         sol_log_data(&[b"ENTER", b"DELEGATECALL", address.as_bytes()]);
@@ -1128,6 +1299,10 @@ impl<B: Database> Machine<B> {
         let return_offset = self.stack.pop_usize()?;
         let return_length = self.stack.pop_usize()?;
 
+        self.expand_memory(args_offset, args_length)?;
+        self.expand_memory(return_offset, return_length)?;
+        self.access_address(backend, &address)?;
+
         self.return_data = Buffer::empty();
         self.return_range = return_offset..(return_offset + return_length);
 
@@ -1141,12 +1316,17 @@ impl<B: Database> Machine<B> {
             code_address: Some(address),
         };
 
+        let gas_limit = self.call_gas(gas_limit);
+
         tracing_event!(super::tracing::Event::BeginVM {
             context,
-            code: code.to_vec()
+            call_type: CallType::StaticCall,
+            code: &code,
+            input: &call_data,
+            gas: gas_limit
         });
 
-        self.fork(Reason::Call, context, code, call_data, Some(gas_limit));
+        self.fork(Reason::Call, context, code, call_data, gas_limit, backend);
         self.is_static = true;
 
         backend.snapshot();
@@ -1163,16 +1343,14 @@ impl<B: Database> Machine<B> {
         backend: &mut B,
         address: &Address,
     ) -> Result<Action> {
-        let result = Self::precompile(address, &self.call_data).map(Ok);
-        let result = result.or_else(|| {
-            backend.precompile_extension(&self.context, address, &self.call_data, self.is_static)
-        });
-
-        if result.is_none() {
+        if !backend.is_precompile(address) {
             return Ok(Action::Noop);
         }
 
-        let result = result.unwrap()?;
+        self.use_gas(backend.precompile_gas(address, &self.call_data))?;
+
+        let result =
+            backend.call_precompile(&self.context, address, &self.call_data, self.is_static)?;
         let return_data = Buffer::new(&result);
 
         self.opcode_return_impl(return_data, backend)
@@ -1183,6 +1361,8 @@ impl<B: Database> Machine<B> {
         let offset = self.stack.pop_usize()?;
         let length = self.stack.pop_usize()?;
 
+        self.expand_memory(offset, length)?;
+
         let return_data = self.memory.read_buffer(offset, length)?;
 
         self.opcode_return_impl(return_data, backend)
@@ -1195,6 +1375,8 @@ impl<B: Database> Machine<B> {
         backend: &mut B,
     ) -> Result<Action> {
         if self.reason == Reason::Create {
+            self.use_gas((return_data.len() as u64).saturating_mul(gas::CODE_DEPOSIT))?;
+
             let code = std::mem::take(&mut return_data);
             backend.set_code(self.context.contract, code)?;
         }
@@ -1206,12 +1388,17 @@ impl<B: Database> Machine<B> {
             return Ok(Action::Return(return_data.to_vec()));
         }
 
-        tracing_event!(super::tracing::Event::EndStep { gas_used: 0_u64 });
+        tracing_event!(super::tracing::Event::EndStep {
+            gas_used: self.step_gas_used()
+        });
         tracing_event!(super::tracing::Event::EndVM {
-            status: super::ExitStatus::Return(return_data.to_vec())
+            status: super::ExitStatus::Return(return_data.to_vec()),
+            gas_used: self.frame_gas_used()
         });
 
         let returned = self.join();
+        self.gas_refund = self.gas_refund.saturating_add(returned.gas_refund);
+
         match returned.reason {
             Reason::Call => {
                 self.memory.write_range(&self.return_range, &return_data)?;
@@ -1233,12 +1420,17 @@ impl<B: Database> Machine<B> {
         let offset = self.stack.pop_usize()?;
         let length = self.stack.pop_usize()?;
 
+        self.expand_memory(offset, length)?;
+
         let return_data = self.memory.read_buffer(offset, length)?;
 
         self.opcode_revert_impl(return_data, backend)
     }
 
     pub fn opcode_revert_impl(&mut self, return_data: Buffer, backend: &mut B) -> Result<Action> {
+        // Refunds are discarded together with the reverted state changes
+        self.gas_refund = 0;
+
         backend.revert_snapshot();
         sol_log_data(&[b"EXIT", b"REVERT", &return_data]);
 
@@ -1246,9 +1438,12 @@ impl<B: Database> Machine<B> {
             return Ok(Action::Revert(return_data.to_vec()));
         }
 
-        tracing_event!(super::tracing::Event::EndStep { gas_used: 0_u64 });
+        tracing_event!(super::tracing::Event::EndStep {
+            gas_used: self.step_gas_used()
+        });
         tracing_event!(super::tracing::Event::EndVM {
-            status: super::ExitStatus::Revert(return_data.to_vec())
+            status: super::ExitStatus::Revert(return_data.to_vec()),
+            gas_used: self.frame_gas_used()
         });
 
         let returned = self.join();
@@ -1283,10 +1478,25 @@ impl<B: Database> Machine<B> {
 
         let address = *self.stack.pop_address()?;
 
+        if self.is_cold_address(backend, &address) {
+            self.use_gas(gas::COLD_ACCOUNT_ACCESS)?;
+        }
+
         let value = backend.balance(&self.context.contract)?;
+        if (value != U256::ZERO) && !Self::account_exists(backend, &address)? {
+            self.use_gas(gas::NEW_ACCOUNT)?;
+        }
+        self.selfdestruct_refund();
+
         backend.transfer(self.context.contract, address, value)?;
         backend.selfdestruct(self.context.contract)?;
 
+        tracing_event!(super::tracing::Event::SelfDestruct {
+            address: self.context.contract,
+            beneficiary: address,
+            value
+        });
+
         backend.commit_snapshot();
         sol_log_data(&[b"EXIT", b"SELFDESTRUCT"]);
 
@@ -1294,12 +1504,17 @@ impl<B: Database> Machine<B> {
             return Ok(Action::Suicide);
         }
 
-        tracing_event!(super::tracing::Event::EndStep { gas_used: 0_u64 });
+        tracing_event!(super::tracing::Event::EndStep {
+            gas_used: self.step_gas_used()
+        });
         tracing_event!(super::tracing::Event::EndVM {
-            status: super::ExitStatus::Suicide
+            status: super::ExitStatus::Suicide,
+            gas_used: self.frame_gas_used()
         });
 
         let returned = self.join();
+        self.gas_refund = self.gas_refund.saturating_add(returned.gas_refund);
+
         match returned.reason {
             Reason::Call => {
                 self.memory.write_range(&self.return_range, &[])?;
@@ -1322,12 +1537,17 @@ impl<B: Database> Machine<B> {
             return Ok(Action::Stop);
         }
 
-        tracing_event!(super::tracing::Event::EndStep { gas_used: 0_u64 });
+        tracing_event!(super::tracing::Event::EndStep {
+            gas_used: self.step_gas_used()
+        });
         tracing_event!(super::tracing::Event::EndVM {
-            status: super::ExitStatus::Stop
+            status: super::ExitStatus::Stop,
+            gas_used: self.frame_gas_used()
         });
 
         let returned = self.join();
+        self.gas_refund = self.gas_refund.saturating_add(returned.gas_refund);
+
         match returned.reason {
             Reason::Call => {
                 self.memory.write_range(&self.return_range, &[])?;
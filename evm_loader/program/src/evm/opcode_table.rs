@@ -77,6 +77,10 @@ impl<B: Database> Machine<B> {
         opcodes[0x59] = Self::opcode_msize;
         opcodes[0x5A] = Self::opcode_gas;
         opcodes[0x5B] = Self::opcode_jumpdest;
+        opcodes[0x5C] = Self::opcode_tload;
+        opcodes[0x5D] = Self::opcode_tstore;
+        opcodes[0x5E] = Self::opcode_mcopy;
+        opcodes[0x5F] = Self::opcode_push_0;
 
         opcodes[0x60] = Self::opcode_push_1;
         opcodes[0x61] = Self::opcode_push_2_31::<2>;
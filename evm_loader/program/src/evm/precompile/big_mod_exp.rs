@@ -1,38 +1,283 @@
-// use ethnum::U256;
+use ethnum::U256;
 
+use crate::error::{Error, Result};
+
+const HEADER_LEN: usize = 96;
+/// Length limit of the base, exponent and modulus of the `sol_big_mod_exp` syscall
+const MAX_LENGTH: usize = 512;
+
+/// `length` bytes at `offset`, bytes past the end of the input are implicit zeros
+struct Padded<'a> {
+    bytes: &'a [u8],
+    zeros: usize,
+}
+
+impl<'a> Padded<'a> {
+    fn read(input: &'a [u8], offset: usize, length: usize) -> Self {
+        let start = offset.min(input.len());
+        let end = offset.saturating_add(length).min(input.len());
+        let bytes = &input[start..end];
+
+        Self {
+            bytes,
+            zeros: length - bytes.len(),
+        }
+    }
+}
+
+fn read_u256(input: &[u8], offset: usize) -> U256 {
+    let padded = Padded::read(input, offset, 32);
+
+    let mut buffer = [0_u8; 32];
+    buffer[..padded.bytes.len()].copy_from_slice(padded.bytes);
+    U256::from_be_bytes(buffer)
+}
+
+fn checked_length(value: U256) -> Result<usize> {
+    match usize::try_from(value) {
+        Ok(length) if length <= MAX_LENGTH => Ok(length),
+        _ => Err(Error::Custom(format!(
+            "big_mod_exp: length {value} exceeds {MAX_LENGTH} bytes"
+        ))),
+    }
+}
+
+/// EIP-198: `<length_of_BASE> <length_of_EXPONENT> <length_of_MODULUS> <BASE> <EXPONENT> <MODULUS>`
+pub fn big_mod_exp(input: &[u8]) -> Result<Vec<u8>> {
+    debug_print!("big_mod_exp");
+
+    let mod_len = read_u256(input, 64);
+    if mod_len == U256::ZERO {
+        return Ok(Vec::new());
+    }
+
+    // Lengths are limited by the syscall, they are checked before anything is read
+    let base_len = checked_length(read_u256(input, 0))?;
+    let exp_len = checked_length(read_u256(input, 32))?;
+    let mod_len = checked_length(mod_len)?;
+
+    let exp_offset = HEADER_LEN + base_len;
+    let mod_offset = exp_offset + exp_len;
+
+    let base = Padded::read(input, HEADER_LEN, base_len);
+    let exponent = Padded::read(input, exp_offset, exp_len);
+    let modulus = Padded::read(input, mod_offset, mod_len);
+
+    modexp::modexp(&base, &exponent, &modulus)
+}
+
+/// EIP-2565: `max(200, multiplication_complexity * iteration_count / 3)`
 #[must_use]
-pub fn big_mod_exp(_input: &[u8]) -> Vec<u8> {
-    // Should be implemented via Solana syscall
-    Vec::new()
-
-    // if input.len() < 96 {
-    //     return vec![];
-    // }
-
-    // let (base_len, rest) = input.split_at(32);
-    // let (exp_len, rest) = rest.split_at(32);
-    // let (mod_len, rest) = rest.split_at(32);
-
-    // let base_len = match U256::from_be_bytes(base_len.try_into().unwrap()).try_into() {
-    //     Ok(value) => value,
-    //     Err(_) => return vec![]
-    // };
-    // let exp_len = match U256::from_be_bytes(exp_len.try_into().unwrap()).try_into() {
-    //     Ok(value) => value,
-    //     Err(_) => return vec![]
-    // };
-    // let mod_len = match U256::from_be_bytes(mod_len.try_into().unwrap()).try_into() {
-    //     Ok(value) => value,
-    //     Err(_) => return vec![]
-    // };
-
-    // if base_len == 0 && mod_len == 0 {
-    //     return vec![0; 32];
-    // }
-
-    // let (base_val, rest) = rest.split_at(base_len);
-    // let (exp_val, rest) = rest.split_at(exp_len);
-    // let (mod_val, _) = rest.split_at(mod_len);
-
-    // solana_program::big_mod_exp::big_mod_exp(base_val, exp_val, mod_val)
+pub fn gas(input: &[u8]) -> u64 {
+    const MIN_GAS: u64 = 200;
+
+    let to_u64 = |value: U256| u64::try_from(value).unwrap_or(u64::MAX);
+
+    let base_len = read_u256(input, 0);
+    let exp_len = read_u256(input, 32);
+    let mod_len = read_u256(input, 64);
+
+    let words = to_u64(base_len.max(mod_len)).saturating_add(7) / 8;
+    let multiplication_complexity = words.saturating_mul(words);
+
+    let exp_head = match usize::try_from(base_len) {
+        Ok(base_len) if exp_len != U256::ZERO => {
+            let head_len = usize::try_from(exp_len.min(U256::new(32))).unwrap_or(32);
+            let head = Padded::read(input, HEADER_LEN.saturating_add(base_len), head_len);
+
+            let begin = 32 - head_len;
+            let mut buffer = [0_u8; 32];
+            buffer[begin..begin + head.bytes.len()].copy_from_slice(head.bytes);
+            U256::from_be_bytes(buffer)
+        }
+        _ => U256::ZERO,
+    };
+
+    let head_bits = u64::from(256 - exp_head.leading_zeros());
+    let iteration_count = if exp_len <= 32 {
+        head_bits.saturating_sub(1)
+    } else {
+        to_u64(exp_len - 32)
+            .saturating_mul(8)
+            .saturating_add(head_bits.saturating_sub(1))
+    };
+    let iteration_count = iteration_count.max(1);
+
+    let gas = multiplication_complexity.saturating_mul(iteration_count) / 3;
+    gas.max(MIN_GAS)
+}
+
+cfg_if::cfg_if! {
+    if #[cfg(target_os = "solana")] {
+        mod modexp {
+            use super::Padded;
+            use crate::error::{Error, Result};
+
+            #[repr(C)]
+            struct BigModExpParams {
+                base: *const u8,
+                base_len: u64,
+                exponent: *const u8,
+                exponent_len: u64,
+                modulus: *const u8,
+                modulus_len: u64,
+            }
+
+            extern "C" {
+                fn sol_big_mod_exp(params: *const u8, result: *mut u8) -> u64;
+            }
+
+            /// The syscall reads contiguous buffers, the lengths are limited by `MAX_LENGTH`
+            fn to_vec(padded: &Padded) -> Vec<u8> {
+                let mut buffer = padded.bytes.to_vec();
+                buffer.resize(padded.bytes.len() + padded.zeros, 0);
+                buffer
+            }
+
+            pub fn modexp(base: &Padded, exponent: &Padded, modulus: &Padded) -> Result<Vec<u8>> {
+                let base = to_vec(base);
+                let exponent = to_vec(exponent);
+                let modulus = to_vec(modulus);
+
+                let mut result = vec![0_u8; modulus.len()];
+
+                let params = BigModExpParams {
+                    base: base.as_ptr(),
+                    base_len: base.len() as u64,
+                    exponent: exponent.as_ptr(),
+                    exponent_len: exponent.len() as u64,
+                    modulus: modulus.as_ptr(),
+                    modulus_len: modulus.len() as u64,
+                };
+
+                let status = unsafe {
+                    sol_big_mod_exp(
+                        std::ptr::addr_of!(params).cast::<u8>(),
+                        result.as_mut_ptr(),
+                    )
+                };
+
+                if status != 0 {
+                    return Err(Error::Custom(format!(
+                        "big_mod_exp: syscall failed with status {status}"
+                    )));
+                }
+
+                Ok(result)
+            }
+        }
+    } else {
+        mod modexp {
+            use num_bigint::BigUint;
+            use num_traits::{One, Zero};
+
+            use super::{Padded, MAX_LENGTH};
+            use crate::error::{Error, Result};
+
+            fn to_biguint(padded: &Padded) -> BigUint {
+                BigUint::from_bytes_be(padded.bytes) << (8 * padded.zeros)
+            }
+
+            /// Same semantics and length limits as the Solana `sol_big_mod_exp` syscall
+            pub fn modexp(base: &Padded, exponent: &Padded, modulus: &Padded) -> Result<Vec<u8>> {
+                let lengths = [base, exponent, modulus].map(|p| p.bytes.len() + p.zeros);
+                if lengths.iter().any(|&length| length > MAX_LENGTH) {
+                    return Err(Error::Custom(format!(
+                        "big_mod_exp: length exceeds {MAX_LENGTH} bytes"
+                    )));
+                }
+
+                let modulus_len = lengths[2];
+
+                let base = to_biguint(base);
+                let exponent = to_biguint(exponent);
+                let modulus = to_biguint(modulus);
+
+                if modulus.is_zero() || modulus.is_one() {
+                    return Ok(vec![0_u8; modulus_len]);
+                }
+
+                let value = base.modpow(&exponent, &modulus).to_bytes_be();
+
+                let mut result = vec![0_u8; modulus_len];
+                result[modulus_len - value.len()..].copy_from_slice(&value);
+
+                Ok(result)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{big_mod_exp, gas};
+
+    fn encode(header: [usize; 3], data: &str) -> Vec<u8> {
+        let mut input = Vec::new();
+        for length in header {
+            let mut word = [0_u8; 32];
+            word[24..].copy_from_slice(&(length as u64).to_be_bytes());
+            input.extend_from_slice(&word);
+        }
+        input.extend(hex::decode(data).unwrap());
+        input
+    }
+
+    // EIP-198 example 1, Fermat's little theorem: 3 ^ (p - 1) mod p == 1
+    #[test]
+    fn eip198_example_1() {
+        let input = encode(
+            [1, 32, 32],
+            "03\
+             fffffffffffffffffffffffffffffffffffffffffffffffffffffffefffffc2e\
+             fffffffffffffffffffffffffffffffffffffffffffffffffffffffefffffc2f",
+        );
+
+        let mut expected = [0_u8; 32];
+        expected[31] = 1;
+
+        assert_eq!(big_mod_exp(&input).unwrap(), expected);
+        assert_eq!(gas(&input), 1360);
+    }
+
+    // EIP-198 example 2, zero base
+    #[test]
+    fn eip198_example_2() {
+        let input = encode(
+            [0, 32, 32],
+            "fffffffffffffffffffffffffffffffffffffffffffffffffffffffefffffc2e\
+             fffffffffffffffffffffffffffffffffffffffffffffffffffffffefffffc2f",
+        );
+
+        assert_eq!(big_mod_exp(&input).unwrap(), [0_u8; 32]);
+    }
+
+    #[test]
+    fn zero_modulus() {
+        let input = encode([1, 1, 2], "0203" /* modulus 0x0000 */);
+        assert_eq!(big_mod_exp(&input).unwrap(), [0_u8; 2]);
+    }
+
+    #[test]
+    fn zero_modulus_length() {
+        let input = encode([1, 1, 0], "0203");
+        assert_eq!(big_mod_exp(&input).unwrap(), Vec::<u8>::new());
+    }
+
+    #[test]
+    fn missing_bytes_are_zeros() {
+        // Modulus `01` is padded to `0100`: 2 ^ 1 mod 256
+        let input = encode([1, 1, 2], "020101");
+        assert_eq!(big_mod_exp(&input).unwrap(), [0x00, 0x02]);
+    }
+
+    #[test]
+    fn oversized_length() {
+        let input = encode([1, 1, 513], "0203");
+        assert!(big_mod_exp(&input).is_err());
+
+        // Huge exponent length with a short input is rejected without the allocation
+        let input = encode([1, 1 << 50, 1], "0203");
+        assert!(big_mod_exp(&input).is_err());
+    }
 }
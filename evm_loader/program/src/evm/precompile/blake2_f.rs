@@ -125,3 +125,16 @@ pub fn blake2_f(input: &[u8]) -> Vec<u8> {
 
     output_buf.to_vec()
 }
+
+/// EIP-152: gas cost is equal to the number of rounds
+#[must_use]
+pub fn rounds(input: &[u8]) -> u64 {
+    if input.len() < 4 {
+        return 0;
+    }
+
+    let mut rounds_arr = [0_u8; 4];
+    rounds_arr.copy_from_slice(&input[..4]);
+
+    u64::from(u32::from_be_bytes(rounds_arr))
+}
@@ -1,26 +1,280 @@
-// Should be implemented via Solana syscall
-// use solana_program::alt_bn128::prelude::*;
+use crate::error::{Error, Result};
+
+const ADD_INPUT_LEN: usize = 128;
+const MUL_INPUT_LEN: usize = 96;
+const PAIRING_ELEMENT_LEN: usize = 192;
+const POINT_LEN: usize = 64;
+
+/// Input is right padded with zeros or truncated to the expected length
+fn padded<const N: usize>(input: &[u8]) -> [u8; N] {
+    let mut buffer = [0_u8; N];
+
+    let len = input.len().min(N);
+    buffer[..len].copy_from_slice(&input[..len]);
+
+    buffer
+}
 
 /// Call inner `bn256Add`
-#[must_use]
-pub fn bn256_add(_input: &[u8]) -> Vec<u8> {
-    // Should be implemented via Solana syscall
-    Vec::new()
-    //alt_bn128_addition(input).unwrap_or_else(|_| vec![])
+pub fn bn256_add(input: &[u8]) -> Result<Vec<u8>> {
+    debug_print!("bn256Add");
+
+    let input = padded::<ADD_INPUT_LEN>(input);
+    let result = alt_bn128::addition(&input).ok_or(Error::PrecompileInvalidInput("bn256Add"))?;
+
+    Ok(result.to_vec())
 }
 
 /// Call inner `bn256ScalarMul`
-#[must_use]
-pub fn bn256_scalar_mul(_input: &[u8]) -> Vec<u8> {
-    // Should be implemented via Solana syscall
-    Vec::new()
-    //alt_bn128_multiplication(input).unwrap_or_else(|_| vec![])
+pub fn bn256_scalar_mul(input: &[u8]) -> Result<Vec<u8>> {
+    debug_print!("bn256ScalarMul");
+
+    let input = padded::<MUL_INPUT_LEN>(input);
+    let result =
+        alt_bn128::multiplication(&input).ok_or(Error::PrecompileInvalidInput("bn256ScalarMul"))?;
+
+    Ok(result.to_vec())
 }
 
 /// Call inner `bn256Pairing`
-#[must_use]
-pub fn bn256_pairing(_input: &[u8]) -> Vec<u8> {
-    // Should be implemented via Solana syscall
-    Vec::new()
-    //alt_bn128_pairing(input).unwrap_or_else(|_| vec![])
+pub fn bn256_pairing(input: &[u8]) -> Result<Vec<u8>> {
+    debug_print!("bn256Pairing");
+
+    if input.len() % PAIRING_ELEMENT_LEN != 0 {
+        return Err(Error::PrecompileInvalidInput("bn256Pairing"));
+    }
+
+    let success = if input.is_empty() {
+        true
+    } else {
+        alt_bn128::pairing(input).ok_or(Error::PrecompileInvalidInput("bn256Pairing"))?
+    };
+
+    let mut result = vec![0_u8; 32];
+    result[31] = u8::from(success);
+
+    Ok(result)
+}
+
+cfg_if::cfg_if! {
+    if #[cfg(target_os = "solana")] {
+        mod alt_bn128 {
+            use super::{ADD_INPUT_LEN, MUL_INPUT_LEN, POINT_LEN};
+            use solana_program::alt_bn128::prelude::{
+                alt_bn128_addition, alt_bn128_multiplication, alt_bn128_pairing,
+            };
+
+            pub fn addition(input: &[u8; ADD_INPUT_LEN]) -> Option<[u8; POINT_LEN]> {
+                alt_bn128_addition(input).ok()?.try_into().ok()
+            }
+
+            pub fn multiplication(input: &[u8; MUL_INPUT_LEN]) -> Option<[u8; POINT_LEN]> {
+                alt_bn128_multiplication(input).ok()?.try_into().ok()
+            }
+
+            pub fn pairing(input: &[u8]) -> Option<bool> {
+                let result = alt_bn128_pairing(input).ok()?;
+                Some(result.last() == Some(&1))
+            }
+        }
+    } else {
+        mod alt_bn128 {
+            use super::{ADD_INPUT_LEN, MUL_INPUT_LEN, PAIRING_ELEMENT_LEN, POINT_LEN};
+            use bn::{AffineG1, AffineG2, Fq, Fq2, Fr, Group, Gt, G1, G2};
+
+            fn read_fq(input: &[u8]) -> Option<Fq> {
+                Fq::from_slice(&input[..32]).ok()
+            }
+
+            /// Point at infinity is encoded as (0, 0)
+            fn read_g1(input: &[u8]) -> Option<G1> {
+                let x = read_fq(&input[0..32])?;
+                let y = read_fq(&input[32..64])?;
+
+                if x == Fq::zero() && y == Fq::zero() {
+                    return Some(G1::zero());
+                }
+
+                AffineG1::new(x, y).ok().map(Into::into)
+            }
+
+            /// Coordinates are encoded as (imaginary, real)
+            fn read_g2(input: &[u8]) -> Option<G2> {
+                let x = Fq2::new(read_fq(&input[32..64])?, read_fq(&input[0..32])?);
+                let y = Fq2::new(read_fq(&input[96..128])?, read_fq(&input[64..96])?);
+
+                if x == Fq2::zero() && y == Fq2::zero() {
+                    return Some(G2::zero());
+                }
+
+                AffineG2::new(x, y).ok().map(Into::into)
+            }
+
+            fn write_g1(point: G1) -> [u8; POINT_LEN] {
+                let mut result = [0_u8; POINT_LEN];
+
+                if let Some(point) = AffineG1::from_jacobian(point) {
+                    let (x, y) = result.split_at_mut(32);
+                    point.x().to_big_endian(x).ok();
+                    point.y().to_big_endian(y).ok();
+                }
+
+                result
+            }
+
+            pub fn addition(input: &[u8; ADD_INPUT_LEN]) -> Option<[u8; POINT_LEN]> {
+                let a = read_g1(&input[0..64])?;
+                let b = read_g1(&input[64..128])?;
+
+                Some(write_g1(a + b))
+            }
+
+            pub fn multiplication(input: &[u8; MUL_INPUT_LEN]) -> Option<[u8; POINT_LEN]> {
+                let point = read_g1(&input[0..64])?;
+                let scalar = Fr::from_slice(&input[64..96]).ok()?;
+
+                Some(write_g1(point * scalar))
+            }
+
+            pub fn pairing(input: &[u8]) -> Option<bool> {
+                let mut pairs = Vec::with_capacity(input.len() / PAIRING_ELEMENT_LEN);
+                for element in input.chunks_exact(PAIRING_ELEMENT_LEN) {
+                    let a = read_g1(&element[0..64])?;
+                    let b = read_g2(&element[64..192])?;
+                    pairs.push((a, b));
+                }
+
+                Some(bn::pairing_batch(&pairs) == Gt::one())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{bn256_add, bn256_pairing, bn256_scalar_mul};
+
+    // Generator of G1
+    const G1: &str = "\
+        0000000000000000000000000000000000000000000000000000000000000001\
+        0000000000000000000000000000000000000000000000000000000000000002";
+
+    // -G1, y = p - 2
+    const G1_NEG: &str = "\
+        0000000000000000000000000000000000000000000000000000000000000001\
+        30644e72e131a029b85045b68181585d97816a916871ca8d3c208c16d87cfd45";
+
+    // 2 * G1
+    const G1_DOUBLE: &str = "\
+        030644e72e131a029b85045b68181585d97816a916871ca8d3c208c16d87cfd3\
+        15ed738c0e0a7c92e7845f96b2ae9c0a68a6a449e3538fc7ff3ebf7a5a18a2c4";
+
+    // Generator of G2, EIP-197 encoding (x_im, x_re, y_im, y_re)
+    const G2: &str = "\
+        198e9393920d483a7260bfb731fb5d25f1aa493335a9e71297e485b7aef312c2\
+        1800deef121f1e76426a00665e5c4479674322d4f75edadd46debd5cd992f6ed\
+        090689d0585ff075ec9e99ad690c3395bc4b313370b38ef355acdadcd122975b\
+        12c85ea5db8c6deb4aab71808dcb408fe3d1e7690c43d37b4ce6cc0166fa7daa";
+
+    fn decode(parts: &[&str]) -> Vec<u8> {
+        hex::decode(parts.concat()).unwrap()
+    }
+
+    fn pairing_result(success: bool) -> Vec<u8> {
+        let mut result = vec![0_u8; 32];
+        result[31] = u8::from(success);
+        result
+    }
+
+    #[test]
+    fn add() {
+        let input = decode(&[G1, G1]);
+        assert_eq!(bn256_add(&input).unwrap(), decode(&[G1_DOUBLE]));
+    }
+
+    #[test]
+    fn add_inverse_is_infinity() {
+        let input = decode(&[G1, G1_NEG]);
+        assert_eq!(bn256_add(&input).unwrap(), vec![0_u8; 64]);
+    }
+
+    #[test]
+    fn add_short_input_is_padded() {
+        // The second point is missing, (0, 0) is the point at infinity
+        let input = decode(&[G1]);
+        assert_eq!(bn256_add(&input).unwrap(), decode(&[G1]));
+        assert_eq!(bn256_add(&[]).unwrap(), vec![0_u8; 64]);
+    }
+
+    #[test]
+    fn add_invalid_point() {
+        // (1, 3) is not on the curve
+        let mut input = decode(&[G1, G1]);
+        input[127] = 3;
+        assert!(bn256_add(&input).is_err());
+    }
+
+    #[test]
+    fn add_coordinate_above_modulus() {
+        // x = p
+        let mut input = decode(&[G1, G1]);
+        input[..32].copy_from_slice(
+            &hex::decode("30644e72e131a029b85045b68181585d97816a916871ca8d3c208c16d87cfd47")
+                .unwrap(),
+        );
+        assert!(bn256_add(&input).is_err());
+    }
+
+    #[test]
+    fn scalar_mul() {
+        let scalar = "0000000000000000000000000000000000000000000000000000000000000002";
+        let input = decode(&[G1, scalar]);
+        assert_eq!(bn256_scalar_mul(&input).unwrap(), decode(&[G1_DOUBLE]));
+    }
+
+    #[test]
+    fn scalar_mul_by_zero() {
+        // The scalar is missing
+        let input = decode(&[G1]);
+        assert_eq!(bn256_scalar_mul(&input).unwrap(), vec![0_u8; 64]);
+    }
+
+    #[test]
+    fn scalar_mul_invalid_point() {
+        let scalar = "0000000000000000000000000000000000000000000000000000000000000002";
+        let mut input = decode(&[G1, scalar]);
+        input[63] = 3;
+        assert!(bn256_scalar_mul(&input).is_err());
+    }
+
+    #[test]
+    fn pairing_empty_input() {
+        assert_eq!(bn256_pairing(&[]).unwrap(), pairing_result(true));
+    }
+
+    #[test]
+    fn pairing_inverse_pair() {
+        // e(G1, G2) * e(-G1, G2) == 1
+        let input = decode(&[G1, G2, G1_NEG, G2]);
+        assert_eq!(bn256_pairing(&input).unwrap(), pairing_result(true));
+    }
+
+    #[test]
+    fn pairing_single_pair() {
+        let input = decode(&[G1, G2]);
+        assert_eq!(bn256_pairing(&input).unwrap(), pairing_result(false));
+    }
+
+    #[test]
+    fn pairing_invalid_point() {
+        let mut input = decode(&[G1, G2]);
+        input[63] = 3;
+        assert!(bn256_pairing(&input).is_err());
+    }
+
+    #[test]
+    fn pairing_wrong_input_length() {
+        let input = decode(&[G1, G2]);
+        assert!(bn256_pairing(&input[..191]).is_err());
+    }
 }
@@ -1,4 +1,7 @@
-use crate::evm::{database::Database, Machine};
+use std::collections::BTreeMap;
+
+use crate::error::{Error, Result};
+use crate::evm::{gas, tracing_event, Context};
 use crate::types::Address;
 
 mod big_mod_exp;
@@ -42,34 +45,215 @@ const SYSTEM_ACCOUNT_BLAKE2F: Address = Address([
     0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x09,
 ]);
 
-/// Is precompile address
-#[must_use]
-pub fn is_precompile_address(address: &Address) -> bool {
-    *address == SYSTEM_ACCOUNT_ECRECOVER
-        || *address == SYSTEM_ACCOUNT_SHA_256
-        || *address == SYSTEM_ACCOUNT_RIPEMD160
-        || *address == SYSTEM_ACCOUNT_DATACOPY
-        || *address == SYSTEM_ACCOUNT_BIGMODEXP
-        || *address == SYSTEM_ACCOUNT_BN256_ADD
-        || *address == SYSTEM_ACCOUNT_BN256_SCALAR_MUL
-        || *address == SYSTEM_ACCOUNT_BN256_PAIRING
-        || *address == SYSTEM_ACCOUNT_BLAKE2F
+/// Restrictions checked before a precompiled contract is executed
+#[derive(Debug, Clone, Copy)]
+pub struct Policy {
+    /// Can be called from a static context, state changing methods still check `is_static`
+    pub static_call: bool,
+    /// Accepts calls with non-zero value
+    pub value_transfer: bool,
+    /// Can be called with DELEGATECALL or CALLCODE
+    pub delegate_call: bool,
 }
 
-impl<B: Database> Machine<B> {
-    #[must_use]
-    pub fn precompile(address: &Address, data: &[u8]) -> Option<Vec<u8>> {
-        match *address {
-            SYSTEM_ACCOUNT_ECRECOVER => Some(ecrecover::ecrecover(data)),
-            SYSTEM_ACCOUNT_SHA_256 => Some(sha256::sha256(data)),
-            SYSTEM_ACCOUNT_RIPEMD160 => Some(ripemd160::ripemd160(data)),
-            SYSTEM_ACCOUNT_DATACOPY => Some(datacopy::datacopy(data)),
-            SYSTEM_ACCOUNT_BIGMODEXP => Some(big_mod_exp::big_mod_exp(data)),
-            SYSTEM_ACCOUNT_BN256_ADD => Some(bn256::bn256_add(data)),
-            SYSTEM_ACCOUNT_BN256_SCALAR_MUL => Some(bn256::bn256_scalar_mul(data)),
-            SYSTEM_ACCOUNT_BN256_PAIRING => Some(bn256::bn256_pairing(data)),
-            SYSTEM_ACCOUNT_BLAKE2F => Some(blake2_f::blake2_f(data)),
-            _ => None,
+impl Policy {
+    /// Ethereum precompiles are pure functions, any kind of call is allowed
+    pub const PURE: Self = Self {
+        static_call: true,
+        value_transfer: true,
+        delegate_call: true,
+    };
+
+    fn check(
+        self,
+        name: &str,
+        context: &Context,
+        address: &Address,
+        is_static: bool,
+    ) -> Result<()> {
+        if is_static && !self.static_call {
+            return Err(Error::StaticModeViolation(*address));
+        }
+
+        if (context.value != 0) && !self.value_transfer {
+            return Err(Error::Custom(format!("{name}: value != 0")));
+        }
+
+        if (&context.contract != address) && !self.delegate_call {
+            return Err(Error::Custom(format!(
+                "{name}: callcode or delegatecall is not allowed"
+            )));
         }
+
+        Ok(())
+    }
+}
+
+/// Precompiled contract executed against the state `S`
+pub trait Precompile<S> {
+    fn name(&self) -> &'static str;
+    fn policy(&self) -> Policy;
+
+    /// Contracts that report non-empty code pass the Solidity `extcodesize` check
+    fn has_code(&self) -> bool {
+        false
+    }
+
+    fn gas(&self, _input: &[u8]) -> u64 {
+        0
+    }
+
+    fn execute(
+        &self,
+        state: &mut S,
+        context: &Context,
+        address: &Address,
+        input: &[u8],
+        is_static: bool,
+    ) -> Result<Vec<u8>>;
+}
+
+/// Precompiled contracts keyed by address
+pub struct Registry<S> {
+    precompiles: BTreeMap<Address, Box<dyn Precompile<S>>>,
+}
+
+impl<S> Registry<S> {
+    /// Registry with the Ethereum precompiled contracts
+    #[must_use]
+    pub fn new() -> Self {
+        let mut registry = Self {
+            precompiles: BTreeMap::new(),
+        };
+
+        registry.register(SYSTEM_ACCOUNT_ECRECOVER, Ecrecover);
+        registry.register(SYSTEM_ACCOUNT_SHA_256, Sha256);
+        registry.register(SYSTEM_ACCOUNT_RIPEMD160, Ripemd160);
+        registry.register(SYSTEM_ACCOUNT_DATACOPY, Datacopy);
+        registry.register(SYSTEM_ACCOUNT_BIGMODEXP, BigModExp);
+        registry.register(SYSTEM_ACCOUNT_BN256_ADD, Bn256Add);
+        registry.register(SYSTEM_ACCOUNT_BN256_SCALAR_MUL, Bn256ScalarMul);
+        registry.register(SYSTEM_ACCOUNT_BN256_PAIRING, Bn256Pairing);
+        registry.register(SYSTEM_ACCOUNT_BLAKE2F, Blake2F);
+
+        registry
+    }
+
+    pub fn register(&mut self, address: Address, precompile: impl Precompile<S> + 'static) {
+        self.precompiles.insert(address, Box::new(precompile));
     }
+
+    #[must_use]
+    pub fn get(&self, address: &Address) -> Option<&dyn Precompile<S>> {
+        self.precompiles.get(address).map(AsRef::as_ref)
+    }
+
+    #[must_use]
+    pub fn contains(&self, address: &Address) -> bool {
+        self.precompiles.contains_key(address)
+    }
+
+    #[must_use]
+    pub fn gas(&self, address: &Address, input: &[u8]) -> u64 {
+        self.get(address)
+            .map_or(0, |precompile| precompile.gas(input))
+    }
+
+    /// Checks the call policy and executes the precompiled contract
+    pub fn execute(
+        &self,
+        state: &mut S,
+        context: &Context,
+        address: &Address,
+        input: &[u8],
+        is_static: bool,
+    ) -> Result<Vec<u8>> {
+        let precompile = self
+            .get(address)
+            .ok_or_else(|| Error::Custom(format!("No precompiled contract at {address}")))?;
+
+        let result = precompile
+            .policy()
+            .check(precompile.name(), context, address, is_static)
+            .and_then(|()| precompile.execute(state, context, address, input, is_static));
+
+        tracing_event!(crate::evm::tracing::Event::PrecompileCall {
+            address: *address,
+            name: precompile.name(),
+            input,
+            output: result.as_deref().unwrap_or_default(),
+            error: result.as_ref().err().map(ToString::to_string)
+        });
+
+        result
+    }
+}
+
+impl<S> Default for Registry<S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+macro_rules! pure_precompile {
+    ($name:ident, |$gas_input:ident| $gas:expr, |$input:ident| $execute:expr) => {
+        struct $name;
+
+        impl<S> Precompile<S> for $name {
+            fn name(&self) -> &'static str {
+                stringify!($name)
+            }
+
+            fn policy(&self) -> Policy {
+                Policy::PURE
+            }
+
+            fn gas(&self, $gas_input: &[u8]) -> u64 {
+                $gas
+            }
+
+            fn execute(
+                &self,
+                _state: &mut S,
+                _context: &Context,
+                _address: &Address,
+                $input: &[u8],
+                _is_static: bool,
+            ) -> Result<Vec<u8>> {
+                $execute
+            }
+        }
+    };
 }
+
+pure_precompile!(Ecrecover, |_input| 3000, |input| Ok(ecrecover::ecrecover(
+    input
+)));
+pure_precompile!(Sha256, |input| 60 + 12 * gas::words(input.len()), |input| {
+    Ok(sha256::sha256(input))
+});
+pure_precompile!(
+    Ripemd160,
+    |input| 600 + 120 * gas::words(input.len()),
+    |input| Ok(ripemd160::ripemd160(input))
+);
+pure_precompile!(
+    Datacopy,
+    |input| 15 + 3 * gas::words(input.len()),
+    |input| Ok(datacopy::datacopy(input))
+);
+pure_precompile!(BigModExp, |input| big_mod_exp::gas(input), |input| {
+    big_mod_exp::big_mod_exp(input)
+});
+pure_precompile!(Bn256Add, |_input| 150, |input| bn256::bn256_add(input));
+pure_precompile!(Bn256ScalarMul, |_input| 6000, |input| {
+    bn256::bn256_scalar_mul(input)
+});
+pure_precompile!(
+    Bn256Pairing,
+    |input| 45000 + 34000 * (input.len() as u64 / 192),
+    |input| bn256::bn256_pairing(input)
+);
+pure_precompile!(Blake2F, |input| blake2_f::rounds(input), |input| Ok(
+    blake2_f::blake2_f(input)
+));
@@ -0,0 +1,78 @@
+use serde::{Deserialize, Serialize};
+
+/// Ethereum hardfork. Selects the opcode set and the gas schedule.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Spec {
+    Istanbul,
+    /// EIP-2929 cold/warm state access, EIP-2930 access lists
+    Berlin,
+    /// EIP-3198 BASEFEE, EIP-3529 reduced refunds
+    London,
+    /// EIP-3855 PUSH0, EIP-3860 initcode limit, EIP-3651 warm COINBASE
+    Shanghai,
+    /// EIP-1153 transient storage, EIP-5656 MCOPY
+    Cancun,
+}
+
+impl Spec {
+    pub const LATEST: Self = Self::Cancun;
+
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Istanbul => "Istanbul",
+            Self::Berlin => "Berlin",
+            Self::London => "London",
+            Self::Shanghai => "Shanghai",
+            Self::Cancun => "Cancun",
+        }
+    }
+
+    #[must_use]
+    pub const fn is_enabled(self, fork: Self) -> bool {
+        (self as u8) >= (fork as u8)
+    }
+
+    /// Hardfork that introduced the opcode.
+    /// Opcodes introduced before Istanbul are available in every spec.
+    const fn introduced(opcode: u8) -> Self {
+        match opcode {
+            0x48 => Self::London,               // BASEFEE
+            0x5F => Self::Shanghai,             // PUSH0
+            0x5C | 0x5D | 0x5E => Self::Cancun, // TLOAD, TSTORE, MCOPY
+            _ => Self::Istanbul,
+        }
+    }
+
+    #[must_use]
+    pub const fn has_opcode(self, opcode: u8) -> bool {
+        self.is_enabled(Self::introduced(opcode))
+    }
+}
+
+impl Default for Spec {
+    fn default() -> Self {
+        Self::LATEST
+    }
+}
+
+impl std::fmt::Display for Spec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl std::str::FromStr for Spec {
+    type Err = crate::error::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "istanbul" => Ok(Self::Istanbul),
+            "berlin" => Ok(Self::Berlin),
+            "london" => Ok(Self::London),
+            "shanghai" => Ok(Self::Shanghai),
+            "cancun" => Ok(Self::Cancun),
+            _ => Err(crate::error::Error::Custom(format!("Unknown EVM spec {s}"))),
+        }
+    }
+}
@@ -39,15 +39,14 @@ impl Stack {
     }
 
     #[allow(dead_code)]
-    pub fn to_vec(&self) -> Vec<[u8; 32]> {
-        let slice = unsafe {
+    pub fn as_slice(&self) -> &[[u8; 32]] {
+        unsafe {
             let start = self.begin.cast::<[u8; 32]>();
             let end = self.top.cast::<[u8; 32]>();
 
             let len = end.offset_from(start).try_into().unwrap();
             std::slice::from_raw_parts(start, len)
-        };
-        slice.to_vec()
+        }
     }
 
     #[inline(always)]
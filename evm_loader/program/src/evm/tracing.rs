@@ -1,37 +1,61 @@
+pub use super::CallType;
 use super::{Context, ExitStatus};
+use crate::types::Address;
 use ethnum::U256;
+use solana_program::{instruction::AccountMeta, pubkey::Pubkey};
 
 environmental::environmental!(listener: dyn EventListener + 'static);
 
+/// Events borrow the machine state, listeners copy only the data they need
 pub trait EventListener {
-    fn event(&mut self, event: Event);
+    fn event(&mut self, event: Event<'_>);
 }
 
-/// Trace event
+/// Trace event.
+/// State change events are emitted only for the changes that succeed. The changes of a frame
+/// are discarded when the frame or one of its parents reverts, consumers must drop
+/// the `Log`, `SelfDestruct` and `ExternalInstruction` events of the reverted frames.
 #[derive(Debug, Clone)]
-pub enum Event {
+pub enum Event<'a> {
     BeginVM {
         context: Context,
-        code: Vec<u8>,
+        call_type: CallType,
+        code: &'a [u8],
+        /// Call data, empty for contract creation
+        input: &'a [u8],
+        /// Gas available to the frame
+        gas: u64,
     },
     EndVM {
         status: ExitStatus,
+        /// Gas consumed by the frame
+        gas_used: u64,
     },
     BeginStep {
         opcode: u8,
         pc: usize,
-        stack: Vec<[u8; 32]>,
-        memory: Vec<u8>,
+        stack: &'a [[u8; 32]],
+        memory: &'a [u8],
+        /// Gas left before the step
+        gas: u64,
+        refund: u64,
+        return_data: &'a [u8],
     },
     EndStep {
         gas_used: u64,
     },
+    /// Exceptional halt of the current step, followed by the frame revert
+    StepError {
+        message: String,
+        /// Geth error of the halt, see `Error::halt_reason`
+        reason: Option<&'static str>,
+    },
     StackPush {
         value: [u8; 32],
     },
     MemorySet {
         offset: usize,
-        data: Vec<u8>,
+        data: &'a [u8],
     },
     StorageSet {
         index: U256,
@@ -41,6 +65,31 @@ pub enum Event {
         index: U256,
         value: [u8; 32],
     },
+    Log {
+        address: Address,
+        topics: &'a [[u8; 32]],
+        data: &'a [u8],
+    },
+    SelfDestruct {
+        address: Address,
+        beneficiary: Address,
+        value: U256,
+    },
+    /// Call of the Ethereum precompile or the Neon extension
+    PrecompileCall {
+        address: Address,
+        name: &'static str,
+        input: &'a [u8],
+        /// Empty if the call failed
+        output: &'a [u8],
+        error: Option<String>,
+    },
+    /// Solana instruction queued for the execution after the transaction
+    ExternalInstruction {
+        program_id: Pubkey,
+        accounts: &'a [AccountMeta],
+        data: &'a [u8],
+    },
 }
 
 pub fn with<F: FnOnce(&mut (dyn EventListener + 'static))>(f: F) {
@@ -42,6 +42,12 @@ pub enum Action {
     EvmSelfDestruct {
         address: Address,
     },
+    EvmLog {
+        address: Address,
+        topics: Vec<[u8; 32]>,
+        #[serde(with = "serde_bytes")]
+        data: Vec<u8>,
+    },
 }
 
 mod serde_bytes_32 {
@@ -1,8 +1,9 @@
 mod action;
 mod cache;
+mod overlay;
 mod precompile_extension;
 mod state;
 
 pub use action::Action;
 pub use cache::OwnedAccountInfo;
-pub use state::ExecutorState;
+pub use state::{ExecutorState, Precompiles};
@@ -0,0 +1,152 @@
+use std::collections::BTreeMap;
+
+use ethnum::U256;
+
+use crate::error::{Error, Result};
+use crate::types::Address;
+
+use super::action::Action;
+
+/// Previous value of the overlay entry, `None` if the entry did not exist
+enum Undo {
+    Nonce(Address, Option<u64>),
+    Balance(Address, Option<BalanceDelta>),
+    Code(Address, Option<usize>),
+    Storage(Address, U256, Option<[u8; 32]>),
+}
+
+#[derive(Clone, Copy, Default)]
+struct BalanceDelta {
+    credit: U256,
+    debit: U256,
+}
+
+/// Indexed view of the state changes recorded in the action list.
+/// Not serialized: it is rebuilt from the actions after deserialization,
+/// the journal is deterministic, so journal lengths saved in snapshots stay valid.
+#[derive(Default)]
+pub struct Overlay {
+    nonce: BTreeMap<Address, u64>,
+    balance: BTreeMap<Address, BalanceDelta>,
+    /// Index of the `EvmSetCode` action
+    code: BTreeMap<Address, usize>,
+    storage: BTreeMap<(Address, U256), [u8; 32]>,
+    journal: Vec<Undo>,
+}
+
+impl Overlay {
+    #[must_use]
+    pub fn from_actions(actions: &[Action]) -> Self {
+        let mut overlay = Self::default();
+        for (position, action) in actions.iter().enumerate() {
+            overlay.apply(position, action);
+        }
+
+        overlay
+    }
+
+    /// Must be called for every action pushed to the action list,
+    /// `position` is the index of the action in the list
+    pub fn apply(&mut self, position: usize, action: &Action) {
+        match action {
+            Action::EvmIncrementNonce { address } => {
+                let previous = self.nonce.get(address).copied();
+                self.journal.push(Undo::Nonce(*address, previous));
+
+                self.nonce
+                    .insert(*address, previous.unwrap_or(0).saturating_add(1));
+            }
+            Action::NeonTransfer {
+                source,
+                target,
+                value,
+            } => {
+                self.update_balance(source, |delta| {
+                    delta.debit = delta.debit.saturating_add(*value)
+                });
+                self.update_balance(target, |delta| {
+                    delta.credit = delta.credit.saturating_add(*value);
+                });
+            }
+            Action::NeonWithdraw { source, value } => {
+                self.update_balance(source, |delta| {
+                    delta.debit = delta.debit.saturating_add(*value)
+                });
+            }
+            Action::EvmSetCode { address, .. } => {
+                let previous = self.code.insert(*address, position);
+                self.journal.push(Undo::Code(*address, previous));
+            }
+            Action::EvmSetStorage {
+                address,
+                index,
+                value,
+            } => {
+                let previous = self.storage.insert((*address, *index), *value);
+                self.journal.push(Undo::Storage(*address, *index, previous));
+            }
+            Action::ExternalInstruction { .. }
+            | Action::EvmSelfDestruct { .. }
+            | Action::EvmLog { .. } => {}
+        }
+    }
+
+    fn update_balance(&mut self, address: &Address, f: impl FnOnce(&mut BalanceDelta)) {
+        let previous = self.balance.get(address).copied();
+        self.journal.push(Undo::Balance(*address, previous));
+
+        f(self.balance.entry(*address).or_default());
+    }
+
+    #[must_use]
+    pub fn journal_len(&self) -> usize {
+        self.journal.len()
+    }
+
+    /// Rolls back every change recorded after the journal had `len` entries
+    pub fn revert(&mut self, len: usize) {
+        while self.journal.len() > len {
+            match self.journal.pop().unwrap() {
+                Undo::Nonce(address, previous) => restore(&mut self.nonce, address, previous),
+                Undo::Balance(address, previous) => restore(&mut self.balance, address, previous),
+                Undo::Code(address, previous) => restore(&mut self.code, address, previous),
+                Undo::Storage(address, index, previous) => {
+                    restore(&mut self.storage, (address, index), previous);
+                }
+            }
+        }
+    }
+
+    pub fn nonce(&self, address: &Address, base: u64) -> Result<u64> {
+        let increments = self.nonce.get(address).copied().unwrap_or(0);
+        base.checked_add(increments).ok_or(Error::IntegerOverflow)
+    }
+
+    pub fn balance(&self, address: &Address, base: U256) -> Result<U256> {
+        match self.balance.get(address) {
+            Some(delta) => base
+                .checked_add(delta.credit)
+                .and_then(|balance| balance.checked_sub(delta.debit))
+                .ok_or(Error::IntegerOverflow),
+            None => Ok(base),
+        }
+    }
+
+    /// Index of the action that set the code
+    #[must_use]
+    pub fn code(&self, address: &Address) -> Option<usize> {
+        self.code.get(address).copied()
+    }
+
+    #[must_use]
+    pub fn storage(&self, address: &Address, index: &U256) -> Option<[u8; 32]> {
+        self.storage.get(&(*address, *index)).copied()
+    }
+}
+
+fn restore<K: Ord, V>(map: &mut BTreeMap<K, V>, key: K, previous: Option<V>) {
+    match previous {
+        Some(value) => map.insert(key, value),
+        None => map.remove(&key),
+    };
+}
@@ -1,4 +1,9 @@
-use crate::{account_storage::AccountStorage, error::Result, evm::Context, types::Address};
+use crate::{
+    account_storage::AccountStorage,
+    error::Result,
+    evm::{Context, Policy, Precompile, Registry},
+    types::Address,
+};
 
 use super::ExecutorState;
 
@@ -7,54 +12,105 @@ mod neon_token;
 mod query_account;
 mod spl_token;
 
-impl<'a, B: AccountStorage> ExecutorState<'a, B> {
-    #[deprecated]
-    const _SYSTEM_ACCOUNT_ERC20_WRAPPER: Address = Address([
-        0xff, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x01,
-    ]);
-    const SYSTEM_ACCOUNT_QUERY: Address = Address([
-        0xff, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x02,
-    ]);
-    const SYSTEM_ACCOUNT_NEON_TOKEN: Address = Address([
-        0xff, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x03,
-    ]);
-    const SYSTEM_ACCOUNT_SPL_TOKEN: Address = Address([
-        0xff, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x04,
-    ]);
-    const SYSTEM_ACCOUNT_METAPLEX: Address = Address([
-        0xff, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x05,
-    ]);
-
-    #[must_use]
-    #[allow(clippy::unused_self)]
-    pub fn is_precompile_extension(&self, address: &Address) -> bool {
-        *address == Self::SYSTEM_ACCOUNT_QUERY
-            || *address == Self::SYSTEM_ACCOUNT_NEON_TOKEN
-            || *address == Self::SYSTEM_ACCOUNT_SPL_TOKEN
-            || *address == Self::SYSTEM_ACCOUNT_METAPLEX
-    }
-
-    pub fn call_precompile_extension(
-        &mut self,
-        context: &Context,
-        address: &Address,
-        input: &[u8],
-        is_static: bool,
-    ) -> Option<Result<Vec<u8>>> {
-        match *address {
-            Self::SYSTEM_ACCOUNT_QUERY => Some(query_account::query_account(
-                self, address, input, context, is_static,
-            )),
-            Self::SYSTEM_ACCOUNT_NEON_TOKEN => Some(neon_token::neon_token(
-                self, address, input, context, is_static,
-            )),
-            Self::SYSTEM_ACCOUNT_SPL_TOKEN => Some(spl_token::spl_token(
-                self, address, input, context, is_static,
-            )),
-            Self::SYSTEM_ACCOUNT_METAPLEX => {
-                Some(metaplex::metaplex(self, address, input, context, is_static))
+#[deprecated]
+const _SYSTEM_ACCOUNT_ERC20_WRAPPER: Address = Address([
+    0xff, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x01,
+]);
+const SYSTEM_ACCOUNT_QUERY: Address = Address([
+    0xff, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x02,
+]);
+const SYSTEM_ACCOUNT_NEON_TOKEN: Address = Address([
+    0xff, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x03,
+]);
+const SYSTEM_ACCOUNT_SPL_TOKEN: Address = Address([
+    0xff, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x04,
+]);
+const SYSTEM_ACCOUNT_METAPLEX: Address = Address([
+    0xff, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x05,
+]);
+
+/// Neon EVM system contracts, executed against the `ExecutorState`
+macro_rules! extension {
+    ($name:ident, $display_name:literal, $policy:expr, $execute:path) => {
+        struct $name;
+
+        impl<'a, B: AccountStorage> Precompile<ExecutorState<'a, B>> for $name {
+            fn name(&self) -> &'static str {
+                $display_name
+            }
+
+            fn policy(&self) -> Policy {
+                $policy
+            }
+
+            fn has_code(&self) -> bool {
+                true // This is required in order to make a normal call to an extension contract
+            }
+
+            fn execute(
+                &self,
+                state: &mut ExecutorState<'a, B>,
+                context: &Context,
+                address: &Address,
+                input: &[u8],
+                is_static: bool,
+            ) -> Result<Vec<u8>> {
+                $execute(state, address, input, context, is_static)
             }
-            _ => None,
         }
-    }
+    };
+}
+
+extension!(
+    QueryAccount,
+    "Query Account",
+    Policy {
+        static_call: true,
+        value_transfer: false,
+        delegate_call: true,
+    },
+    query_account::query_account
+);
+extension!(
+    NeonToken,
+    "Withdraw",
+    Policy {
+        static_call: true,
+        value_transfer: true,
+        delegate_call: false,
+    },
+    neon_token::neon_token
+);
+extension!(
+    SplToken,
+    "SplToken",
+    Policy {
+        static_call: true,
+        value_transfer: false,
+        delegate_call: false,
+    },
+    spl_token::spl_token
+);
+extension!(
+    Metaplex,
+    "Metaplex",
+    Policy {
+        static_call: true,
+        value_transfer: false,
+        delegate_call: false,
+    },
+    metaplex::metaplex
+);
+
+/// Ethereum precompiles and Neon EVM system contracts
+#[must_use]
+pub fn registry<'a, B: AccountStorage>() -> Registry<ExecutorState<'a, B>> {
+    let mut registry = Registry::new();
+
+    registry.register(SYSTEM_ACCOUNT_QUERY, QueryAccount);
+    registry.register(SYSTEM_ACCOUNT_NEON_TOKEN, NeonToken);
+    registry.register(SYSTEM_ACCOUNT_SPL_TOKEN, SplToken);
+    registry.register(SYSTEM_ACCOUNT_METAPLEX, Metaplex);
+
+    registry
 }
@@ -27,12 +27,6 @@ pub fn neon_token<B: AccountStorage>(
 ) -> Result<Vec<u8>> {
     debug_print!("neon_token({})", hex::encode(input));
 
-    if &context.contract != address {
-        return Err(Error::Custom(
-            "Withdraw: callcode or delegatecall is not allowed".to_string(),
-        ));
-    }
-
     let (method_id, rest) = input.split_at(4);
     let method_id: &[u8; 4] = method_id.try_into().unwrap_or(&[0_u8; 4]);
 
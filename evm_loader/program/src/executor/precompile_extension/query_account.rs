@@ -34,15 +34,11 @@ pub fn query_account<B: AccountStorage>(
     state: &mut ExecutorState<B>,
     address: &Address,
     input: &[u8],
-    context: &crate::evm::Context,
+    _context: &crate::evm::Context,
     _is_static: bool,
 ) -> Result<Vec<u8>> {
     debug_print!("query_account({})", hex::encode(input));
 
-    if context.value != 0 {
-        return Err(Error::Custom("Query Account: value != 0".to_string()));
-    }
-
     let (method_id, rest) = input.split_at(4);
     let method_id: [u8; 4] = method_id.try_into()?;
 
@@ -40,16 +40,6 @@ pub fn spl_token<B: AccountStorage>(
     context: &crate::evm::Context,
     is_static: bool,
 ) -> Result<Vec<u8>> {
-    if context.value != 0 {
-        return Err(Error::Custom("SplToken: value != 0".to_string()));
-    }
-
-    if &context.contract != address {
-        return Err(Error::Custom(
-            "SplToken: callcode or delegatecall is not allowed".to_string(),
-        ));
-    }
-
     let (selector, input) = input.split_at(4);
     let selector: [u8; 4] = selector.try_into()?;
 
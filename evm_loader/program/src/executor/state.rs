@@ -1,27 +1,84 @@
 use std::cell::RefCell;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
+use std::rc::Rc;
 
 use ethnum::{AsU256, U256};
-use solana_program::instruction::Instruction;
+use serde::{Deserialize, Serialize};
+use solana_program::instruction::{AccountMeta, Instruction};
 use solana_program::pubkey::Pubkey;
 
 use crate::account_storage::AccountStorage;
 use crate::error::{Error, Result};
 use crate::evm::database::Database;
-use crate::evm::{Context, ExitStatus};
+use crate::evm::{tracing_event, Context, ExitStatus, Registry, Spec};
 use crate::types::Address;
 
 use super::action::Action;
 use super::cache::Cache;
+use super::overlay::Overlay;
+use super::precompile_extension;
 use super::OwnedAccountInfo;
 
+/// EIP-1153 transient storage write, `previous` is restored on revert.
+/// Never applied to the accounts, dropped together with the executor state.
+#[derive(Serialize, Deserialize)]
+struct TransientStorage {
+    address: Address,
+    #[serde(with = "ethnum::serde::bytes::le")]
+    index: U256,
+    value: [u8; 32],
+    previous: Option<[u8; 32]>,
+}
+
+/// EIP-2929 accessed addresses and storage slots
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Accessed {
+    Address(Address),
+    Storage(Address, #[serde(with = "ethnum::serde::bytes::le")] U256),
+}
+
+/// Lengths of `actions`, `transient_storage`, `accessed`
+/// and the overlay journal at the moment of the snapshot
+#[derive(Serialize, Deserialize)]
+struct Snapshot {
+    actions: usize,
+    transient_storage: usize,
+    accessed: usize,
+    overlay: usize,
+}
+
+/// Solana accounts modified by the queued external instructions.
+/// Instructions are emulated once, `applied` is the number of actions already processed.
+#[derive(Default)]
+struct ExternalAccounts {
+    applied: usize,
+    accounts: BTreeMap<Pubkey, OwnedAccountInfo>,
+}
+
+/// Precompiled contracts of the executor, built once by the caller and shared by the states
+pub type Precompiles<'a, B> = Rc<Registry<ExecutorState<'a, B>>>;
+
 /// Represents the state of executor abstracted away from a self.backend.
 /// UPDATE `serialize/deserialize` WHEN THIS STRUCTURE CHANGES
 pub struct ExecutorState<'a, B: AccountStorage> {
     pub backend: &'a B,
+    /// Not serialized, passed on construction
+    precompiles: Precompiles<'a, B>,
     cache: RefCell<Cache>,
     actions: Vec<Action>,
-    stack: Vec<usize>,
+    /// Not serialized, rebuilt from `actions`
+    overlay: Overlay,
+    /// Not serialized, emulated lazily
+    external: RefCell<ExternalAccounts>,
+    /// Journal of the writes, truncated on revert
+    transient_storage: Vec<TransientStorage>,
+    /// Not serialized, rebuilt from `transient_storage`
+    transient_values: BTreeMap<(Address, U256), [u8; 32]>,
+    /// Entries in the order of the first access, truncated on revert
+    accessed: Vec<Accessed>,
+    /// Not serialized, rebuilt from `accessed`
+    accessed_set: BTreeSet<Accessed>,
+    stack: Vec<Snapshot>,
     exit_status: Option<ExitStatus>,
 }
 
@@ -29,25 +86,53 @@ impl<'a, B: AccountStorage> ExecutorState<'a, B> {
     pub fn serialize_into(&self, buffer: &mut [u8]) -> Result<usize> {
         let mut cursor = std::io::Cursor::new(buffer);
 
-        let value = (&self.cache, &self.actions, &self.stack, &self.exit_status);
+        let value = (
+            &self.cache,
+            &self.actions,
+            &self.transient_storage,
+            &self.accessed,
+            &self.stack,
+            &self.exit_status,
+        );
         bincode::serialize_into(&mut cursor, &value)?;
 
         cursor.position().try_into().map_err(Error::from)
     }
 
-    pub fn deserialize_from(buffer: &[u8], backend: &'a B) -> Result<Self> {
-        let (cache, actions, stack, exit_status) = bincode::deserialize(buffer)?;
+    /// Ethereum precompiled contracts and Neon EVM system contracts
+    #[must_use]
+    pub fn precompiles() -> Precompiles<'a, B> {
+        Rc::new(precompile_extension::registry())
+    }
+
+    pub fn deserialize_from(
+        buffer: &[u8],
+        backend: &'a B,
+        precompiles: Precompiles<'a, B>,
+    ) -> Result<Self> {
+        let (cache, actions, transient_storage, accessed, stack, exit_status) =
+            bincode::deserialize(buffer)?;
         Ok(Self {
             backend,
+            precompiles,
             cache,
+            overlay: Overlay::from_actions(&actions),
+            external: RefCell::default(),
             actions,
+            transient_values: transient_storage
+                .iter()
+                .map(|t| ((t.address, t.index), t.value))
+                .collect(),
+            transient_storage,
+            accessed_set: accessed.iter().copied().collect(),
+            accessed,
             stack,
             exit_status,
         })
     }
 
     #[must_use]
-    pub fn new(backend: &'a B) -> Self {
+    pub fn new(backend: &'a B, precompiles: Precompiles<'a, B>) -> Self {
         let cache = Cache {
             solana_accounts: BTreeMap::new(),
             block_number: backend.block_number(),
@@ -56,8 +141,15 @@ impl<'a, B: AccountStorage> ExecutorState<'a, B> {
 
         Self {
             backend,
+            precompiles,
             cache: RefCell::new(cache),
             actions: Vec::with_capacity(64),
+            overlay: Overlay::default(),
+            external: RefCell::default(),
+            transient_storage: Vec::new(),
+            transient_values: BTreeMap::new(),
+            accessed: Vec::new(),
+            accessed_set: BTreeSet::new(),
             stack: Vec::with_capacity(16),
             exit_status: None,
         }
@@ -83,7 +175,7 @@ impl<'a, B: AccountStorage> ExecutorState<'a, B> {
 
     pub fn withdraw_neons(&mut self, source: Address, value: U256) {
         let withdraw = Action::NeonWithdraw { source, value };
-        self.actions.push(withdraw);
+        self.push_action(withdraw);
     }
 
     pub fn queue_external_instruction(
@@ -92,6 +184,12 @@ impl<'a, B: AccountStorage> ExecutorState<'a, B> {
         seeds: Vec<Vec<u8>>,
         allocate: usize,
     ) {
+        tracing_event!(crate::evm::tracing::Event::ExternalInstruction {
+            program_id: instruction.program_id,
+            accounts: &instruction.accounts,
+            data: &instruction.data
+        });
+
         let action = Action::ExternalInstruction {
             program_id: instruction.program_id,
             data: instruction.data,
@@ -100,40 +198,39 @@ impl<'a, B: AccountStorage> ExecutorState<'a, B> {
             allocate,
         };
 
+        self.push_action(action);
+    }
+
+    fn overlay_code(&self, address: &Address) -> Option<&crate::evm::Buffer> {
+        let position = self.overlay.code(address)?;
+        match &self.actions[position] {
+            Action::EvmSetCode { code, .. } => Some(code),
+            _ => unreachable!("overlay points to EvmSetCode"),
+        }
+    }
+
+    /// Returns `true` if the entry was accessed before
+    fn access(&mut self, entry: Accessed) -> bool {
+        if !self.accessed_set.insert(entry) {
+            return true;
+        }
+
+        self.accessed.push(entry);
+        false
+    }
+
+    fn push_action(&mut self, action: Action) {
+        self.overlay.apply(self.actions.len(), &action);
         self.actions.push(action);
     }
 
     pub fn external_account(&self, address: Pubkey) -> Result<OwnedAccountInfo> {
         let mut cache = self.cache.borrow_mut();
+        let mut external = self.external.borrow_mut();
 
-        let metas = self
-            .actions
-            .iter()
-            .filter_map(|a| {
-                if let Action::ExternalInstruction { accounts, .. } = a {
-                    Some(accounts)
-                } else {
-                    None
-                }
-            })
-            .flatten()
-            .collect::<Vec<_>>();
-
-        if !metas.iter().any(|m| (m.pubkey == address) && m.is_writable) {
-            return Ok(cache.get_account_or_insert(address, self.backend).clone());
-        }
-
-        let mut accounts = metas
-            .into_iter()
-            .map(|m| {
-                (
-                    m.pubkey,
-                    cache.get_account_or_insert(m.pubkey, self.backend).clone(),
-                )
-            })
-            .collect::<BTreeMap<Pubkey, OwnedAccountInfo>>();
-
-        for action in &self.actions {
+        // Emulate only the instructions queued since the previous call
+        let start = external.applied;
+        for action in &self.actions[start..] {
             if let Action::ExternalInstruction {
                 program_id,
                 data,
@@ -141,34 +238,56 @@ impl<'a, B: AccountStorage> ExecutorState<'a, B> {
                 ..
             } = action
             {
-                match program_id {
-                    program_id if solana_program::system_program::check_id(program_id) => {
-                        crate::external_programs::system::emulate(data, meta, &mut accounts)?;
-                    }
-                    program_id if spl_token::check_id(program_id) => {
-                        crate::external_programs::spl_token::emulate(data, meta, &mut accounts)?;
-                    }
-                    program_id if spl_associated_token_account::check_id(program_id) => {
-                        crate::external_programs::spl_associated_token::emulate(
-                            data,
-                            meta,
-                            &mut accounts,
-                        )?;
-                    }
-                    program_id if mpl_token_metadata::check_id(program_id) => {
-                        crate::external_programs::metaplex::emulate(data, meta, &mut accounts)?;
-                    }
-                    _ => {
-                        return Err(Error::Custom(format!(
-                            "Unknown external program: {program_id}"
-                        )));
-                    }
+                let ExternalAccounts { accounts, .. } = &mut *external;
+                for m in meta {
+                    accounts.entry(m.pubkey).or_insert_with(|| {
+                        cache.get_account_or_insert(m.pubkey, self.backend).clone()
+                    });
+                }
+
+                if let Err(e) = emulate_external_instruction(program_id, data, meta, accounts) {
+                    *external = ExternalAccounts::default();
+                    return Err(e);
                 }
             }
         }
+        external.applied = self.actions.len();
+
+        if let Some(account) = external.accounts.get(&address) {
+            return Ok(account.clone());
+        }
 
-        Ok(accounts[&address].clone())
+        Ok(cache.get_account_or_insert(address, self.backend).clone())
+    }
+}
+
+fn emulate_external_instruction(
+    program_id: &Pubkey,
+    data: &[u8],
+    meta: &[AccountMeta],
+    accounts: &mut BTreeMap<Pubkey, OwnedAccountInfo>,
+) -> Result<()> {
+    match program_id {
+        program_id if solana_program::system_program::check_id(program_id) => {
+            crate::external_programs::system::emulate(data, meta, accounts)?;
+        }
+        program_id if spl_token::check_id(program_id) => {
+            crate::external_programs::spl_token::emulate(data, meta, accounts)?;
+        }
+        program_id if spl_associated_token_account::check_id(program_id) => {
+            crate::external_programs::spl_associated_token::emulate(data, meta, accounts)?;
+        }
+        program_id if mpl_token_metadata::check_id(program_id) => {
+            crate::external_programs::metaplex::emulate(data, meta, accounts)?;
+        }
+        _ => {
+            return Err(Error::Custom(format!(
+                "Unknown external program: {program_id}"
+            )));
+        }
     }
+
+    Ok(())
 }
 
 impl<'a, B: AccountStorage> Database for ExecutorState<'a, B> {
@@ -177,55 +296,25 @@ impl<'a, B: AccountStorage> Database for ExecutorState<'a, B> {
         U256::from(chain_id)
     }
 
-    fn nonce(&self, from_address: &Address) -> Result<u64> {
-        let mut nonce = self.backend.nonce(from_address);
-
-        for action in &self.actions {
-            if let Action::EvmIncrementNonce { address } = action {
-                if from_address == address {
-                    nonce = nonce.checked_add(1).ok_or(Error::IntegerOverflow)?;
-                }
-            }
-        }
+    fn spec(&self) -> Spec {
+        self.backend.spec()
+    }
 
-        Ok(nonce)
+    fn nonce(&self, from_address: &Address) -> Result<u64> {
+        let nonce = self.backend.nonce(from_address);
+        self.overlay.nonce(from_address, nonce)
     }
 
     fn increment_nonce(&mut self, address: Address) -> Result<()> {
         let increment = Action::EvmIncrementNonce { address };
-        self.actions.push(increment);
+        self.push_action(increment);
 
         Ok(())
     }
 
     fn balance(&self, from_address: &Address) -> Result<U256> {
-        let mut balance = self.backend.balance(from_address);
-
-        for action in &self.actions {
-            match action {
-                Action::NeonTransfer {
-                    source,
-                    target,
-                    value,
-                } => {
-                    if from_address == source {
-                        balance = balance.checked_sub(*value).ok_or(Error::IntegerOverflow)?;
-                    }
-
-                    if from_address == target {
-                        balance = balance.checked_add(*value).ok_or(Error::IntegerOverflow)?;
-                    }
-                }
-                Action::NeonWithdraw { source, value } => {
-                    if from_address == source {
-                        balance = balance.checked_sub(*value).ok_or(Error::IntegerOverflow)?;
-                    }
-                }
-                _ => {}
-            }
-        }
-
-        Ok(balance)
+        let balance = self.backend.balance(from_address);
+        self.overlay.balance(from_address, balance)
     }
 
     fn transfer(&mut self, source: Address, target: Address, value: U256) -> Result<()> {
@@ -246,22 +335,18 @@ impl<'a, B: AccountStorage> Database for ExecutorState<'a, B> {
             target,
             value,
         };
-        self.actions.push(transfer);
+        self.push_action(transfer);
 
         Ok(())
     }
 
     fn code_size(&self, from_address: &Address) -> Result<usize> {
-        if self.is_precompile_extension(from_address) {
-            return Ok(1); // This is required in order to make a normal call to an extension contract
+        if let Some(precompile) = self.precompiles.get(from_address) {
+            return Ok(usize::from(precompile.has_code()));
         }
 
-        for action in &self.actions {
-            if let Action::EvmSetCode { address, code } = action {
-                if from_address == address {
-                    return Ok(code.len());
-                }
-            }
+        if let Some(code) = self.overlay_code(from_address) {
+            return Ok(code.len());
         }
 
         Ok(self.backend.code_size(from_address))
@@ -270,29 +355,25 @@ impl<'a, B: AccountStorage> Database for ExecutorState<'a, B> {
     fn code_hash(&self, from_address: &Address) -> Result<[u8; 32]> {
         use solana_program::keccak::hash;
 
-        for action in &self.actions {
-            if let Action::EvmSetCode { address, code } = action {
-                if from_address == address {
-                    return Ok(hash(code).to_bytes());
-                }
-            }
+        if let Some(code) = self.overlay_code(from_address) {
+            return Ok(hash(code).to_bytes());
         }
 
         Ok(self.backend.code_hash(from_address))
     }
 
     fn code(&self, from_address: &Address) -> Result<crate::evm::Buffer> {
-        for action in &self.actions {
-            if let Action::EvmSetCode { address, code } = action {
-                if from_address == address {
-                    return Ok(code.clone());
-                }
-            }
+        if let Some(code) = self.overlay_code(from_address) {
+            return Ok(code.clone());
         }
 
         Ok(self.backend.code(from_address))
     }
 
+    fn jump_destinations(&self, code: &crate::evm::Buffer) -> crate::evm::JumpDestinations {
+        self.backend.jump_destinations(code)
+    }
+
     fn set_code(&mut self, address: Address, code: crate::evm::Buffer) -> Result<()> {
         if code.starts_with(&[0xEF]) {
             // https://eips.ethereum.org/EIPS/eip-3541
@@ -305,46 +386,76 @@ impl<'a, B: AccountStorage> Database for ExecutorState<'a, B> {
         }
 
         let set_code = Action::EvmSetCode { address, code };
-        self.actions.push(set_code);
+        self.push_action(set_code);
 
         Ok(())
     }
 
     fn selfdestruct(&mut self, address: Address) -> Result<()> {
         let suicide = Action::EvmSelfDestruct { address };
-        self.actions.push(suicide);
+        self.push_action(suicide);
 
         Ok(())
     }
 
     fn storage(&self, from_address: &Address, from_index: &U256) -> Result<[u8; 32]> {
-        for action in self.actions.iter().rev() {
-            if let Action::EvmSetStorage {
-                address,
-                index,
-                value,
-            } = action
-            {
-                if (from_address == address) && (from_index == index) {
-                    return Ok(*value);
-                }
-            }
+        if let Some(value) = self.overlay.storage(from_address, from_index) {
+            return Ok(value);
         }
 
         Ok(self.backend.storage(from_address, from_index))
     }
 
+    fn original_storage(&self, address: &Address, index: &U256) -> Result<[u8; 32]> {
+        Ok(self.backend.storage(address, index))
+    }
+
     fn set_storage(&mut self, address: Address, index: U256, value: [u8; 32]) -> Result<()> {
         let set_storage = Action::EvmSetStorage {
             address,
             index,
             value,
         };
-        self.actions.push(set_storage);
+        self.push_action(set_storage);
+
+        Ok(())
+    }
+
+    fn transient_storage(&self, address: &Address, index: &U256) -> Result<[u8; 32]> {
+        let value = self
+            .transient_values
+            .get(&(*address, *index))
+            .copied()
+            .unwrap_or_default();
+
+        Ok(value)
+    }
+
+    fn set_transient_storage(
+        &mut self,
+        address: Address,
+        index: U256,
+        value: [u8; 32],
+    ) -> Result<()> {
+        let previous = self.transient_values.insert((address, index), value);
+        self.transient_storage.push(TransientStorage {
+            address,
+            index,
+            value,
+            previous,
+        });
 
         Ok(())
     }
 
+    fn access_address(&mut self, address: Address) -> bool {
+        self.access(Accessed::Address(address))
+    }
+
+    fn access_storage(&mut self, address: Address, index: U256) -> bool {
+        self.access(Accessed::Storage(address, index))
+    }
+
     fn block_hash(&self, number: U256) -> Result<[u8; 32]> {
         // geth:
         //  - checks the overflow
@@ -380,17 +491,62 @@ impl<'a, B: AccountStorage> Database for ExecutorState<'a, B> {
         Ok(cache.block_timestamp)
     }
 
+    fn block_coinbase(&self) -> Result<Address> {
+        Ok(self.backend.block_coinbase())
+    }
+
+    fn log(&mut self, address: Address, topics: &[[u8; 32]], data: &[u8]) {
+        let log = Action::EvmLog {
+            address,
+            topics: topics.to_vec(),
+            data: data.to_vec(),
+        };
+        self.push_action(log);
+    }
+
     fn snapshot(&mut self) {
-        self.stack.push(self.actions.len());
+        self.stack.push(Snapshot {
+            actions: self.actions.len(),
+            transient_storage: self.transient_storage.len(),
+            accessed: self.accessed.len(),
+            overlay: self.overlay.journal_len(),
+        });
     }
 
     fn revert_snapshot(&mut self) {
-        let actions_len = self
+        let snapshot = self
             .stack
             .pop()
             .expect("Fatal Error: Inconsistent EVM Call Stack");
 
-        self.actions.truncate(actions_len);
+        let external = self.external.get_mut();
+        if external.applied > snapshot.actions {
+            let reverted = &self.actions[snapshot.actions..external.applied];
+            if reverted
+                .iter()
+                .any(|a| matches!(a, Action::ExternalInstruction { .. }))
+            {
+                *external = ExternalAccounts::default();
+            } else {
+                external.applied = snapshot.actions;
+            }
+        }
+
+        self.actions.truncate(snapshot.actions);
+        self.overlay.revert(snapshot.overlay);
+        for t in self
+            .transient_storage
+            .drain(snapshot.transient_storage..)
+            .rev()
+        {
+            match t.previous {
+                Some(value) => self.transient_values.insert((t.address, t.index), value),
+                None => self.transient_values.remove(&(t.address, t.index)),
+            };
+        }
+        for entry in self.accessed.drain(snapshot.accessed..) {
+            self.accessed_set.remove(&entry);
+        }
 
         if self.stack.is_empty() {
             // sanity check
@@ -405,13 +561,22 @@ impl<'a, B: AccountStorage> Database for ExecutorState<'a, B> {
             .expect("Fatal Error: Inconsistent EVM Call Stack");
     }
 
-    fn precompile_extension(
+    fn is_precompile(&self, address: &Address) -> bool {
+        self.precompiles.contains(address)
+    }
+
+    fn precompile_gas(&self, address: &Address, data: &[u8]) -> u64 {
+        self.precompiles.gas(address, data)
+    }
+
+    fn call_precompile(
         &mut self,
         context: &Context,
         address: &Address,
         data: &[u8],
         is_static: bool,
-    ) -> Option<Result<Vec<u8>>> {
-        self.call_precompile_extension(context, address, data, is_static)
+    ) -> Result<Vec<u8>> {
+        let precompiles = Rc::clone(&self.precompiles);
+        precompiles.execute(self, context, address, data, is_static)
     }
 }
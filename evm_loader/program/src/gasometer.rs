@@ -8,9 +8,31 @@ use solana_program::program_error::ProgramError;
 
 pub const LAMPORTS_PER_SIGNATURE: u64 = 5000;
 
-const WRITE_TO_HOLDER_TRX_COST: u64 = LAMPORTS_PER_SIGNATURE;
-const CANCEL_TRX_COST: u64 = LAMPORTS_PER_SIGNATURE;
-const LAST_ITERATION_COST: u64 = LAMPORTS_PER_SIGNATURE;
+pub const WRITE_TO_HOLDER_TRX_COST: u64 = LAMPORTS_PER_SIGNATURE;
+pub const CANCEL_TRX_COST: u64 = LAMPORTS_PER_SIGNATURE;
+pub const LAST_ITERATION_COST: u64 = LAMPORTS_PER_SIGNATURE;
+
+pub const MIN_ACCOUNTS_TO_USE_ALT: usize = 30;
+pub const ACCOUNTS_PER_ALT_EXTEND: usize = 30;
+
+/// Cost of writing the transaction to the holder by `HOLDER_MSG_SIZE` chunks
+#[must_use]
+pub fn write_to_holder_cost(rlp_len: usize) -> u64 {
+    let size: u64 = rlp_len.try_into().expect("usize is 8 bytes");
+    ((size + (HOLDER_MSG_SIZE - 1)) / HOLDER_MSG_SIZE).saturating_mul(WRITE_TO_HOLDER_TRX_COST)
+}
+
+/// Cost of the address lookup table, zero if the accounts fit without it
+#[must_use]
+pub fn address_lookup_table_cost(accounts_count: usize) -> u64 {
+    if accounts_count < MIN_ACCOUNTS_TO_USE_ALT {
+        return 0;
+    }
+
+    let extend_count = (accounts_count + (ACCOUNTS_PER_ALT_EXTEND - 1)) / ACCOUNTS_PER_ALT_EXTEND;
+    // create_alt + extend_alt + deactivate_alt + close_alt
+    (extend_count + 3) as u64 * LAMPORTS_PER_SIGNATURE
+}
 
 pub struct Gasometer {
     paid_gas: U256,
@@ -57,25 +79,13 @@ impl Gasometer {
     }
 
     pub fn record_write_to_holder(&mut self, trx: &Transaction) {
-        let size: u64 = trx.rlp_len.try_into().expect("usize is 8 bytes");
-        let cost: u64 = ((size + (HOLDER_MSG_SIZE - 1)) / HOLDER_MSG_SIZE)
-            .saturating_mul(WRITE_TO_HOLDER_TRX_COST);
+        let cost = write_to_holder_cost(trx.rlp_len);
 
         self.gas = self.gas.saturating_add(cost);
     }
 
     pub fn record_address_lookup_table(&mut self, accounts: &[AccountInfo]) {
-        const MIN_ACCOUNTS_TO_USE_ALT: usize = 30;
-        const ACCOUNTS_PER_ALT_EXTEND: usize = 30;
-
-        if accounts.len() < MIN_ACCOUNTS_TO_USE_ALT {
-            return;
-        }
-
-        let extend_count =
-            (accounts.len() + (ACCOUNTS_PER_ALT_EXTEND - 1)) / ACCOUNTS_PER_ALT_EXTEND;
-        // create_alt + extend_alt + deactivate_alt + close_alt
-        let cost = (extend_count + 3) as u64 * LAMPORTS_PER_SIGNATURE;
+        let cost = address_lookup_table_cost(accounts.len());
 
         self.gas = self.gas.saturating_add(cost);
     }
@@ -52,7 +52,7 @@ pub fn execute<'a>(
     let gas_price = trx.gas_price;
 
     let (exit_reason, apply_state) = {
-        let mut backend = ExecutorState::new(account_storage);
+        let mut backend = ExecutorState::new(account_storage, ExecutorState::precompiles());
 
         let mut evm = Machine::new(trx, caller_address, &mut backend)?;
         let (result, _) = evm.execute(u64::MAX, &mut backend)?;
@@ -37,7 +37,7 @@ pub fn do_begin<'a>(
     account_storage.check_for_blocked_accounts()?;
     account_storage.block_accounts(true);
 
-    let mut backend = ExecutorState::new(account_storage);
+    let mut backend = ExecutorState::new(account_storage, ExecutorState::precompiles());
     let evm = Machine::new(trx, caller, &mut backend)?;
 
     serialize_evm_state(&mut storage, &backend, &evm)?;
@@ -223,7 +223,11 @@ fn deserialize_evm_state<'a, 'r>(
     let buffer = state.evm_data();
 
     let executor_state_data = &buffer[..state.evm_state_len];
-    let backend = ExecutorState::deserialize_from(executor_state_data, account_storage)?;
+    let backend = ExecutorState::deserialize_from(
+        executor_state_data,
+        account_storage,
+        ExecutorState::precompiles(),
+    )?;
 
     let evm_data = &buffer[state.evm_state_len..][..state.evm_machine_len];
     let evm = Machine::deserialize_from(evm_data, &backend)?;
@@ -2,4 +2,6 @@ mod address;
 mod transaction;
 
 pub use address::Address;
-pub use transaction::Transaction;
+pub use transaction::{
+    effective_gas_price, AccessListItem, Transaction, TransactionType, BASE_FEE_PER_GAS,
+};
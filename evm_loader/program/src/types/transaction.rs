@@ -4,14 +4,60 @@ use std::convert::TryInto;
 
 use super::Address;
 
+/// EIP-2718 transaction envelope type
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransactionType {
+    Legacy,
+    /// EIP-2930
+    AccessList,
+    /// EIP-1559
+    DynamicFee,
+}
+
+impl TransactionType {
+    const ACCESS_LIST: u8 = 0x01;
+    const DYNAMIC_FEE: u8 = 0x02;
+}
+
+impl Default for TransactionType {
+    fn default() -> Self {
+        Self::Legacy
+    }
+}
+
+/// EIP-1559 base fee, Neon has no base fee and `BASEFEE` returns zero
+pub const BASE_FEE_PER_GAS: U256 = U256::ZERO;
+
+/// EIP-1559: the sender pays the base fee and at most `max_priority_fee_per_gas` on top of it
+#[must_use]
+pub fn effective_gas_price(
+    base_fee_per_gas: U256,
+    max_priority_fee_per_gas: U256,
+    max_fee_per_gas: U256,
+) -> U256 {
+    max_fee_per_gas.min(base_fee_per_gas.saturating_add(max_priority_fee_per_gas))
+}
+
+/// EIP-2930 access list entry
+#[derive(Debug, Clone, Default)]
+pub struct AccessListItem {
+    pub address: Address,
+    pub storage_keys: Vec<U256>,
+}
+
 #[derive(Default)]
 pub struct Transaction {
+    pub transaction_type: TransactionType,
     pub nonce: u64,
+    /// For EIP-1559 transactions the effective gas price, see `effective_gas_price`
     pub gas_price: U256,
+    pub max_priority_fee_per_gas: U256,
+    pub max_fee_per_gas: U256,
     pub gas_limit: U256,
     pub target: Option<Address>,
     pub value: U256,
     pub call_data: crate::evm::Buffer,
+    pub access_list: Vec<AccessListItem>,
     pub v: U256,
     pub r: U256,
     pub s: U256,
@@ -24,7 +70,108 @@ pub struct Transaction {
 
 impl Transaction {
     pub fn from_rlp(transaction: &[u8]) -> Result<Self, Error> {
-        rlp::decode(transaction).map_err(Error::from)
+        match transaction.first() {
+            Some(&TransactionType::ACCESS_LIST) => {
+                Self::decode_typed(TransactionType::AccessList, transaction)
+            }
+            Some(&TransactionType::DYNAMIC_FEE) => {
+                Self::decode_typed(TransactionType::DynamicFee, transaction)
+            }
+            _ => rlp::decode(transaction),
+        }
+        .map_err(Error::from)
+    }
+
+    /// EIP-2718: `TransactionType || TransactionPayload`
+    fn decode_typed(
+        transaction_type: TransactionType,
+        transaction: &[u8],
+    ) -> Result<Self, rlp::DecoderError> {
+        let rlp = rlp::Rlp::new(&transaction[1..]);
+        if !rlp.is_list() {
+            return Err(rlp::DecoderError::RlpExpectedToBeList);
+        }
+
+        let rlp_len = {
+            let info = rlp.payload_info()?;
+            info.header_len + info.value_len
+        };
+
+        if rlp.as_raw().len() != rlp_len {
+            return Err(rlp::DecoderError::RlpInconsistentLengthAndData);
+        }
+
+        let chain_id: U256 = u256(&rlp.at(0)?)?;
+        let nonce: u64 = rlp.val_at(1)?;
+
+        let (max_priority_fee_per_gas, max_fee_per_gas, gas_price, offset) = match transaction_type
+        {
+            TransactionType::AccessList => {
+                let gas_price: U256 = u256(&rlp.at(2)?)?;
+                (gas_price, gas_price, gas_price, 3)
+            }
+            TransactionType::DynamicFee => {
+                let max_priority_fee_per_gas: U256 = u256(&rlp.at(2)?)?;
+                let max_fee_per_gas: U256 = u256(&rlp.at(3)?)?;
+                if max_priority_fee_per_gas > max_fee_per_gas {
+                    return Err(rlp::DecoderError::Custom(
+                        "max_priority_fee_per_gas > max_fee_per_gas",
+                    ));
+                }
+                let gas_price = effective_gas_price(
+                    BASE_FEE_PER_GAS,
+                    max_priority_fee_per_gas,
+                    max_fee_per_gas,
+                );
+                (max_priority_fee_per_gas, max_fee_per_gas, gas_price, 4)
+            }
+            TransactionType::Legacy => unreachable!(),
+        };
+
+        let gas_limit: U256 = u256(&rlp.at(offset)?)?;
+        let target = target(&rlp.at(offset + 1)?)?;
+        let value: U256 = u256(&rlp.at(offset + 2)?)?;
+        let call_data = crate::evm::Buffer::new(rlp.at(offset + 3)?.data()?);
+        let access_list = access_list(&rlp.at(offset + 4)?)?;
+        let v: U256 = u256(&rlp.at(offset + 5)?)?;
+        let r: U256 = u256(&rlp.at(offset + 6)?)?;
+        let s: U256 = u256(&rlp.at(offset + 7)?)?;
+
+        if rlp.at(offset + 8).is_ok() {
+            return Err(rlp::DecoderError::RlpIncorrectListLen);
+        }
+
+        let recovery_id = match v {
+            v if v == U256::ZERO => 0_u8,
+            v if v == U256::ONE => 1_u8,
+            _ => return Err(rlp::DecoderError::RlpExpectedToBeData),
+        };
+
+        let hash = solana_program::keccak::hash(transaction).to_bytes();
+        let signed_hash = typed_signed_hash(transaction[0], &rlp, offset + 5)?;
+
+        let tx = Self {
+            transaction_type,
+            nonce,
+            gas_price,
+            max_priority_fee_per_gas,
+            max_fee_per_gas,
+            gas_limit,
+            target,
+            value,
+            call_data,
+            access_list,
+            v,
+            r,
+            s,
+            chain_id: Some(chain_id),
+            recovery_id,
+            rlp_len: 1 + rlp_len,
+            hash,
+            signed_hash,
+        };
+
+        Ok(tx)
     }
 
     pub fn recover_caller_address(&self) -> Result<Address, Error> {
@@ -59,18 +206,7 @@ impl rlp::Decodable for Transaction {
         let nonce: u64 = rlp.val_at(0)?;
         let gas_price: U256 = u256(&rlp.at(1)?)?;
         let gas_limit: U256 = u256(&rlp.at(2)?)?;
-        let target: Option<Address> = {
-            let target = rlp.at(3)?;
-            if target.is_empty() {
-                if target.is_data() {
-                    None
-                } else {
-                    return Err(rlp::DecoderError::RlpExpectedToBeData);
-                }
-            } else {
-                Some(target.as_val()?)
-            }
-        };
+        let target = target(&rlp.at(3)?)?;
         let value: U256 = u256(&rlp.at(4)?)?;
         let call_data = crate::evm::Buffer::new(rlp.at(5)?.data()?);
         let v: U256 = u256(&rlp.at(6)?)?;
@@ -97,12 +233,16 @@ impl rlp::Decodable for Transaction {
         let signed_hash = signed_hash(rlp, chain_id)?;
 
         let tx = Self {
+            transaction_type: TransactionType::Legacy,
             nonce,
             gas_price,
+            max_priority_fee_per_gas: gas_price,
+            max_fee_per_gas: gas_price,
             gas_limit,
             target,
             value,
             call_data,
+            access_list: Vec::new(),
             v,
             r,
             s,
@@ -157,32 +297,99 @@ fn signed_hash(
         trailer
     });
 
-    let header: Vec<u8> = {
-        let len = middle.len() + trailer.len();
-        if len <= 55 {
-            let len: u8 = len.try_into().unwrap();
-            vec![0xC0 + len]
-        } else {
-            let len_bytes = {
-                let leading_empty_bytes = (len.leading_zeros() as usize) / 8;
-                let bytes = len.to_be_bytes();
-                bytes[leading_empty_bytes..].to_vec()
-            };
-            let len_bytes_len: u8 = len_bytes.len().try_into().unwrap();
-
-            let mut header = Vec::with_capacity(10);
-            header.extend_from_slice(&[0xF7 + len_bytes_len]);
-            header.extend_from_slice(&len_bytes);
-
-            header
-        }
-    };
+    let header = list_header(middle.len() + trailer.len());
 
     let hash = solana_program::keccak::hashv(&[&header, middle, &trailer]).to_bytes();
 
     Ok(hash)
 }
 
+/// EIP-2718: `keccak256(TransactionType || rlp([fields without signature]))`
+fn typed_signed_hash(
+    transaction_type: u8,
+    transaction: &rlp::Rlp,
+    signature_index: usize,
+) -> Result<[u8; 32], rlp::DecoderError> {
+    let raw = transaction.as_raw();
+    let payload_info = transaction.payload_info()?;
+    let (_, v_offset) = transaction.at_with_offset(signature_index)?;
+
+    let middle = &raw[payload_info.header_len..v_offset];
+    let header = list_header(middle.len());
+
+    let hash = solana_program::keccak::hashv(&[&[transaction_type], &header, middle]).to_bytes();
+
+    Ok(hash)
+}
+
+fn list_header(len: usize) -> Vec<u8> {
+    if len <= 55 {
+        let len: u8 = len.try_into().unwrap();
+        vec![0xC0 + len]
+    } else {
+        let len_bytes = {
+            let leading_empty_bytes = (len.leading_zeros() as usize) / 8;
+            let bytes = len.to_be_bytes();
+            bytes[leading_empty_bytes..].to_vec()
+        };
+        let len_bytes_len: u8 = len_bytes.len().try_into().unwrap();
+
+        let mut header = Vec::with_capacity(10);
+        header.extend_from_slice(&[0xF7 + len_bytes_len]);
+        header.extend_from_slice(&len_bytes);
+
+        header
+    }
+}
+
+fn target(rlp: &rlp::Rlp) -> Result<Option<Address>, rlp::DecoderError> {
+    if rlp.is_empty() {
+        if rlp.is_data() {
+            Ok(None)
+        } else {
+            Err(rlp::DecoderError::RlpExpectedToBeData)
+        }
+    } else {
+        Ok(Some(rlp.as_val()?))
+    }
+}
+
+fn access_list(rlp: &rlp::Rlp) -> Result<Vec<AccessListItem>, rlp::DecoderError> {
+    if !rlp.is_list() {
+        return Err(rlp::DecoderError::RlpExpectedToBeList);
+    }
+
+    let mut access_list = Vec::with_capacity(rlp.item_count()?);
+    for item in rlp.iter() {
+        if item.item_count()? != 2 {
+            return Err(rlp::DecoderError::RlpIncorrectListLen);
+        }
+
+        let address: Address = item.val_at(0)?;
+
+        let keys = item.at(1)?;
+        if !keys.is_list() {
+            return Err(rlp::DecoderError::RlpExpectedToBeList);
+        }
+
+        let mut storage_keys = Vec::with_capacity(keys.item_count()?);
+        for key in keys.iter() {
+            let key = key.data()?;
+            let key: [u8; 32] = key
+                .try_into()
+                .map_err(|_| rlp::DecoderError::RlpInvalidLength)?;
+            storage_keys.push(U256::from_be_bytes(key));
+        }
+
+        access_list.push(AccessListItem {
+            address,
+            storage_keys,
+        });
+    }
+
+    Ok(access_list)
+}
+
 #[inline]
 fn u256(rlp: &rlp::Rlp) -> Result<U256, rlp::DecoderError> {
     rlp.decoder().decode_value(|bytes| {
@@ -197,3 +404,170 @@ fn u256(rlp: &rlp::Rlp) -> Result<U256, rlp::DecoderError> {
         }
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{effective_gas_price, Address, Transaction, TransactionType, U256};
+
+    /// Every test transaction is signed with the EIP-155 example key `0x4646...46`
+    const SENDER: &str = "0x9d8a62f656a8d1615c1294fd71e9cfb3e4855a4f";
+
+    fn decode(raw: &str) -> Transaction {
+        Transaction::from_rlp(&hex::decode(raw).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn legacy_eip155() {
+        // Example from EIP-155
+        let tx = decode(
+            "f86c098504a817c800825208943535353535353535353535353535353535353535880de0b6b3a7640000\
+             8025a028ef61340bd939bc2195fe537567866003e1a15d3c71ff63e1590620aa636276a067cbe9d8997f\
+             761aecb703304b3800ccf555c9f3dc64214b297fb1966a3b6d83",
+        );
+
+        assert_eq!(tx.transaction_type, TransactionType::Legacy);
+        assert_eq!(tx.nonce, 9);
+        assert_eq!(tx.gas_price, U256::new(20_000_000_000));
+        assert_eq!(tx.max_fee_per_gas, tx.gas_price);
+        assert_eq!(tx.gas_limit, U256::new(21_000));
+        assert_eq!(tx.target, Some(Address::from([0x35; 20])));
+        assert_eq!(tx.value, U256::new(1_000_000_000_000_000_000));
+        assert!(tx.call_data.is_empty());
+        assert_eq!(tx.v, U256::new(37));
+        assert_eq!(tx.chain_id, Some(U256::ONE));
+        assert_eq!(tx.recovery_id, 0);
+        assert_eq!(tx.rlp_len, 110);
+        assert_eq!(
+            hex::encode(tx.signed_hash),
+            "daf5a779ae972f972197303d7b574746c7ef83eadac0f2791ad23db92e4c8e53"
+        );
+        assert_eq!(
+            hex::encode(tx.hash),
+            "33469b22e9f636356c4160a87eb19df52b7412e8eac32a4a55ffe88ea8350788"
+        );
+        assert_eq!(tx.recover_caller_address().unwrap().to_string(), SENDER);
+    }
+
+    #[test]
+    fn access_list_eip2930() {
+        let tx = decode(
+            "01f8cd01808504a817c800827530943535353535353535353535353535353535353535880de0b6b3a764\
+             000083c0ffeef85bf85994de0b295669a9fd93d5f28d9ec85e40f4cb697baef842a00000000000000000\
+             000000000000000000000000000000000000000000000000a00000000000000000000000000000000000\
+             00000000000000000000000000000101a0d47644539acec3da5e3ecf5fe8863c628a9c97e8b71e9ea916\
+             7a6f4f83c03c32a07e3aaa895b7e8f7bbedacf854b40dc2a1ae22abdedfb785a7e84772e78854344",
+        );
+
+        assert_eq!(tx.transaction_type, TransactionType::AccessList);
+        assert_eq!(tx.chain_id, Some(U256::ONE));
+        assert_eq!(tx.nonce, 0);
+        assert_eq!(tx.gas_price, U256::new(20_000_000_000));
+        assert_eq!(tx.max_priority_fee_per_gas, tx.gas_price);
+        assert_eq!(tx.max_fee_per_gas, tx.gas_price);
+        assert_eq!(tx.gas_limit, U256::new(30_000));
+        assert_eq!(tx.target, Some(Address::from([0x35; 20])));
+        assert_eq!(tx.value, U256::new(1_000_000_000_000_000_000));
+        assert_eq!(&*tx.call_data, &[0xC0, 0xFF, 0xEE]);
+
+        assert_eq!(tx.access_list.len(), 1);
+        assert_eq!(
+            tx.access_list[0].address.to_string(),
+            "0xde0b295669a9fd93d5f28d9ec85e40f4cb697bae"
+        );
+        assert_eq!(tx.access_list[0].storage_keys, vec![U256::ZERO, U256::ONE]);
+
+        assert_eq!(tx.recovery_id, 1);
+        assert_eq!(tx.rlp_len, 208);
+        assert_eq!(
+            hex::encode(tx.signed_hash),
+            "ddb47a6ecfd2caf2bb3e12936fc7752c72006509d633ae726d682c98f1a17402"
+        );
+        assert_eq!(
+            hex::encode(tx.hash),
+            "4b6a91ee8083d234e5a0f38b2f5679d8ac224609c3e9d4c9250a796bd67e8b84"
+        );
+        assert_eq!(tx.recover_caller_address().unwrap().to_string(), SENDER);
+    }
+
+    #[test]
+    fn dynamic_fee_eip1559_deploy() {
+        let tx = decode(
+            "02f85a01078477359400850ba43b7400830186a08080826000c001a0f30e4bd8094e53a679ddb8f55b52\
+             16b03c44623fc4279ef0791f9aa1f6930d49a035bd136867bc70a8d3a72ee24a396718439d3ff741125f\
+             a106746f1531732e23",
+        );
+
+        assert_eq!(tx.transaction_type, TransactionType::DynamicFee);
+        assert_eq!(tx.chain_id, Some(U256::ONE));
+        assert_eq!(tx.nonce, 7);
+        assert_eq!(tx.max_priority_fee_per_gas, U256::new(2_000_000_000));
+        assert_eq!(tx.max_fee_per_gas, U256::new(50_000_000_000));
+        // Without the base fee the sender pays the priority fee
+        assert_eq!(tx.gas_price, U256::new(2_000_000_000));
+        assert_eq!(tx.gas_limit, U256::new(100_000));
+        assert_eq!(tx.target, None);
+        assert_eq!(tx.value, U256::ZERO);
+        assert_eq!(&*tx.call_data, &[0x60, 0x00]);
+        assert!(tx.access_list.is_empty());
+
+        assert_eq!(tx.recovery_id, 1);
+        assert_eq!(tx.rlp_len, 93);
+        assert_eq!(
+            hex::encode(tx.signed_hash),
+            "60d287da24bbffb71af405a11c7aa3cccfd6807960ed0acbe782da324ab85c03"
+        );
+        assert_eq!(
+            hex::encode(tx.hash),
+            "7942763eb1631b4f276351a30b85e06cea65307f5ef9766d6fedc07c9fd9b854"
+        );
+        assert_eq!(tx.recover_caller_address().unwrap().to_string(), SENDER);
+    }
+
+    #[test]
+    fn effective_gas_price_eip1559() {
+        let gwei = |value: u128| U256::new(value * 1_000_000_000);
+
+        assert_eq!(effective_gas_price(gwei(0), gwei(2), gwei(50)), gwei(2));
+        assert_eq!(effective_gas_price(gwei(30), gwei(2), gwei(50)), gwei(32));
+        // Capped by the max fee
+        assert_eq!(effective_gas_price(gwei(49), gwei(2), gwei(50)), gwei(50));
+        assert_eq!(effective_gas_price(U256::MAX, gwei(2), gwei(50)), gwei(50));
+    }
+
+    #[test]
+    fn priority_fee_above_max_fee() {
+        // max_priority_fee_per_gas = 3, max_fee_per_gas = 2
+        let raw =
+            hex::decode("02e2018003028252089435353535353535353535353535353535353535358080c0800101")
+                .unwrap();
+
+        assert!(Transaction::from_rlp(&raw).is_err());
+    }
+
+    #[test]
+    fn typed_invalid_y_parity() {
+        // EIP-1559 deploy transaction with y_parity = 2
+        let mut raw = hex::decode(
+            "02f85a01078477359400850ba43b7400830186a08080826000c001a0f30e4bd8094e53a679ddb8f55b52\
+             16b03c44623fc4279ef0791f9aa1f6930d49a035bd136867bc70a8d3a72ee24a396718439d3ff741125f\
+             a106746f1531732e23",
+        )
+        .unwrap();
+        assert_eq!(raw[26], 0x01);
+        raw[26] = 0x02;
+
+        assert!(Transaction::from_rlp(&raw).is_err());
+    }
+
+    #[test]
+    fn trailing_bytes() {
+        let mut raw =
+            hex::decode("02e2018003028252089435353535353535353535353535353535353535358080c0800101")
+                .unwrap();
+        // Fix the fees and append a byte after the payload
+        raw[4] = 0x02;
+        raw.push(0x00);
+
+        assert!(Transaction::from_rlp(&raw).is_err());
+    }
+}